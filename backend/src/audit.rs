@@ -0,0 +1,232 @@
+use anyhow::{bail, Context, Result};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+
+/// `prev` of the first record.
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Key file for the chain, next to the log.
+const KEY_FILE: &str = "audit.key";
+
+/// Results that count as someone trying a wrong password.
+const FAILURE_RESULTS: [&str; 3] = ["auth_failed", "account_locked", "rate_limited"];
+
+/// One authentication outcome. `hash` is HMAC-SHA-256, keyed with
+/// `audit.key`, over `prev`, a newline and the record serialized without
+/// `hash`, so editing, dropping or reordering records breaks the chain from
+/// that point on unless the editor also has the key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    pub seq: u64,
+    pub ts: u64,
+    pub user: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// `authenticated` when PAM accepted the credentials, then `success`
+    /// once the session started, or an error code (`auth_failed`,
+    /// `cancelled`, `timeout`, ...).
+    pub result: String,
+    pub duration_ms: u64,
+    pub prev: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    #[serde(flatten)]
+    record: Record,
+    hash: String,
+}
+
+/// What the UI shows after a successful auth.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LastLogin {
+    /// Unix time of the previous successful login, if any.
+    pub previous: Option<u64>,
+    /// Failed attempts for the user since then.
+    pub failures: u32,
+}
+
+/// Outcome being appended; `seq`, `ts` and the chain are filled in.
+pub struct Outcome<'a> {
+    pub user: &'a str,
+    pub session_id: Option<&'a str>,
+    pub result: &'a str,
+    pub duration_ms: u64,
+}
+
+/// Append-only audit log, one JSON record per line, mode 0600.
+pub struct AuditLog {
+    path: PathBuf,
+    key: Vec<u8>,
+}
+
+impl AuditLog {
+    /// The log at `path`, keyed with `audit.key` next to it; the key is
+    /// created (mode 0600) on first use.
+    pub fn open(path: PathBuf) -> Result<Self> {
+        let key_path = path.with_file_name(KEY_FILE);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("create audit dir {}", parent.display()))?;
+        }
+        let key = tiss_greetd_logging::load_key(&key_path)
+            .with_context(|| format!("load audit key {}", key_path.display()))?;
+        Ok(AuditLog { path, key })
+    }
+
+    /// The log at `path` for checking only: the key must already exist.
+    pub fn open_existing(path: PathBuf) -> Result<Self> {
+        let key_path = path.with_file_name(KEY_FILE);
+        let key = fs::read(&key_path).with_context(|| format!("read audit key {}", key_path.display()))?;
+        if key.len() < 16 {
+            bail!("audit key {} is too short", key_path.display());
+        }
+        Ok(AuditLog { path, key })
+    }
+
+    pub fn append(&self, outcome: Outcome<'_>, now: u64) -> Result<Record> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .mode(0o600)
+            .custom_flags(libc::O_NOFOLLOW)
+            .open(&self.path)
+            .with_context(|| format!("open audit log {}", self.path.display()))?;
+        let (seq, prev) = match last_entry(&mut file)? {
+            Some(last) => (last.record.seq + 1, last.hash),
+            None => (0, GENESIS.to_string()),
+        };
+        let record = Record {
+            seq,
+            ts: now,
+            user: outcome.user.to_string(),
+            session_id: outcome.session_id.map(str::to_string),
+            result: outcome.result.to_string(),
+            duration_ms: outcome.duration_ms,
+            prev,
+        };
+        let entry = Entry {
+            hash: chain_hash(&self.key, &record)?,
+            record,
+        };
+        let mut line = serde_json::to_vec(&entry).context("serialize audit record")?;
+        line.push(b'\n');
+        file.write_all(&line)
+            .and_then(|()| file.sync_data())
+            .with_context(|| format!("write audit log {}", self.path.display()))?;
+        Ok(entry.record)
+    }
+
+    /// Previous success of `user` and the failures recorded after it. An
+    /// `authenticated` record ends the run of failures too, even if the
+    /// session then did not start.
+    pub fn last_login(&self, user: &str) -> Result<LastLogin> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(LastLogin::default());
+            }
+            Err(err) => {
+                return Err(err).with_context(|| format!("read audit log {}", self.path.display()));
+            }
+        };
+        let mut last = LastLogin::default();
+        for line in content.lines().filter(|line| !line.trim().is_empty()) {
+            let entry: Entry = serde_json::from_str(line).context("parse audit record")?;
+            if entry.record.user != user {
+                continue;
+            }
+            if entry.record.result == "success" {
+                last = LastLogin {
+                    previous: Some(entry.record.ts),
+                    failures: 0,
+                };
+            } else if entry.record.result == "authenticated" {
+                last.failures = 0;
+            } else if FAILURE_RESULTS.contains(&entry.record.result.as_str()) {
+                last.failures = last.failures.saturating_add(1);
+            }
+        }
+        Ok(last)
+    }
+
+    /// Checks the whole chain against the key. Returns the number of
+    /// records, or an error naming the first line that does not verify.
+    /// Whoever can read `audit.key` can also forge a chain that passes.
+    pub fn verify(&self) -> Result<u64> {
+        let content = fs::read_to_string(&self.path)
+            .with_context(|| format!("read audit log {}", self.path.display()))?;
+        let mut prev = GENESIS.to_string();
+        let mut count = 0;
+        for (index, line) in content.lines().enumerate() {
+            let lineno = index + 1;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: Entry = serde_json::from_str(line)
+                .with_context(|| format!("line {}: not an audit record", lineno))?;
+            if entry.record.seq != count {
+                bail!("line {}: expected seq {}, found {}", lineno, count, entry.record.seq);
+            }
+            if entry.record.prev != prev {
+                bail!("line {}: chain broken (prev does not match line before)", lineno);
+            }
+            if chain_hash(&self.key, &entry.record)? != entry.hash {
+                bail!("line {}: hash mismatch (record modified)", lineno);
+            }
+            prev = entry.hash;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+fn chain_hash(key: &[u8], record: &Record) -> Result<String> {
+    let body = serde_json::to_vec(record).context("serialize audit record")?;
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac accepts any key length");
+    mac.update(record.prev.as_bytes());
+    mac.update(b"\n");
+    mac.update(&body);
+    Ok(mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// Reads the last record without loading the whole file.
+fn last_entry(file: &mut File) -> Result<Option<Entry>> {
+    let len = file.metadata().context("stat audit log")?.len();
+    let mut window = 4096u64;
+    loop {
+        let start = len.saturating_sub(window);
+        file.seek(SeekFrom::Start(start)).context("seek audit log")?;
+        let mut tail = Vec::new();
+        file.read_to_end(&mut tail).context("read audit log")?;
+        let text = String::from_utf8_lossy(&tail);
+        let trimmed = text.trim_end();
+        match trimmed.rfind('\n') {
+            Some(pos) => {
+                return parse_last(&trimmed[pos + 1..]).map(Some);
+            }
+            None if start == 0 => {
+                if trimmed.is_empty() {
+                    return Ok(None);
+                }
+                return parse_last(trimmed).map(Some);
+            }
+            None => window *= 2,
+        }
+    }
+}
+
+fn parse_last(line: &str) -> Result<Entry> {
+    serde_json::from_str(line).context("parse last audit record")
+}
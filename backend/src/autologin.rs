@@ -0,0 +1,145 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use tiss_greetd_logging::Logger;
+
+const DEFAULT_DELAY_SECS: u64 = 5;
+const DEFAULT_MARKER: &str = "/run/tiss-greetd/autologin";
+const BOOT_ID: &str = "/proc/sys/kernel/random/boot_id";
+
+/// `[login] autologin_user` / `autologin_delay_secs`, as exported by the
+/// launcher.
+pub struct Rule {
+    pub user: String,
+    pub delay: Duration,
+    /// Holds the boot id of the boot the rule last fired in.
+    pub marker: PathBuf,
+}
+
+impl Rule {
+    pub fn load(log: &mut Logger) -> Option<Rule> {
+        let user = env::var("TISS_GREETD_AUTOLOGIN_USER").unwrap_or_default();
+        let user = user.trim();
+        if user.is_empty() {
+            return None;
+        }
+        let delay = match env::var("TISS_GREETD_AUTOLOGIN_DELAY_SECS") {
+            Ok(raw) if !raw.trim().is_empty() => match raw.trim().parse() {
+                Ok(secs) => secs,
+                Err(_) => {
+                    log.warn(&format!(
+                        "invalid TISS_GREETD_AUTOLOGIN_DELAY_SECS {:?}; using {}",
+                        raw, DEFAULT_DELAY_SECS
+                    ));
+                    DEFAULT_DELAY_SECS
+                }
+            },
+            _ => DEFAULT_DELAY_SECS,
+        };
+        let marker = env::var("TISS_GREETD_AUTOLOGIN_MARKER")
+            .ok()
+            .filter(|path| !path.trim().is_empty())
+            .unwrap_or_else(|| DEFAULT_MARKER.to_string());
+        Some(Rule {
+            user: user.to_string(),
+            delay: Duration::from_secs(delay),
+            marker: PathBuf::from(marker),
+        })
+    }
+
+    /// True if the rule already ran this boot.
+    pub fn used(&self, log: &mut Logger) -> bool {
+        if marked(&self.marker, &boot_id()) {
+            log.log("autologin: already used this boot");
+            return true;
+        }
+        false
+    }
+
+    /// Starts counting down from now. Called once a UI is there to show it,
+    /// so the whole delay is left to cancel in; that UI gets the first tick
+    /// with its `hello` answer. The marker is written first, so a cancelled
+    /// countdown or a greeter restart does not count down again; without
+    /// it there is no countdown at all.
+    pub fn start(self, log: &mut Logger) -> Option<Countdown> {
+        if let Err(err) = mark(&self.marker, &boot_id()) {
+            log.warn(&format!(
+                "autologin: cannot write marker {}: {}; not counting down",
+                self.marker.display(),
+                err
+            ));
+            return None;
+        }
+        log.log(&format!(
+            "autologin: {} in {}s",
+            log.user(&self.user),
+            self.delay.as_secs()
+        ));
+        let now = Instant::now();
+        Some(Countdown {
+            user: self.user,
+            deadline: now + self.delay,
+            next_tick: now + Duration::from_secs(1),
+        })
+    }
+}
+
+/// A running countdown. The UI gets one tick per second.
+pub struct Countdown {
+    pub user: String,
+    deadline: Instant,
+    next_tick: Instant,
+}
+
+impl Countdown {
+    /// When the event loop has to wake up next.
+    pub fn wake_at(&self) -> Instant {
+        self.next_tick.min(self.deadline)
+    }
+
+    pub fn remaining_secs(&self) -> u64 {
+        let left = self.deadline.saturating_duration_since(Instant::now());
+        // Round up, so the UI shows 1 until the login actually starts.
+        left.as_secs() + u64::from(left.subsec_nanos() > 0)
+    }
+
+    pub fn expired(&self) -> bool {
+        Instant::now() >= self.deadline
+    }
+
+    /// True once per second while counting down.
+    pub fn tick(&mut self) -> bool {
+        let now = Instant::now();
+        if now < self.next_tick {
+            return false;
+        }
+        while self.next_tick <= now {
+            self.next_tick += Duration::from_secs(1);
+        }
+        true
+    }
+}
+
+fn boot_id() -> String {
+    fs::read_to_string(BOOT_ID)
+        .map(|id| id.trim().to_string())
+        .unwrap_or_default()
+}
+
+/// The marker lives on tmpfs, so existing at all means this boot; the boot
+/// id guards against a marker path on persistent storage.
+fn marked(marker: &Path, boot: &str) -> bool {
+    match fs::read_to_string(marker) {
+        Ok(content) => boot.is_empty() || content.trim() == boot,
+        Err(_) => false,
+    }
+}
+
+fn mark(marker: &Path, boot: &str) -> std::io::Result<()> {
+    if let Some(parent) = marker.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(marker, format!("{}\n", boot))
+}
@@ -1,41 +1,10 @@
-use anyhow::Context;
-use serde::{Deserialize, Serialize};
-use std::io::{Read, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
 use std::os::unix::net::UnixStream;
+use tiss_greetd_ipc::{Client, Response, Secret, SessionState};
 
-use crate::logging::Logger;
+use tiss_greetd_logging::{Fields, Level, Logger};
 
-#[derive(Debug, Serialize)]
-#[serde(tag = "type", rename_all = "snake_case")]
-enum Request {
-    CreateSession { username: String },
-    PostAuthMessageResponse {
-        #[serde(skip_serializing_if = "Option::is_none")]
-        response: Option<String>,
-    },
-    StartSession { cmd: Vec<String>, env: Vec<String> },
-    CancelSession,
-}
-
-#[derive(Debug, Deserialize)]
-#[serde(tag = "type", rename_all = "snake_case")]
-enum Response {
-    Success,
-    Error { error_type: String, description: String },
-    AuthMessage {
-        auth_message_type: AuthMessageType,
-        auth_message: String,
-    },
-}
-
-#[derive(Debug, Deserialize, PartialEq, Eq, Copy, Clone)]
-#[serde(rename_all = "snake_case")]
-pub enum AuthMessageType {
-    Visible,
-    Secret,
-    Info,
-    Error,
-}
+pub use tiss_greetd_ipc::AuthMessageType;
 
 #[derive(Debug, Clone, Copy)]
 pub enum AuthErrorCode {
@@ -43,6 +12,9 @@ pub enum AuthErrorCode {
     AccountLocked,
     PasswordExpired,
     PamError,
+    RateLimited,
+    Cancelled,
+    Timeout,
 }
 
 impl AuthErrorCode {
@@ -52,6 +24,9 @@ impl AuthErrorCode {
             AuthErrorCode::AccountLocked => "account_locked",
             AuthErrorCode::PasswordExpired => "password_expired",
             AuthErrorCode::PamError => "pam_error",
+            AuthErrorCode::RateLimited => "rate_limited",
+            AuthErrorCode::Cancelled => "cancelled",
+            AuthErrorCode::Timeout => "timeout",
         }
     }
 }
@@ -61,6 +36,7 @@ pub struct AuthError {
     code: AuthErrorCode,
     message: String,
     return_to_idle: bool,
+    retry_after_secs: Option<u64>,
 }
 
 impl AuthError {
@@ -69,6 +45,7 @@ impl AuthError {
             code: kind.code(),
             message: format_auth_error(kind, detail),
             return_to_idle: false,
+            retry_after_secs: None,
         }
     }
 
@@ -77,22 +54,47 @@ impl AuthError {
             code: AuthErrorCode::PamError,
             message: message.into(),
             return_to_idle: false,
+            retry_after_secs: None,
         }
     }
 
     pub fn cancelled() -> Self {
         AuthError {
-            code: AuthErrorCode::PamError,
+            code: AuthErrorCode::Cancelled,
             message: "authentication cancelled".to_string(),
             return_to_idle: true,
+            retry_after_secs: None,
         }
     }
 
     pub fn timeout() -> Self {
         AuthError {
-            code: AuthErrorCode::PamError,
+            code: AuthErrorCode::Timeout,
             message: "authentication timed out".to_string(),
             return_to_idle: true,
+            retry_after_secs: None,
+        }
+    }
+
+    /// PAM asked an autologin for input; only passwordless users qualify.
+    pub fn autologin_needs_input() -> Self {
+        AuthError {
+            code: AuthErrorCode::PamError,
+            message: "autologin needs interactive authentication".to_string(),
+            return_to_idle: true,
+            retry_after_secs: None,
+        }
+    }
+
+    pub fn rate_limited(retry_after_secs: u64) -> Self {
+        AuthError {
+            code: AuthErrorCode::RateLimited,
+            message: format!(
+                "Too many failed attempts. Try again in {} seconds.",
+                retry_after_secs
+            ),
+            return_to_idle: false,
+            retry_after_secs: Some(retry_after_secs),
         }
     }
 
@@ -107,6 +109,10 @@ impl AuthError {
     pub fn return_to_idle(&self) -> bool {
         self.return_to_idle
     }
+
+    pub fn retry_after_secs(&self) -> Option<u64> {
+        self.retry_after_secs
+    }
 }
 
 impl std::fmt::Display for AuthError {
@@ -123,61 +129,107 @@ impl From<anyhow::Error> for AuthError {
     }
 }
 
+impl From<tiss_greetd_ipc::Error> for AuthError {
+    fn from(err: tiss_greetd_ipc::Error) -> Self {
+        AuthError::pam_error(err.to_string())
+    }
+}
+
 pub type AuthResult<T> = std::result::Result<T, AuthError>;
 
-fn write_request(stream: &mut UnixStream, mut req: Request) -> anyhow::Result<()> {
-    let payload = serde_json::to_vec(&req).context("serialize greetd request")?;
-    zero_request(&mut req);
-    let len = u32::try_from(payload.len()).context("payload too large")?;
-    stream
-        .write_all(&len.to_ne_bytes())
-        .context("write greetd length")?;
-    stream
-        .write_all(&payload)
-        .context("write greetd payload")?;
-    stream.flush().ok();
-    Ok(())
+/// One greetd conversation, driven by the backend event loop.
+///
+/// Every request is written immediately; responses are only read once the
+/// socket is reported readable, so the caller never blocks on greetd.
+pub struct Conversation {
+    client: Client<UnixStream>,
+    starting: bool,
+    last_info: Option<String>,
 }
 
-fn read_response(stream: &mut UnixStream) -> anyhow::Result<Response> {
-    let mut len_buf = [0u8; 4];
-    stream.read_exact(&mut len_buf).context("read greetd length")?;
-    let len = u32::from_ne_bytes(len_buf) as usize;
-    let mut payload = vec![0u8; len];
-    stream
-        .read_exact(&mut payload)
-        .context("read greetd payload")?;
-    serde_json::from_slice(&payload).context("parse greetd response")
+/// Outcome of a single greetd response.
+pub enum Event {
+    /// PAM asks for input; answer with [`Conversation::respond`].
+    Prompt {
+        kind: AuthMessageType,
+        message: String,
+    },
+    /// Informational PAM text; already acknowledged towards greetd.
+    Message {
+        kind: AuthMessageType,
+        message: String,
+    },
+    /// Authentication succeeded; call [`Conversation::start_session`].
+    Authenticated,
+    /// greetd accepted start_session.
+    Started,
 }
 
-fn cancel_session(stream: &mut UnixStream) {
-    let _ = write_request(stream, Request::CancelSession);
-}
+impl Conversation {
+    pub fn begin(username: &str, log: &mut Logger) -> AuthResult<Self> {
+        let mut client = Client::connect_env()?;
 
-pub fn authenticate_and_start(
-    username: &str,
-    command: &[String],
-    env: &[String],
-    log: &mut Logger,
-    mut prompt: impl FnMut(AuthMessageType, &str) -> AuthResult<Option<String>>,
-    on_waiting: &mut dyn FnMut(),
-) -> AuthResult<()> {
-    let sock = std::env::var("GREETD_SOCK").context("GREETD_SOCK not set")?;
-    let mut stream = UnixStream::connect(sock).context("connect greetd socket")?;
-
-    log.log(&format!("create_session {}", username));
-    write_request(
-        &mut stream,
-        Request::CreateSession {
-            username: username.to_string(),
-        },
-    )?;
-
-    let mut last_info: Option<String> = None;
-
-    loop {
-        match read_response(&mut stream)? {
-            Response::Success => break,
+        log.event(
+            Level::Info,
+            "create_session",
+            Fields {
+                user: Some(username),
+                ..Default::default()
+            },
+        );
+        client.create_session(username)?;
+
+        Ok(Conversation {
+            client,
+            starting: false,
+            last_info: None,
+        })
+    }
+
+    pub fn as_raw_fd(&self) -> RawFd {
+        self.client.get_ref().as_raw_fd()
+    }
+
+    /// True while a greetd response is outstanding.
+    pub fn awaiting_greetd(&self) -> bool {
+        self.client.state() == SessionState::AwaitingResponse
+    }
+
+    /// Reads one response from greetd. On error the greetd session has
+    /// already been cancelled.
+    pub fn handle_readable(&mut self, log: &mut Logger) -> AuthResult<Event> {
+        let response = match self.client.read_response() {
+            Ok(response) => response,
+            Err(err) => {
+                self.cancel();
+                return Err(err.into());
+            }
+        };
+        if !self.starting {
+            return self.handle_auth_response(response, log);
+        }
+        match response {
+            Response::Success => Ok(Event::Started),
+            Response::Error {
+                error_type,
+                description,
+            } => {
+                self.cancel();
+                Err(AuthError::pam_error(format!("{}: {}", error_type, description)))
+            }
+            Response::AuthMessage { auth_message, .. } => {
+                self.cancel();
+                Err(AuthError::pam_error(format!(
+                    "unexpected auth message during start_session: {}",
+                    auth_message
+                )))
+            }
+        }
+    }
+
+    fn handle_auth_response(&mut self, response: Response, log: &mut Logger) -> AuthResult<Event> {
+        match response {
+            Response::Success => Ok(Event::Authenticated),
             Response::AuthMessage {
                 auth_message_type,
                 auth_message,
@@ -185,58 +237,45 @@ pub fn authenticate_and_start(
                 AuthMessageType::Info | AuthMessageType::Error => {
                     let msg = auth_message.trim();
                     if !msg.is_empty() {
-                        log.log(&format!("pam message: {:?}: {}", auth_message_type, msg));
+                        log.debug(&format!("pam message: {:?}: {}", auth_message_type, log.pam_text(msg)));
+                        self.last_info = Some(auth_message.clone());
                     } else {
-                        log.log(&format!("pam message: {:?} (empty)", auth_message_type));
+                        log.debug(&format!("pam message: {:?} (empty)", auth_message_type));
                     }
-                    if !auth_message.trim().is_empty() {
-                        last_info = Some(auth_message.clone());
+                    let message = msg.to_string();
+                    if let Err(err) = self.client.post_auth_message_response(None) {
+                        self.cancel();
+                        return Err(err.into());
                     }
-                    let response = match prompt(auth_message_type, msg) {
-                        Ok(resp) => resp,
-                        Err(err) => {
-                            cancel_session(&mut stream);
-                            return Err(err);
-                        }
-                    };
-                    write_request(
-                        &mut stream,
-                        Request::PostAuthMessageResponse { response },
-                    )?;
+                    Ok(Event::Message {
+                        kind: auth_message_type,
+                        message,
+                    })
                 }
                 AuthMessageType::Visible | AuthMessageType::Secret => {
                     let prompt_text = auth_message.trim();
                     if !prompt_text.is_empty() {
-                        log.log(&format!("pam prompt: {:?}: {}", auth_message_type, prompt_text));
+                        log.debug(&format!(
+                            "pam prompt: {:?}: {}",
+                            auth_message_type,
+                            log.pam_text(prompt_text)
+                        ));
                     } else {
-                        log.log(&format!("pam prompt: {:?} (empty)", auth_message_type));
+                        log.debug(&format!("pam prompt: {:?} (empty)", auth_message_type));
                     }
-                    let response = match prompt(auth_message_type, prompt_text) {
-                        Ok(Some(resp)) => resp,
-                        Ok(None) => {
-                            cancel_session(&mut stream);
-                            return Err(AuthError::pam_error("prompt response missing"));
-                        }
-                        Err(err) => {
-                            cancel_session(&mut stream);
-                            return Err(err);
-                        }
-                    };
-                    write_request(
-                        &mut stream,
-                        Request::PostAuthMessageResponse {
-                            response: Some(response),
-                        },
-                    )?;
+                    Ok(Event::Prompt {
+                        kind: auth_message_type,
+                        message: prompt_text.to_string(),
+                    })
                 }
             },
             Response::Error {
                 error_type,
                 description,
             } => {
-                log.log(&format!("greetd error: {} {}", error_type, description));
-                cancel_session(&mut stream);
-                let detail = last_info.unwrap_or_default();
+                log.warn(&format!("greetd error: {} {}", error_type, log.pam_text(&description)));
+                self.cancel();
+                let detail = self.last_info.take().unwrap_or_default();
                 if error_type == "auth_error" {
                     let kind = classify_auth_failure(&description, &detail);
                     return Err(AuthError::auth_failure(kind, &detail));
@@ -246,31 +285,39 @@ pub fn authenticate_and_start(
                 } else {
                     format!("{}: {} ({})", error_type, description, detail)
                 };
-                return Err(AuthError::pam_error(message));
+                Err(AuthError::pam_error(message))
             }
         }
     }
 
-    log.log("start_session");
-    on_waiting();
-    write_request(
-        &mut stream,
-        Request::StartSession {
-            cmd: command.to_vec(),
-            env: env.to_vec(),
-        },
-    )?;
-
-    match read_response(&mut stream)? {
-        Response::Success => Ok(()),
-        Response::Error {
-            error_type,
-            description,
-        } => Err(AuthError::pam_error(format!("{}: {}", error_type, description))),
-        Response::AuthMessage { auth_message, .. } => Err(AuthError::pam_error(format!(
-            "unexpected auth message during start_session: {}",
-            auth_message
-        ))),
+    /// Answers the pending visible/secret prompt.
+    pub fn respond(&mut self, response: Option<Secret>) -> AuthResult<()> {
+        if response.is_none() {
+            self.cancel();
+            return Err(AuthError::pam_error("prompt response missing"));
+        }
+        if let Err(err) = self.client.post_auth_message_response(response) {
+            self.cancel();
+            return Err(err.into());
+        }
+        Ok(())
+    }
+
+    pub fn start_session(
+        &mut self,
+        command: &[String],
+        env: &[String],
+        log: &mut Logger,
+    ) -> AuthResult<()> {
+        log.log("start_session");
+        self.starting = true;
+        self.client.start_session(command.to_vec(), env.to_vec())?;
+        Ok(())
+    }
+
+    /// Cancels the greetd session; the connection closes on drop.
+    pub fn cancel(&mut self) {
+        let _ = self.client.cancel_session();
     }
 }
 
@@ -344,18 +391,6 @@ fn map_pam_token(token: &str) -> Option<AuthFailureKind> {
     }
 }
 
-fn zero_request(req: &mut Request) {
-    if let Request::PostAuthMessageResponse { response: Some(ref mut value) } = req {
-        zero_string(value);
-    }
-}
-
-fn zero_string(value: &mut String) {
-    unsafe {
-        value.as_mut_vec().fill(0);
-    }
-}
-
 fn format_auth_error(kind: AuthFailureKind, detail: &str) -> String {
     let suffix = detail.trim();
     let message = match kind {
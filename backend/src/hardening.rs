@@ -0,0 +1,60 @@
+use anyhow::{bail, Result};
+use std::env;
+
+/// Which process protections are in effect, for the startup log.
+pub struct Report {
+    pub euid: libc::uid_t,
+    pub dumpable_off: bool,
+    pub core_limit_zero: bool,
+    pub mlock: bool,
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let flag = |on: bool| if on { "on" } else { "OFF" };
+        write!(
+            f,
+            "hardening: euid={} no_dump={} core_limit_0={} mlock={}",
+            self.euid,
+            flag(self.dumpable_off),
+            flag(self.core_limit_zero),
+            flag(self.mlock)
+        )
+    }
+}
+
+/// `TISS_GREETD_ALLOW_ROOT=1` (from `[security] allow_root`) permits root.
+pub fn root_allowed() -> bool {
+    matches!(
+        env::var("TISS_GREETD_ALLOW_ROOT").unwrap_or_default().trim(),
+        "1" | "true" | "yes"
+    )
+}
+
+/// Locks down the process before any secret is read: no core dumps, no
+/// ptrace/`/proc/<pid>/mem` access by the same uid, and a check that secret
+/// buffers can be `mlock`ed. Running as root is refused unless allowed.
+pub fn apply(allow_root: bool) -> Result<Report> {
+    let euid = unsafe { libc::geteuid() };
+    if euid == 0 && !allow_root {
+        bail!("refusing to run as root; set [security] allow_root = true to override");
+    }
+
+    let dumpable_off = unsafe { libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0) } == 0;
+
+    let no_core = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    let core_limit_zero = unsafe { libc::setrlimit(libc::RLIMIT_CORE, &no_core) } == 0;
+
+    let probe = vec![0u8; 64];
+    let mlock = tiss_greetd_ipc::lock_memory(&probe);
+
+    Ok(Report {
+        euid,
+        dumpable_off,
+        core_limit_zero,
+        mlock,
+    })
+}
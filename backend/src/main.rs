@@ -1,18 +1,26 @@
 use anyhow::{Context, Result};
-use serde::{Deserialize, Serialize};
-use std::cell::{Cell, RefCell};
+use serde::Deserialize;
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::env;
 use std::fs;
 use std::fs::File;
-use std::io::{self, BufRead, Write};
-use std::os::unix::io::AsRawFd;
-use std::rc::Rc;
+use std::io::Write;
+use std::path::PathBuf;
 use std::time::{Duration, Instant};
 
+use tiss_greetd_config::state::{PersistedState, RememberedSelection, STATE_VERSION};
+use tiss_greetd_ipc::Secret;
+use tiss_greetd_logging as logging;
+use tiss_greetd_protocol as protocol;
+use tiss_greetd_protocol::Phase;
+
+mod audit;
+mod autologin;
 mod greetd;
-mod logging;
-mod protocol;
+mod hardening;
+mod ratelimit;
+mod sandbox;
+mod transport;
 
 fn default_command(log: &mut logging::Logger) -> Vec<String> {
     if let Ok(cmd_json) = env::var("TISS_GREETD_SESSION_JSON") {
@@ -21,23 +29,54 @@ fn default_command(log: &mut logging::Logger) -> Vec<String> {
                 return cmd;
             }
         } else {
-            log.log("invalid TISS_GREETD_SESSION_JSON; falling back to default command");
+            log.warn("invalid TISS_GREETD_SESSION_JSON; falling back to default command");
         }
     }
     vec!["niri".to_string()]
 }
 
-fn default_env() -> BTreeMap<String, String> {
+/// Installed by the package; see `scripts/tiss-greetd-x11-session`.
+const DEFAULT_X11_WRAPPER: &str = "/usr/lib/tiss-greetd/tiss-greetd-x11-session";
+
+/// XDG session variables for `session`, or for a bare `command` when no
+/// known session was picked. `XDG_CURRENT_DESKTOP` comes from the entry's
+/// `DesktopNames`; without those, and for `XDG_SESSION_DESKTOP`, the desktop
+/// file id (or the session id, or the command's file name) is used.
+fn session_env(session: Option<&SessionListEntry>, command: &[String]) -> BTreeMap<String, String> {
+    let fallback_name = match session {
+        Some(session) => session
+            .desktop_id
+            .clone()
+            .filter(|id| !id.trim().is_empty())
+            .unwrap_or_else(|| session.id.clone()),
+        None => command
+            .first()
+            .map(|program| program.rsplit('/').next().unwrap_or(program).to_string())
+            .unwrap_or_default(),
+    };
+    let session_type = session
+        .and_then(|session| session.session_type.as_deref())
+        .filter(|value| !value.trim().is_empty())
+        .unwrap_or("wayland");
+    let desktop_names = session
+        .map(|session| session.desktop_names.join(":"))
+        .filter(|names| !names.is_empty())
+        .unwrap_or_else(|| fallback_name.clone());
+
     let mut env = BTreeMap::new();
-    env.insert("XDG_SESSION_TYPE".to_string(), "wayland".to_string());
+    env.insert("XDG_SESSION_TYPE".to_string(), session_type.to_string());
     env.insert("XDG_SESSION_CLASS".to_string(), "user".to_string());
-    env.insert("XDG_CURRENT_DESKTOP".to_string(), "niri".to_string());
-    env.insert("XDG_SESSION_DESKTOP".to_string(), "niri".to_string());
+    if !desktop_names.is_empty() {
+        env.insert("XDG_CURRENT_DESKTOP".to_string(), desktop_names);
+    }
+    if !fallback_name.is_empty() {
+        env.insert("XDG_SESSION_DESKTOP".to_string(), fallback_name);
+    }
     env
 }
 
-fn build_env(overrides: HashMap<String, String>) -> Vec<String> {
-    let mut env_map = default_env();
+fn build_env(base: BTreeMap<String, String>, overrides: HashMap<String, String>) -> Vec<String> {
+    let mut env_map = base;
     for (key, value) in overrides {
         if key.trim().is_empty() {
             continue;
@@ -59,7 +98,7 @@ fn auth_timeout(log: &mut logging::Logger) -> Option<Duration> {
         Ok(0) => None,
         Ok(secs) => Some(Duration::from_secs(secs)),
         Err(err) => {
-            log.log(&format!(
+            log.warn(&format!(
                 "invalid TISS_GREETD_AUTH_TIMEOUT_SECS='{}': {}",
                 value, err
             ));
@@ -75,31 +114,29 @@ fn send_response(out: &mut dyn Write, resp: protocol::BackendResponse) -> Result
     Ok(())
 }
 
-fn send_error(out: &mut dyn Write, code: &str, message: impl Into<String>) -> Result<()> {
-    send_response(
-        out,
-        protocol::BackendResponse::Error {
-            code: code.to_string(),
-            message: message.into(),
-        },
-    )
-}
-
-fn set_phase(out: &mut dyn Write, current: &Cell<&'static str>, phase: &'static str) -> Result<()> {
-    current.set(phase);
-    send_response(
-        out,
-        protocol::BackendResponse::State {
-            phase: phase.to_string(),
-        },
-    )
-}
-
 #[derive(Debug, Deserialize)]
 struct SessionListEntry {
     id: String,
+    /// Desktop file id, when `id` was changed to tell sessions apart.
+    #[serde(default)]
+    desktop_id: Option<String>,
     #[serde(default)]
     exec: Vec<String>,
+    /// `wayland` or `x11`.
+    #[serde(default, rename = "type")]
+    session_type: Option<String>,
+    /// `DesktopNames` of the desktop entry.
+    #[serde(default)]
+    desktop_names: Vec<String>,
+    /// Extra environment from a `[[sessions]]` config entry.
+    #[serde(default)]
+    env: HashMap<String, String>,
+}
+
+impl SessionListEntry {
+    fn is_x11(&self) -> bool {
+        self.session_type.as_deref().map(str::trim) == Some("x11")
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -111,27 +148,8 @@ struct ProfileEntry {
     env: HashMap<String, String>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Default)]
-struct PersistedState {
-    #[serde(default)]
-    last_session_id: Option<String>,
-    #[serde(default)]
-    last_profile_id: Option<String>,
-    #[serde(default)]
-    last_locale: Option<String>,
-}
-
-fn read_line(reader: &mut dyn BufRead) -> Result<Option<String>> {
-    let mut line = String::new();
-    let bytes = reader.read_line(&mut line).context("read line")?;
-    if bytes == 0 {
-        return Ok(None);
-    }
-    Ok(Some(line))
-}
-
 fn parse_request(line: &str) -> Result<protocol::UiRequest> {
-    serde_json::from_str(line).context("parse request json")
+    protocol::parse_request(line).context("parse request json")
 }
 
 fn prompt_kind(kind: greetd::AuthMessageType) -> (&'static str, bool) {
@@ -154,75 +172,9 @@ fn message_kind(kind: greetd::AuthMessageType) -> &'static str {
     }
 }
 
-fn wait_prompt_response(
-    prompt_id: u64,
-    reader: &mut dyn BufRead,
-    stdin_fd: std::os::unix::io::RawFd,
-    out: &mut dyn Write,
-    timeout: Option<Duration>,
-) -> greetd::AuthResult<Option<String>> {
-    let start = Instant::now();
-    loop {
-        if let Some(timeout) = timeout {
-            let elapsed = start.elapsed();
-            let remaining = timeout.saturating_sub(elapsed);
-            if remaining.is_zero() {
-                return Err(greetd::AuthError::timeout());
-            }
-            if !poll_readable(stdin_fd, remaining).map_err(greetd::AuthError::from)? {
-                return Err(greetd::AuthError::timeout());
-            }
-        }
-        let line = match read_line(reader)? {
-            Some(line) => line,
-            None => return Err(greetd::AuthError::pam_error("ui disconnected during auth")),
-        };
-        if line.trim().is_empty() {
-            continue;
-        }
-        let req = match parse_request(&line) {
-            Ok(req) => req,
-            Err(err) => {
-                let _ = send_error(out, "pam_error", format!("invalid json: {}", err));
-                continue;
-            }
-        };
-        match req {
-            protocol::UiRequest::PromptResponse { id, response } => {
-                if id == prompt_id {
-                    return Ok(response);
-                }
-                let _ = send_error(out, "pam_error", format!("unexpected prompt id: {}", id));
-            }
-            protocol::UiRequest::Cancel => {
-                return Err(greetd::AuthError::cancelled());
-            }
-            protocol::UiRequest::Hello { .. } => {
-                continue;
-            }
-            _ => {
-                let _ = send_error(out, "pam_error", "auth in progress");
-            }
-        }
-    }
-}
-
-fn poll_readable(fd: std::os::unix::io::RawFd, timeout: Duration) -> Result<bool> {
-    let millis = timeout
-        .as_millis()
-        .min(i32::MAX as u128)
-        .try_into()
-        .unwrap_or(i32::MAX);
-    let mut pollfd = libc::pollfd {
-        fd,
-        events: libc::POLLIN,
-        revents: 0,
-    };
-    let res = unsafe { libc::poll(&mut pollfd as *mut libc::pollfd, 1, millis) };
-    if res < 0 {
-        Err(std::io::Error::last_os_error()).context("poll stdin")?
-    }
-    Ok(res > 0)
+/// Hash-chained record of every authentication outcome.
+fn audit_path() -> PathBuf {
+    state_path().with_file_name("audit.jsonl")
 }
 
 fn state_path() -> std::path::PathBuf {
@@ -234,7 +186,7 @@ fn state_path() -> std::path::PathBuf {
     std::path::PathBuf::from("/var/lib/tiss-greetd/state.json")
 }
 
-fn load_sessions(log: &mut logging::Logger) -> HashMap<String, Vec<String>> {
+fn load_sessions(log: &mut logging::Logger) -> HashMap<String, SessionListEntry> {
     let raw = env::var("TISS_GREETD_SESSIONS_JSON").unwrap_or_default();
     if raw.trim().is_empty() {
         return HashMap::new();
@@ -242,7 +194,7 @@ fn load_sessions(log: &mut logging::Logger) -> HashMap<String, Vec<String>> {
     let entries: Vec<SessionListEntry> = match serde_json::from_str(&raw) {
         Ok(entries) => entries,
         Err(err) => {
-            log.log(&format!("invalid TISS_GREETD_SESSIONS_JSON: {}", err));
+            log.warn(&format!("invalid TISS_GREETD_SESSIONS_JSON: {}", err));
             return HashMap::new();
         }
     };
@@ -251,11 +203,27 @@ fn load_sessions(log: &mut logging::Logger) -> HashMap<String, Vec<String>> {
         if entry.id.trim().is_empty() || entry.exec.is_empty() {
             continue;
         }
-        sessions.insert(entry.id, entry.exec);
+        sessions.insert(entry.id.clone(), entry);
     }
     sessions
 }
 
+/// Command prefix that starts an X server for `x11` sessions.
+fn load_x11_wrapper(log: &mut logging::Logger) -> Vec<String> {
+    let raw = env::var("TISS_GREETD_X11_WRAPPER_JSON").unwrap_or_default();
+    if !raw.trim().is_empty() {
+        match serde_json::from_str::<Vec<String>>(&raw) {
+            Ok(wrapper) if !wrapper.is_empty() => return wrapper,
+            Ok(_) => {}
+            Err(err) => log.warn(&format!(
+                "invalid TISS_GREETD_X11_WRAPPER_JSON: {}; using {}",
+                err, DEFAULT_X11_WRAPPER
+            )),
+        }
+    }
+    vec![DEFAULT_X11_WRAPPER.to_string()]
+}
+
 fn load_profiles(log: &mut logging::Logger) -> HashMap<String, ProfileEntry> {
     let raw = env::var("TISS_GREETD_PROFILES_JSON").unwrap_or_default();
     if raw.trim().is_empty() {
@@ -264,7 +232,7 @@ fn load_profiles(log: &mut logging::Logger) -> HashMap<String, ProfileEntry> {
     let entries: Vec<ProfileEntry> = match serde_json::from_str(&raw) {
         Ok(entries) => entries,
         Err(err) => {
-            log.log(&format!("invalid TISS_GREETD_PROFILES_JSON: {}", err));
+            log.warn(&format!("invalid TISS_GREETD_PROFILES_JSON: {}", err));
             return HashMap::new();
         }
     };
@@ -278,6 +246,23 @@ fn load_profiles(log: &mut logging::Logger) -> HashMap<String, ProfileEntry> {
     profiles
 }
 
+fn load_users(log: &mut logging::Logger) -> Vec<protocol::UserInfo> {
+    let raw = env::var("TISS_GREETD_USERS_JSON").unwrap_or_default();
+    if raw.trim().is_empty() {
+        return Vec::new();
+    }
+    match serde_json::from_str::<Vec<protocol::UserInfo>>(&raw) {
+        Ok(users) => users
+            .into_iter()
+            .filter(|user| !user.name.trim().is_empty())
+            .collect(),
+        Err(err) => {
+            log.warn(&format!("invalid TISS_GREETD_USERS_JSON: {}", err));
+            Vec::new()
+        }
+    }
+}
+
 fn load_power_actions(log: &mut logging::Logger) -> HashSet<String> {
     let raw = env::var("TISS_GREETD_POWER_ACTIONS_JSON").unwrap_or_default();
     if raw.trim().is_empty() {
@@ -286,7 +271,7 @@ fn load_power_actions(log: &mut logging::Logger) -> HashSet<String> {
     let entries: Vec<String> = match serde_json::from_str(&raw) {
         Ok(entries) => entries,
         Err(err) => {
-            log.log(&format!("invalid TISS_GREETD_POWER_ACTIONS_JSON: {}", err));
+            log.warn(&format!("invalid TISS_GREETD_POWER_ACTIONS_JSON: {}", err));
             return HashSet::new();
         }
     };
@@ -297,23 +282,42 @@ fn load_power_actions(log: &mut logging::Logger) -> HashSet<String> {
         .collect()
 }
 
-fn load_power_allowed_states(log: &mut logging::Logger) -> HashSet<String> {
+/// Phases in which power actions are allowed. Unknown phase names are
+/// rejected with a log line; if none are left, only `idle` is allowed.
+fn load_power_allowed_states(log: &mut logging::Logger) -> HashSet<Phase> {
+    let default: HashSet<Phase> = [Phase::Idle].into_iter().collect();
     let raw = env::var("TISS_GREETD_POWER_ALLOWED_STATES_JSON").unwrap_or_default();
     if raw.trim().is_empty() {
-        return ["idle".to_string()].into_iter().collect();
+        return default;
     }
     let entries: Vec<String> = match serde_json::from_str(&raw) {
         Ok(entries) => entries,
         Err(err) => {
-            log.log(&format!("invalid TISS_GREETD_POWER_ALLOWED_STATES_JSON: {}", err));
-            return ["idle".to_string()].into_iter().collect();
+            log.warn(&format!("invalid TISS_GREETD_POWER_ALLOWED_STATES_JSON: {}", err));
+            return default;
         }
     };
-    entries
-        .into_iter()
-        .map(|entry| entry.trim().to_ascii_lowercase())
-        .filter(|entry| !entry.is_empty())
-        .collect()
+    let mut states = HashSet::new();
+    for entry in entries {
+        let entry = entry.trim().to_ascii_lowercase();
+        if entry.is_empty() {
+            continue;
+        }
+        match Phase::parse(&entry) {
+            Some(phase) => {
+                states.insert(phase);
+            }
+            None => log.warn(&format!(
+                "invalid TISS_GREETD_POWER_ALLOWED_STATES_JSON: unknown phase {:?}",
+                entry
+            )),
+        }
+    }
+    if states.is_empty() {
+        log.warn("no valid power allowed states; using idle");
+        return default;
+    }
+    states
 }
 
 fn read_state(log: &mut logging::Logger) -> PersistedState {
@@ -325,21 +329,41 @@ fn read_state(log: &mut logging::Logger) -> PersistedState {
     let mut state: PersistedState = match serde_json::from_str(&content) {
         Ok(state) => state,
         Err(err) => {
-            log.log(&format!("failed to parse state {}: {}", path.display(), err));
+            log.error(&format!("failed to parse state {}: {}", path.display(), err));
             return PersistedState::default();
         }
     };
-    state.last_session_id = state.last_session_id.filter(|value| !value.trim().is_empty());
-    state.last_profile_id = state.last_profile_id.filter(|value| !value.trim().is_empty());
-    state.last_locale = state.last_locale.filter(|value| !value.trim().is_empty());
+    state.migrate();
+    state.normalize();
     state
 }
 
+/// Rewrites a state file from an older version in the current layout.
+fn migrate_state(log: &mut logging::Logger) {
+    let path = state_path();
+    let Ok(content) = fs::read_to_string(&path) else {
+        return;
+    };
+    let Ok(mut state) = serde_json::from_str::<PersistedState>(&content) else {
+        return;
+    };
+    let from = state.version.max(1);
+    if state.migrate() {
+        write_state(&state, log);
+        log.log(&format!(
+            "migrated state {} from version {} to {}",
+            path.display(),
+            from,
+            STATE_VERSION
+        ));
+    }
+}
+
 fn write_state(state: &PersistedState, log: &mut logging::Logger) {
     let path = state_path();
     if let Some(parent) = path.parent() {
         if let Err(err) = fs::create_dir_all(parent) {
-            log.log(&format!(
+            log.error(&format!(
                 "failed to create state dir {}: {}",
                 parent.display(),
                 err
@@ -353,81 +377,48 @@ fn write_state(state: &PersistedState, log: &mut logging::Logger) {
             match File::create(&tmp_path) {
                 Ok(mut file) => {
                     if let Err(err) = file.write_all(&payload) {
-                        log.log(&format!("failed to write state {}: {}", tmp_path.display(), err));
+                        log.error(&format!("failed to write state {}: {}", tmp_path.display(), err));
                         return;
                     }
                     if let Err(err) = file.sync_all() {
-                        log.log(&format!("failed to fsync state {}: {}", tmp_path.display(), err));
+                        log.error(&format!("failed to fsync state {}: {}", tmp_path.display(), err));
                         return;
                     }
                     if let Err(err) = fs::rename(&tmp_path, &path) {
-                        log.log(&format!("failed to replace state {}: {}", path.display(), err));
+                        log.error(&format!("failed to replace state {}: {}", path.display(), err));
                     }
                 }
                 Err(err) => {
-                    log.log(&format!("failed to create state {}: {}", tmp_path.display(), err));
+                    log.error(&format!("failed to create state {}: {}", tmp_path.display(), err));
                 }
             }
         }
         Err(err) => {
-            log.log(&format!("failed to serialize state: {}", err));
-        }
-    }
-}
-
-fn wait_for_ack(reader: &mut dyn BufRead, log: &mut logging::Logger) -> Result<()> {
-    loop {
-        let line = match read_line(reader)? {
-            Some(line) => line,
-            None => {
-                log.log("ui disconnected while waiting for success ack");
-                return Ok(());
-            }
-        };
-        if line.trim().is_empty() {
-            continue;
-        }
-        let req = match parse_request(&line) {
-            Ok(req) => req,
-            Err(err) => {
-                log.log(&format!("invalid json while waiting for ack: {}", err));
-                continue;
-            }
-        };
-        match req {
-            protocol::UiRequest::Ack { kind } => {
-                if kind == "success" {
-                    log.log("received success ack");
-                    return Ok(());
-                }
-                log.log(&format!("unexpected ack kind: {}", kind));
-            }
-            _ => {
-                log.log("ignoring request while waiting for success ack");
-            }
+            log.error(&format!("failed to serialize state: {}", err));
         }
     }
 }
 
+/// Records `username` as the last user and remembers the started selection
+/// both for them and globally.
 fn persist_state_update(
+    username: &str,
     session_id: Option<&str>,
     profile_id: Option<&str>,
     locale: Option<&str>,
     log: &mut logging::Logger,
 ) {
-    if session_id.is_none() && profile_id.is_none() && locale.is_none() {
-        return;
-    }
     let mut state = read_state(log);
-    if let Some(session_id) = session_id.map(str::trim).filter(|value| !value.is_empty()) {
-        state.last_session_id = Some(session_id.to_string());
-    }
-    if let Some(profile_id) = profile_id.map(str::trim).filter(|value| !value.is_empty()) {
-        state.last_profile_id = Some(profile_id.to_string());
-    }
-    if let Some(locale) = locale.map(str::trim).filter(|value| !value.is_empty()) {
-        state.last_locale = Some(locale.to_string());
-    }
+    state.last_user = Some(username.to_string());
+    let mut update = RememberedSelection {
+        session_id: session_id.map(str::to_string),
+        profile_id: profile_id.map(str::to_string),
+        locale: locale.map(str::to_string),
+    };
+    update.normalize();
+    let user = state.users.entry(username.to_string()).or_default();
+    *user = update.or(user);
+    state.global = update.or(&state.global);
     write_state(&state, log);
 }
 
@@ -462,288 +453,1221 @@ fn request_power_action(action: &str) -> std::result::Result<(), String> {
     }
 }
 
-fn main() -> Result<()> {
-    let stdin = io::stdin();
-    let mut stdin_lock = stdin.lock();
-    let stdin_fd = stdin.as_raw_fd();
-    let stdout = Rc::new(RefCell::new(io::stdout()));
-    let mut log = logging::Logger::new("backend");
-    let current_phase = Cell::new("idle");
 
-    log.log("backend start");
-    let sessions = load_sessions(&mut log);
-    let profiles = load_profiles(&mut log);
-    let power_actions = load_power_actions(&mut log);
-    let power_allowed_states = load_power_allowed_states(&mut log);
-    let auth_timeout = auth_timeout(&mut log);
-    let mut auth_attempts: u64 = 0;
-    set_phase(&mut *stdout.borrow_mut(), &current_phase, "idle")?;
-
-    loop {
-        let line = match read_line(&mut stdin_lock)? {
-            Some(line) => line,
-            None => break,
+struct Settings {
+    sessions: HashMap<String, SessionListEntry>,
+    profiles: HashMap<String, ProfileEntry>,
+    power_actions: HashSet<String>,
+    power_allowed_states: HashSet<Phase>,
+    auth_timeout: Option<Duration>,
+    rate_limit: ratelimit::Policy,
+    users: Vec<protocol::UserInfo>,
+    x11_wrapper: Vec<String>,
+}
+
+impl Settings {
+    fn load(log: &mut logging::Logger) -> Self {
+        Settings {
+            sessions: load_sessions(log),
+            profiles: load_profiles(log),
+            power_actions: load_power_actions(log),
+            power_allowed_states: load_power_allowed_states(log),
+            auth_timeout: auth_timeout(log),
+            rate_limit: ratelimit::Policy::load(log),
+            users: load_users(log),
+            x11_wrapper: load_x11_wrapper(log),
+        }
+    }
+}
+
+/// What the UI asked to launch, as sent in `auth` or `start`.
+#[derive(Default)]
+struct Selection {
+    command: Vec<String>,
+    env: HashMap<String, String>,
+    session_id: Option<String>,
+    profile_id: Option<String>,
+    locale: Option<String>,
+}
+
+impl Selection {
+    fn new(
+        command: Vec<String>,
+        env: HashMap<String, String>,
+        session_id: Option<String>,
+        profile_id: Option<String>,
+        locale: Option<String>,
+    ) -> Self {
+        Selection {
+            command,
+            env,
+            session_id: trimmed(session_id),
+            profile_id: trimmed(profile_id),
+            locale: trimmed(locale),
+        }
+    }
+
+    /// Applies a later `start` request on top of the selection made at `auth`.
+    /// Naming any of command/session/profile replaces all three; env keys and
+    /// locale override individually.
+    fn overlay(mut self, later: Selection) -> Selection {
+        if !later.command.is_empty() || later.session_id.is_some() || later.profile_id.is_some() {
+            self.command = later.command;
+            self.session_id = later.session_id;
+            self.profile_id = later.profile_id;
+        }
+        self.env.extend(later.env);
+        if later.locale.is_some() {
+            self.locale = later.locale;
+        }
+        self
+    }
+}
+
+/// A resolved start_session request plus the ids to persist on success.
+struct Launch {
+    command: Vec<String>,
+    env: Vec<String>,
+    session_id: Option<String>,
+    profile_id: Option<String>,
+    locale: Option<String>,
+}
+
+/// An in-flight authentication: the greetd conversation plus everything
+/// needed to start the session and persist the selection afterwards.
+struct AuthSession {
+    conversation: greetd::Conversation,
+    username: String,
+    attempt: u64,
+    started: Instant,
+    deadline: Option<Instant>,
+    prompt_id: u64,
+    pending_prompt: Option<PendingPrompt>,
+    /// Wait for a `start` request after authentication instead of starting
+    /// the session right away.
+    defer_start: bool,
+    authenticated: bool,
+    /// Started by the autologin countdown; nobody is there to answer prompts.
+    autologin: bool,
+    selection: Selection,
+    launch: Option<Launch>,
+}
+
+impl AuthSession {
+    fn session_id(&self) -> Option<&str> {
+        match self.launch.as_ref() {
+            Some(launch) => launch.session_id.as_deref(),
+            None => self.selection.session_id.as_deref(),
+        }
+    }
+}
+
+/// The prompt the UI still has to answer; resent when a UI reattaches.
+#[derive(Clone)]
+struct PendingPrompt {
+    id: u64,
+    kind: &'static str,
+    message: String,
+    echo: bool,
+}
+
+impl PendingPrompt {
+    fn to_response(&self) -> protocol::BackendResponse {
+        protocol::BackendResponse::Prompt {
+            id: self.id,
+            kind: self.kind.to_string(),
+            message: self.message.clone(),
+            echo: self.echo,
+        }
+    }
+}
+
+/// Set once start_session succeeded; the backend exits after the UI acks.
+struct Completed {
+    attempt: u64,
+    username: String,
+    started: Instant,
+}
+
+#[derive(PartialEq, Eq)]
+enum Flow {
+    Continue,
+    Exit,
+}
+
+struct Backend {
+    /// The attached UI; `None` while a socket-mode backend waits for one.
+    ui: Option<transport::UiConnection>,
+    listener: Option<transport::UiListener>,
+    log: logging::Logger,
+    settings: Settings,
+    phase: Phase,
+    auth_attempts: u64,
+    auth: Option<AuthSession>,
+    completed: Option<Completed>,
+    /// `None` when the audit key could not be loaded; nothing is recorded.
+    audit: Option<audit::AuditLog>,
+    /// Set when the attached UI announced an unsupported protocol version.
+    ui_rejected: bool,
+    /// Armed autologin rule, waiting for the first `hello` to start counting.
+    autologin_rule: Option<autologin::Rule>,
+    /// Pending autologin; any UI request other than `hello`/`ping` cancels it.
+    autologin: Option<autologin::Countdown>,
+}
+
+fn trimmed(value: Option<String>) -> Option<String> {
+    value.and_then(|value| {
+        let trimmed = value.trim();
+        if trimmed.is_empty() {
+            None
+        } else {
+            Some(trimmed.to_string())
+        }
+    })
+}
+
+impl Backend {
+    /// Moves to `phase` and tells the UI. A move not in the transition table
+    /// is a bug: it fails without changing the phase.
+    fn set_phase(&mut self, phase: Phase) -> Result<()> {
+        debug_assert!(
+            self.phase.can_transition_to(phase),
+            "illegal phase transition: {} -> {}",
+            self.phase,
+            phase
+        );
+        if !self.phase.can_transition_to(phase) {
+            anyhow::bail!("illegal phase transition: {} -> {}", self.phase, phase);
+        }
+        self.log.event(
+            logging::Level::Info,
+            &format!("phase: {} -> {}", self.phase, phase),
+            logging::Fields {
+                phase: Some(phase.as_str()),
+                ..Default::default()
+            },
+        );
+        self.phase = phase;
+        self.send_state()
+    }
+
+    fn send_state(&mut self) -> Result<()> {
+        let phase = self.phase;
+        self.send(protocol::BackendResponse::State { phase })
+    }
+
+    /// Writes to the attached UI. In socket mode a failed write detaches the
+    /// UI instead of ending the backend.
+    fn send(&mut self, resp: protocol::BackendResponse) -> Result<()> {
+        let Some(ui) = self.ui.as_mut() else {
+            return Ok(());
         };
-        if line.trim().is_empty() {
-            continue;
+        match send_response(&mut *ui.writer, resp) {
+            Err(err) if self.listener.is_some() => {
+                self.log.warn(&format!("ui write failed: {:#}; detaching", err));
+                self.ui = None;
+                Ok(())
+            }
+            result => result,
         }
+    }
+
+    fn send_error(&mut self, code: &str, message: impl Into<String>) -> Result<()> {
+        self.send(protocol::BackendResponse::Error {
+            code: code.to_string(),
+            message: message.into(),
+            retry_after_secs: None,
+        })
+    }
+
+    fn send_auth_error(&mut self, err: &greetd::AuthError) -> Result<()> {
+        self.send(protocol::BackendResponse::Error {
+            code: err.code().as_str().to_string(),
+            message: err.message().to_string(),
+            retry_after_secs: err.retry_after_secs(),
+        })
+    }
+
+    fn refresh_deadline(&mut self) {
+        let timeout = self.settings.auth_timeout;
+        if let Some(auth) = self.auth.as_mut() {
+            auth.deadline = timeout.map(|timeout| Instant::now() + timeout);
+        }
+    }
+
+    fn run(&mut self) -> Result<()> {
+        loop {
+            let ui_fd = self.ui.as_ref().map(|ui| ui.reader.as_raw_fd());
+            let greetd_fd = self
+                .auth
+                .as_ref()
+                .filter(|auth| auth.conversation.awaiting_greetd())
+                .map(|auth| auth.conversation.as_raw_fd());
+            let listen_fd = self.listener.as_ref().map(|listener| listener.as_raw_fd());
+            let deadline = self.auth.as_ref().and_then(|auth| auth.deadline);
+            let wake = deadline
+                .into_iter()
+                .chain(self.autologin.as_ref().map(autologin::Countdown::wake_at))
+                .min();
+            let timeout = wake.map(|wake| wake.saturating_duration_since(Instant::now()));
+
+            let ready = transport::poll_inputs(&[ui_fd, greetd_fd, listen_fd], timeout)?;
+            self.advance_autologin()?;
+            if !ready.contains(&true) {
+                if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                    self.abort_auth(greetd::AuthError::timeout())?;
+                }
+                continue;
+            }
+
+            if ready[1] {
+                self.handle_greetd()?;
+            }
+            if ready[0] && self.handle_ui_input()? == Flow::Exit {
+                return Ok(());
+            }
+            if ready[2] {
+                self.accept_ui()?;
+            }
+        }
+    }
 
-        let req: protocol::UiRequest = match parse_request(&line) {
+    fn handle_ui_input(&mut self) -> Result<Flow> {
+        let Some(ui) = self.ui.as_mut() else {
+            return Ok(Flow::Continue);
+        };
+        let open = ui.reader.fill()?;
+        let mut lines = Vec::new();
+        while let Some(line) = ui.reader.next_line() {
+            lines.push(line);
+        }
+        for line in lines {
+            if self.ui.is_none() {
+                break;
+            }
+            if self.handle_line(line.expose())? == Flow::Exit {
+                return Ok(Flow::Exit);
+            }
+        }
+        if !open {
+            if self.ui.as_ref().is_some_and(|ui| ui.reader.overflowed()) {
+                self.log.warn(&format!(
+                    "ui sent a line longer than {} bytes; dropping the connection",
+                    transport::MAX_LINE_BYTES
+                ));
+            }
+            return Ok(self.handle_disconnect());
+        }
+        Ok(Flow::Continue)
+    }
+
+    fn handle_disconnect(&mut self) -> Flow {
+        if self.completed.is_some() {
+            self.log.log("ui disconnected while waiting for success ack");
+            return Flow::Exit;
+        }
+        if self.listener.is_some() {
+            self.log.log("ui disconnected; waiting for reattach");
+            self.ui = None;
+            return Flow::Continue;
+        }
+        if let Some(mut auth) = self.auth.take() {
+            auth.conversation.cancel();
+            self.log.warn("auth failed: ui disconnected during auth");
+        }
+        Flow::Exit
+    }
+
+    fn accept_ui(&mut self) -> Result<()> {
+        let Some(listener) = self.listener.as_ref() else {
+            return Ok(());
+        };
+        let ui = match listener.accept() {
+            Ok(ui) => ui,
+            Err(err) => {
+                self.log.warn(&format!("{:#}", err));
+                return Ok(());
+            }
+        };
+        if self.ui.is_some() {
+            self.log.log("ui attached; replacing previous connection");
+        } else {
+            self.log.log("ui attached");
+        }
+        self.ui = Some(ui);
+        self.ui_rejected = false;
+        self.resync()
+    }
+
+    /// Brings a (re)attached UI up to date: current phase, the prompt it
+    /// still has to answer, or the success it still has to ack.
+    fn resync(&mut self) -> Result<()> {
+        self.send_state()?;
+        let pending = self
+            .auth
+            .as_ref()
+            .and_then(|auth| auth.pending_prompt.as_ref())
+            .map(PendingPrompt::to_response);
+        if let Some(prompt) = pending {
+            self.send(prompt)?;
+        }
+        self.send_autologin()?;
+        if self.completed.is_some() {
+            self.send(protocol::BackendResponse::Success)?;
+        }
+        Ok(())
+    }
+
+    fn handle_greetd(&mut self) -> Result<()> {
+        let Some(auth) = self.auth.as_mut() else {
+            return Ok(());
+        };
+        let event = match auth.conversation.handle_readable(&mut self.log) {
+            Ok(event) => event,
+            Err(err) => return self.fail_auth(err),
+        };
+        match event {
+            greetd::Event::Prompt { .. } if auth.autologin => {
+                self.abort_auth(greetd::AuthError::autologin_needs_input())
+            }
+            greetd::Event::Prompt { kind, message } => {
+                auth.prompt_id += 1;
+                let (kind, echo) = prompt_kind(kind);
+                let prompt = PendingPrompt {
+                    id: auth.prompt_id,
+                    kind,
+                    message,
+                    echo,
+                };
+                let resp = prompt.to_response();
+                auth.pending_prompt = Some(prompt);
+                self.refresh_deadline();
+                self.send(resp)
+            }
+            greetd::Event::Message { kind, message } => {
+                self.refresh_deadline();
+                self.send(protocol::BackendResponse::Message {
+                    kind: message_kind(kind).to_string(),
+                    message,
+                })
+            }
+            greetd::Event::Authenticated => {
+                let username = auth.username.clone();
+                let session_id = auth.session_id().map(str::to_string);
+                let duration_ms = auth.started.elapsed().as_millis() as u64;
+                self.send_last_login(&username)?;
+                // Credentials were accepted whatever happens to the start;
+                // the outcome of that gets its own record.
+                self.audit(audit::Outcome {
+                    user: &username,
+                    session_id: session_id.as_deref(),
+                    result: "authenticated",
+                    duration_ms,
+                });
+                self.clear_auth_failures(&username);
+                let auth = self.auth.as_mut().expect("auth session");
+                if auth.defer_start {
+                    auth.authenticated = true;
+                    self.log.log("auth success; waiting for start request");
+                    self.refresh_deadline();
+                    return self.set_phase(Phase::Authenticated);
+                }
+                let selection = std::mem::take(&mut auth.selection);
+                self.start_session(selection)
+            }
+            greetd::Event::Started => {
+                let auth = self.auth.take().expect("auth session");
+                self.log.log("auth success; start_session ok");
+                self.audit(audit::Outcome {
+                    user: &auth.username,
+                    session_id: auth.session_id(),
+                    result: "success",
+                    duration_ms: auth.started.elapsed().as_millis() as u64,
+                });
+                if let Some(launch) = auth.launch.as_ref() {
+                    persist_state_update(
+                        &auth.username,
+                        launch.session_id.as_deref(),
+                        launch.profile_id.as_deref(),
+                        launch.locale.as_deref(),
+                        &mut self.log,
+                    );
+                }
+                self.completed = Some(Completed {
+                    attempt: auth.attempt,
+                    username: auth.username.clone(),
+                    started: auth.started,
+                });
+                self.set_phase(Phase::Success)?;
+                self.send(protocol::BackendResponse::Success)
+            }
+        }
+    }
+
+    fn start_session(&mut self, selection: Selection) -> Result<()> {
+        let launch = self.resolve_launch(selection);
+        self.set_phase(Phase::Waiting)?;
+        let auth = self.auth.as_mut().expect("auth session");
+        let launch = auth.launch.insert(launch);
+        if let Err(err) =
+            auth.conversation
+                .start_session(&launch.command, &launch.env, &mut self.log)
+        {
+            return self.fail_auth(err);
+        }
+        self.refresh_deadline();
+        Ok(())
+    }
+
+    fn resolve_launch(&mut self, selection: Selection) -> Launch {
+        let Selection {
+            command,
+            env,
+            session_id,
+            profile_id,
+            locale,
+        } = selection;
+        let mut effective_session_id = session_id;
+        let profile = profile_id
+            .as_ref()
+            .and_then(|id| self.settings.profiles.get(id));
+        if effective_session_id.is_none() {
+            if let Some(profile) = profile {
+                let value = profile.session.trim();
+                if !value.is_empty() {
+                    effective_session_id = Some(value.to_string());
+                }
+            }
+        }
+        let session = effective_session_id
+            .as_ref()
+            .and_then(|id| self.settings.sessions.get(id));
+        let mut cmd = if !command.is_empty() {
+            command
+        } else {
+            session.map(|session| session.exec.clone()).unwrap_or_default()
+        };
+        if cmd.is_empty() {
+            cmd = default_command(&mut self.log);
+        }
+
+        // Session env is the base; what the UI sent, the profile and the
+        // locale override it in that order.
+        let mut env_map = session.map(|session| session.env.clone()).unwrap_or_default();
+        env_map.extend(env);
+        if let Some(profile) = profile {
+            for (key, value) in profile.env.iter() {
+                env_map.insert(key.clone(), value.clone());
+            }
+        }
+        if let Some(locale) = locale.as_ref() {
+            env_map.insert("LANG".to_string(), locale.clone());
+            env_map.insert("LC_ALL".to_string(), locale.clone());
+        }
+        let base = session_env(session, &cmd);
+
+        // X sessions cannot run without a server, and a Wayland type left in
+        // `[session.env]` would make logind register the session wrongly.
+        if session.is_some_and(SessionListEntry::is_x11) {
+            env_map.insert("XDG_SESSION_TYPE".to_string(), "x11".to_string());
+            self.log.debug(&format!(
+                "x11 session; starting it through {}",
+                self.settings.x11_wrapper.join(" ")
+            ));
+            cmd = self.settings.x11_wrapper.iter().cloned().chain(cmd).collect();
+        }
+        Launch {
+            env: build_env(base, env_map),
+            command: cmd,
+            session_id: effective_session_id,
+            profile_id,
+            locale,
+        }
+    }
+
+    /// Cancels the active greetd session and reports `err` to the UI.
+    fn abort_auth(&mut self, err: greetd::AuthError) -> Result<()> {
+        if let Some(auth) = self.auth.as_mut() {
+            auth.conversation.cancel();
+        }
+        self.fail_auth(err)
+    }
+
+    fn fail_auth(&mut self, err: greetd::AuthError) -> Result<()> {
+        let Some(auth) = self.auth.take() else {
+            return Ok(());
+        };
+        self.report_auth_failure(
+            &err,
+            auth.attempt,
+            &auth.username,
+            auth.session_id(),
+            auth.started,
+        );
+        if err.return_to_idle() {
+            self.send_auth_error(&err)?;
+            self.set_phase(Phase::Idle)
+        } else {
+            self.set_phase(Phase::Error)?;
+            self.send_auth_error(&err)
+        }
+    }
+
+    /// Logs, audits and counts a failed attempt; the caller moves the phase
+    /// and tells the UI.
+    fn report_auth_failure(
+        &mut self,
+        err: &greetd::AuthError,
+        attempt: u64,
+        username: &str,
+        session_id: Option<&str>,
+        started: Instant,
+    ) {
+        let duration_ms = started.elapsed().as_millis() as u64;
+        self.log.event(
+            logging::Level::Warn,
+            &format!(
+                "auth failed: {}: {}",
+                err.code().as_str(),
+                self.log.pam_text(err.message())
+            ),
+            logging::Fields {
+                attempt: Some(attempt),
+                user: Some(username),
+                duration_ms: Some(duration_ms),
+                phase: Some(self.phase.as_str()),
+            },
+        );
+        self.audit(audit::Outcome {
+            user: username,
+            session_id,
+            result: err.code().as_str(),
+            duration_ms,
+        });
+        self.record_auth_failure(username, err);
+    }
+
+    fn handle_line(&mut self, line: &str) -> Result<Flow> {
+        if line.trim().is_empty() {
+            return Ok(Flow::Continue);
+        }
+        let req = match parse_request(line) {
             Ok(req) => req,
             Err(err) => {
-                let _ = send_error(&mut *stdout.borrow_mut(), "pam_error", format!("invalid json: {}", err));
-                continue;
+                if self.completed.is_some() {
+                    self.log
+                        .warn(&format!("invalid json while waiting for ack: {}", err));
+                } else {
+                    let _ = self.send_error("pam_error", format!("invalid json: {}", err));
+                }
+                return Ok(Flow::Continue);
             }
         };
 
+        if self.ui_rejected
+            && !matches!(
+                req,
+                protocol::UiRequest::Hello { .. } | protocol::UiRequest::Ping
+            )
+        {
+            self.send_error("unsupported_version", "ui protocol version not supported")?;
+            return Ok(Flow::Continue);
+        }
+
+        let is_hello_or_ping = matches!(
+            req,
+            protocol::UiRequest::Hello { .. } | protocol::UiRequest::Ping
+        );
+        // Someone is already using a UI that skipped `hello`; counting down
+        // behind their back would log in over them.
+        if !is_hello_or_ping && self.autologin_rule.take().is_some() {
+            self.log.log("autologin: ui sent a request before hello; not counting down");
+        }
+        if self.autologin.is_some() && !is_hello_or_ping {
+            self.cancel_autologin()?;
+            if matches!(req, protocol::UiRequest::Cancel) {
+                return Ok(Flow::Continue);
+            }
+        }
+
         match req {
             protocol::UiRequest::Hello { ui_version } => {
-                log.log(&format!("request: hello ui_version={}", ui_version));
-                set_phase(&mut *stdout.borrow_mut(), &current_phase, "idle")?;
+                self.handle_hello(ui_version)?;
             }
-            protocol::UiRequest::Auth {
-                username,
+            protocol::UiRequest::Ping => {
+                self.send(protocol::BackendResponse::Pong)?;
+            }
+            protocol::UiRequest::Power { action } => {
+                self.handle_power(action)?;
+            }
+            protocol::UiRequest::SelectUser { username } => {
+                self.handle_select_user(username)?;
+            }
+            protocol::UiRequest::Ack { kind } => {
+                if self.completed.is_none() {
+                    self.send_error("pam_error", "unexpected ack")?;
+                } else if kind == "success" {
+                    self.log.log("received success ack");
+                    if let Some(completed) = self.completed.take() {
+                        self.log.event(
+                            logging::Level::Info,
+                            "auth success",
+                            logging::Fields {
+                                attempt: Some(completed.attempt),
+                                user: Some(&completed.username),
+                                duration_ms: Some(completed.started.elapsed().as_millis() as u64),
+                                phase: Some(self.phase.as_str()),
+                            },
+                        );
+                    }
+                    return Ok(Flow::Exit);
+                } else {
+                    self.log.warn(&format!("unexpected ack kind: {}", kind));
+                }
+            }
+            _ if self.completed.is_some() => {
+                self.log.debug("ignoring request while waiting for success ack");
+            }
+            protocol::UiRequest::Cancel => {
+                if self.auth.is_some() {
+                    self.abort_auth(greetd::AuthError::cancelled())?;
+                } else {
+                    self.send_error("pam_error", "no active auth session")?;
+                }
+            }
+            protocol::UiRequest::PromptResponse { id, response } => {
+                self.handle_prompt_response(id, response)?;
+            }
+            protocol::UiRequest::Start {
                 command,
                 env,
                 session_id,
                 profile_id,
                 locale,
             } => {
-                auth_attempts += 1;
-                let auth_started = Instant::now();
-                log.log(&format!(
-                    "request: auth attempt={} user={}",
-                    auth_attempts, username
+                self.log.log(&format!(
+                    "request: start session_id={} profile_id={} command_len={} env_len={}",
+                    session_id.as_deref().unwrap_or("-"),
+                    profile_id.as_deref().unwrap_or("-"),
+                    command.len(),
+                    env.len()
                 ));
-                set_phase(&mut *stdout.borrow_mut(), &current_phase, "auth")?;
-                let username = username.trim().to_string();
-                if username.is_empty() {
-                    set_phase(&mut *stdout.borrow_mut(), &current_phase, "error")?;
-                    send_error(
-                        &mut *stdout.borrow_mut(),
-                        "pam_error",
-                        "username is required",
-                    )?;
-                    continue;
-                }
-                let session_id = session_id.and_then(|value| {
-                    let trimmed = value.trim();
-                    if trimmed.is_empty() {
-                        None
-                    } else {
-                        Some(trimmed.to_string())
-                    }
-                });
-                let profile_id = profile_id.and_then(|value| {
-                    let trimmed = value.trim();
-                    if trimmed.is_empty() {
-                        None
-                    } else {
-                        Some(trimmed.to_string())
-                    }
-                });
-                let locale = locale.and_then(|value| {
-                    let trimmed = value.trim();
-                    if trimmed.is_empty() {
-                        None
-                    } else {
-                        Some(trimmed.to_string())
-                    }
-                });
-                let mut effective_session_id = session_id.clone();
-                let profile = profile_id.as_ref().and_then(|id| profiles.get(id));
-                if effective_session_id.is_none() {
-                    if let Some(profile) = profile {
-                        let value = profile.session.trim();
-                        if !value.is_empty() {
-                            effective_session_id = Some(value.to_string());
-                        }
+                let later = Selection::new(command, env, session_id, profile_id, locale);
+                match self.auth.as_mut() {
+                    Some(auth) if auth.authenticated && auth.launch.is_none() => {
+                        let selection = std::mem::take(&mut auth.selection).overlay(later);
+                        self.start_session(selection)?;
                     }
+                    Some(_) => self.send_error("pam_error", "auth in progress")?,
+                    None => self.send_error("pam_error", "no authenticated session")?,
                 }
-                let mut cmd = if !command.is_empty() {
-                    command
-                } else if let Some(id) = effective_session_id.as_ref() {
-                    sessions.get(id).cloned().unwrap_or_default()
-                } else {
-                    Vec::new()
-                };
-                if cmd.is_empty() {
-                    cmd = default_command(&mut log);
-                }
+            }
+            _ if self.auth.is_some() => {
+                self.send_error("pam_error", "auth in progress")?;
+            }
+            protocol::UiRequest::Auth {
+                username,
+                command,
+                env,
+                session_id,
+                profile_id,
+                locale,
+                defer_start,
+            } => {
+                let selection = Selection::new(command, env, session_id, profile_id, locale);
+                self.begin_auth(username, selection, defer_start)?;
+            }
+        }
+        Ok(Flow::Continue)
+    }
 
-                let mut env_map = env;
-                if let Some(profile) = profile {
-                    for (key, value) in profile.env.iter() {
-                        env_map.insert(key.clone(), value.clone());
-                    }
-                }
-                if let Some(locale) = locale.as_ref() {
-                    env_map.insert("LANG".to_string(), locale.clone());
-                    env_map.insert("LC_ALL".to_string(), locale.clone());
+    fn handle_prompt_response(&mut self, id: u64, response: Option<Secret>) -> Result<()> {
+        let Some(auth) = self.auth.as_mut() else {
+            return self.send_error("pam_error", "no active prompt");
+        };
+        match auth.pending_prompt.as_ref().map(|prompt| prompt.id) {
+            Some(pending) if pending == id => {
+                auth.pending_prompt = None;
+                if let Err(err) = auth.conversation.respond(response) {
+                    return self.fail_auth(err);
                 }
-                let env_vec = build_env(env_map);
-                let mut prompt_id = 0u64;
-                let stdout_for_prompt = Rc::clone(&stdout);
-                let stdout_for_wait = Rc::clone(&stdout);
-                let mut prompt_handler =
-                    |kind: greetd::AuthMessageType,
-                     message: &str|
-                     -> greetd::AuthResult<Option<String>> {
-                        match kind {
-                            greetd::AuthMessageType::Visible | greetd::AuthMessageType::Secret => {
-                                prompt_id += 1;
-                                let (kind_str, echo) = prompt_kind(kind);
-                                {
-                                    let mut out = stdout_for_prompt.borrow_mut();
-                                    send_response(
-                                        &mut *out,
-                                        protocol::BackendResponse::Prompt {
-                                            id: prompt_id,
-                                            kind: kind_str.to_string(),
-                                            message: message.to_string(),
-                                            echo,
-                                        },
-                                    )?;
-                                }
-                                let mut out = stdout_for_prompt.borrow_mut();
-                                wait_prompt_response(
-                                    prompt_id,
-                                    &mut stdin_lock,
-                                    stdin_fd,
-                                    &mut *out,
-                                    auth_timeout,
-                                )
-                            }
-                            greetd::AuthMessageType::Info | greetd::AuthMessageType::Error => {
-                                let kind_str = message_kind(kind);
-                                let mut out = stdout_for_prompt.borrow_mut();
-                                send_response(
-                                    &mut *out,
-                                    protocol::BackendResponse::Message {
-                                        kind: kind_str.to_string(),
-                                        message: message.to_string(),
-                                    },
-                                )?;
-                                Ok(None)
-                            }
-                        }
-                    };
-                let mut on_waiting = || {
-                    let _ =
-                        set_phase(&mut *stdout_for_wait.borrow_mut(), &current_phase, "waiting");
-                };
-                match greetd::authenticate_and_start(
+                self.refresh_deadline();
+                Ok(())
+            }
+            Some(_) => self.send_error("pam_error", format!("unexpected prompt id: {}", id)),
+            None => self.send_error("pam_error", "no active prompt"),
+        }
+    }
+
+    fn begin_auth(&mut self, username: String, selection: Selection, defer_start: bool) -> Result<()> {
+        self.auth_attempts += 1;
+        let attempt = self.auth_attempts;
+        let started = Instant::now();
+        self.log.event(
+            logging::Level::Info,
+            "request: auth",
+            logging::Fields {
+                attempt: Some(attempt),
+                user: Some(&username),
+                ..Default::default()
+            },
+        );
+        self.set_phase(Phase::Auth)?;
+        let username = username.trim().to_string();
+        if username.is_empty() {
+            self.set_phase(Phase::Error)?;
+            return self.send_error("pam_error", "username is required");
+        }
+        if let Some(retry_after) = self.rate_limit_retry_after(&username) {
+            self.log.event(
+                logging::Level::Warn,
+                &format!("auth rate limited; retry after {}s", retry_after),
+                logging::Fields {
+                    attempt: Some(attempt),
+                    user: Some(&username),
+                    ..Default::default()
+                },
+            );
+            self.audit(audit::Outcome {
+                user: &username,
+                session_id: selection.session_id.as_deref(),
+                result: greetd::AuthErrorCode::RateLimited.as_str(),
+                duration_ms: 0,
+            });
+            self.set_phase(Phase::Error)?;
+            return self.send_auth_error(&greetd::AuthError::rate_limited(retry_after));
+        }
+
+        let conversation = match greetd::Conversation::begin(&username, &mut self.log) {
+            Ok(conversation) => conversation,
+            Err(err) => {
+                self.report_auth_failure(
+                    &err,
+                    attempt,
                     &username,
-                    &cmd,
-                    &env_vec,
-                    &mut log,
-                    &mut prompt_handler,
-                    &mut on_waiting,
-                ) {
-                    Ok(()) => {
-                        log.log("auth success; start_session ok");
-                        persist_state_update(
-                            effective_session_id.as_deref(),
-                            profile_id.as_deref(),
-                            locale.as_deref(),
-                            &mut log,
-                        );
-                        set_phase(&mut *stdout.borrow_mut(), &current_phase, "success")?;
-                        send_response(&mut *stdout.borrow_mut(), protocol::BackendResponse::Success)?;
-                        wait_for_ack(&mut stdin_lock, &mut log)?;
-                        log.log(&format!(
-                            "auth attempt={} success in {}ms",
-                            auth_attempts,
-                            auth_started.elapsed().as_millis()
-                        ));
-                        return Ok(());
-                    }
-                    Err(err) => {
-                        log.log(&format!("auth failed: {}", err));
-                        log.log(&format!(
-                            "auth attempt={} failed in {}ms",
-                            auth_attempts,
-                            auth_started.elapsed().as_millis()
-                        ));
-                        if err.return_to_idle() {
-                            send_error(
-                                &mut *stdout.borrow_mut(),
-                                err.code().as_str(),
-                                err.message(),
-                            )?;
-                            set_phase(&mut *stdout.borrow_mut(), &current_phase, "idle")?;
-                        } else {
-                            set_phase(&mut *stdout.borrow_mut(), &current_phase, "error")?;
-                            send_error(
-                                &mut *stdout.borrow_mut(),
-                                err.code().as_str(),
-                                err.message(),
-                            )?;
-                        }
-                    }
-                }
+                    selection.session_id.as_deref(),
+                    started,
+                );
+                self.set_phase(Phase::Error)?;
+                return self.send_auth_error(&err);
             }
-            protocol::UiRequest::Start { command, env } => {
-                log.log(&format!(
-                    "request: start {:?} env_len={}",
-                    command,
-                    env.len()
-                ));
-                set_phase(&mut *stdout.borrow_mut(), &current_phase, "waiting")?;
-                send_error(
-                    &mut *stdout.borrow_mut(),
-                    "pam_error",
-                    format!("start not implemented: {:?}", command),
-                )?;
+        };
+        self.auth = Some(AuthSession {
+            conversation,
+            username,
+            attempt,
+            started,
+            deadline: None,
+            prompt_id: 0,
+            pending_prompt: None,
+            defer_start,
+            authenticated: false,
+            autologin: false,
+            selection,
+            launch: None,
+        });
+        self.refresh_deadline();
+        Ok(())
+    }
+
+    fn rate_limit_retry_after(&mut self, username: &str) -> Option<u64> {
+        let policy = &self.settings.rate_limit;
+        if !policy.enabled() {
+            return None;
+        }
+        let state = read_state(&mut self.log);
+        let record = state.auth_failures.get(username)?;
+        policy.retry_after(record, ratelimit::unix_now())
+    }
+
+    /// Counts a failed password check against `username` in the state file.
+    /// Cancellations, timeouts and PAM/system errors are not counted.
+    fn record_auth_failure(&mut self, username: &str, err: &greetd::AuthError) {
+        let policy = &self.settings.rate_limit;
+        if !policy.enabled() || !matches!(err.code(), greetd::AuthErrorCode::AuthFailed) {
+            return;
+        }
+        let now = ratelimit::unix_now();
+        let mut state = read_state(&mut self.log);
+        policy.prune(&mut state.auth_failures, now);
+        let record = state.auth_failures.entry(username.to_string()).or_default();
+        if let Some(delay) = policy.record_failure(record, now) {
+            self.log.event(
+                logging::Level::Warn,
+                &format!("locked out for {}s after {} failures", delay, record.failures),
+                logging::Fields {
+                    user: Some(username),
+                    ..Default::default()
+                },
+            );
+        }
+        write_state(&state, &mut self.log);
+    }
+
+    /// Appends an audit record; failures are logged, never fatal.
+    fn audit(&mut self, outcome: audit::Outcome<'_>) {
+        let Some(audit) = self.audit.as_ref() else {
+            return;
+        };
+        if let Err(err) = audit.append(outcome, ratelimit::unix_now()) {
+            self.log.error(&format!("audit: {:#}", err));
+        }
+    }
+
+    /// Tells the UI when `username` last logged in, before this success is
+    /// recorded.
+    fn send_last_login(&mut self, username: &str) -> Result<()> {
+        let Some(audit) = self.audit.as_ref() else {
+            return Ok(());
+        };
+        let last = match audit.last_login(username) {
+            Ok(last) => last,
+            Err(err) => {
+                self.log.error(&format!("audit: {:#}", err));
+                return Ok(());
             }
-            protocol::UiRequest::PromptResponse { .. } => {
-                send_error(&mut *stdout.borrow_mut(), "pam_error", "no active prompt")?;
+        };
+        self.send(protocol::BackendResponse::LastLogin {
+            previous_login: last.previous,
+            failed_attempts: last.failures,
+        })
+    }
+
+    fn clear_auth_failures(&mut self, username: &str) {
+        let mut state = read_state(&mut self.log);
+        if state.auth_failures.remove(username).is_some() {
+            write_state(&state, &mut self.log);
+        }
+    }
+
+    /// Answers `hello` with the versions and capabilities of this backend.
+    /// UIs older than `MIN_UI_VERSION` get `unsupported_version` for every
+    /// later request; newer ones are expected to speak `PROTOCOL_VERSION`.
+    fn handle_hello(&mut self, ui_version: u32) -> Result<()> {
+        self.log
+            .log(&format!("request: hello ui_version={}", ui_version));
+        let mut power_actions: Vec<String> = self.settings.power_actions.iter().cloned().collect();
+        power_actions.sort();
+        self.send(protocol::BackendResponse::Hello {
+            protocol_version: protocol::PROTOCOL_VERSION,
+            min_ui_version: protocol::MIN_UI_VERSION,
+            max_ui_version: protocol::MAX_UI_VERSION,
+            capabilities: self.capabilities(),
+            power_actions,
+        })?;
+        self.ui_rejected = ui_version < protocol::MIN_UI_VERSION;
+        if self.ui_rejected {
+            self.log.warn(&format!(
+                "rejecting ui_version={} (supported {}..={})",
+                ui_version,
+                protocol::MIN_UI_VERSION,
+                protocol::MAX_UI_VERSION
+            ));
+            return self.send_error(
+                "unsupported_version",
+                format!(
+                    "ui protocol {} is too old; backend supports {}..={}",
+                    ui_version,
+                    protocol::MIN_UI_VERSION,
+                    protocol::MAX_UI_VERSION
+                ),
+            );
+        }
+        if ui_version > protocol::MAX_UI_VERSION {
+            self.log.log(&format!(
+                "ui_version={} is newer than backend; using protocol {}",
+                ui_version,
+                protocol::PROTOCOL_VERSION
+            ));
+        }
+        if !self.settings.users.is_empty() {
+            self.send(protocol::BackendResponse::Users {
+                users: self.settings.users.clone(),
+            })?;
+        }
+        self.send_state()?;
+        if let Some(rule) = self.autologin_rule.take() {
+            self.autologin = rule.start(&mut self.log);
+        }
+        self.send_autologin()
+    }
+
+    /// Sends the countdown tick, if one is running.
+    fn send_autologin(&mut self) -> Result<()> {
+        let Some(countdown) = self.autologin.as_ref() else {
+            return Ok(());
+        };
+        let resp = protocol::BackendResponse::Autologin {
+            username: countdown.user.clone(),
+            remaining_secs: countdown.remaining_secs(),
+        };
+        self.send(resp)
+    }
+
+    /// Ticks the countdown and starts the login once it runs out.
+    fn advance_autologin(&mut self) -> Result<()> {
+        let Some(countdown) = self.autologin.as_mut() else {
+            return Ok(());
+        };
+        if !countdown.expired() {
+            if countdown.tick() {
+                self.send_autologin()?;
             }
-            protocol::UiRequest::Cancel => {
-                send_error(
-                    &mut *stdout.borrow_mut(),
-                    "pam_error",
-                    "no active auth session",
-                )?;
+            return Ok(());
+        }
+        let Some(countdown) = self.autologin.take() else {
+            return Ok(());
+        };
+        if self.auth.is_some() || self.phase != Phase::Idle {
+            self.log.log("autologin: ui is busy; cancelled");
+            return self.send(protocol::BackendResponse::AutologinCancelled);
+        }
+        let remembered = read_state(&mut self.log).remembered(&countdown.user);
+        let selection = Selection {
+            session_id: remembered.session_id,
+            profile_id: remembered.profile_id,
+            locale: remembered.locale,
+            ..Default::default()
+        };
+        self.log.log("autologin: starting");
+        self.begin_auth(countdown.user, selection, false)?;
+        if let Some(auth) = self.auth.as_mut() {
+            auth.autologin = true;
+        }
+        Ok(())
+    }
+
+    fn cancel_autologin(&mut self) -> Result<()> {
+        if self.autologin.take().is_none() {
+            return Ok(());
+        }
+        self.log.log("autologin: cancelled by ui");
+        self.send(protocol::BackendResponse::AutologinCancelled)
+    }
+
+    fn capabilities(&self) -> Vec<String> {
+        let mut caps = vec!["cancel", "ping", "two_phase_start", "session_select"];
+        if !self.settings.profiles.is_empty() {
+            caps.push("profiles");
+        }
+        if !self.settings.power_actions.is_empty() {
+            caps.push("power");
+        }
+        if self.listener.is_some() {
+            caps.push("reattach");
+        }
+        if self.settings.rate_limit.enabled() {
+            caps.push("rate_limit");
+        }
+        if self.audit.is_some() {
+            caps.push("last_login");
+        }
+        if !self.settings.users.is_empty() {
+            caps.push("users");
+            caps.push("user_selection");
+        }
+        if self.autologin_rule.is_some() || self.autologin.is_some() {
+            caps.push("autologin");
+        }
+        caps.into_iter().map(str::to_string).collect()
+    }
+
+    /// Answers `select_user` with what the user started last time. Ids that
+    /// are no longer offered are left out.
+    fn handle_select_user(&mut self, username: String) -> Result<()> {
+        let username = username.trim().to_string();
+        self.log.event(
+            logging::Level::Debug,
+            "request: select_user",
+            logging::Fields {
+                user: Some(&username),
+                ..Default::default()
+            },
+        );
+        let remembered = read_state(&mut self.log).remembered(&username);
+        let sessions = &self.settings.sessions;
+        let profiles = &self.settings.profiles;
+        self.send(protocol::BackendResponse::UserSelection {
+            session_id: remembered
+                .session_id
+                .filter(|id| sessions.is_empty() || sessions.contains_key(id)),
+            profile_id: remembered
+                .profile_id
+                .filter(|id| profiles.is_empty() || profiles.contains_key(id)),
+            locale: remembered.locale,
+            username,
+        })
+    }
+
+    fn handle_power(&mut self, action: String) -> Result<()> {
+        let action = action.trim().to_ascii_lowercase();
+        self.log.log(&format!("request: power {}", action));
+        if action.is_empty() {
+            return self.send_error("power_error", "power action missing");
+        }
+        if !self.settings.power_allowed_states.contains(&self.phase) {
+            let message = format!("power action not allowed during {}", self.phase);
+            return self.send_error("power_denied", message);
+        }
+        if !self.settings.power_actions.contains(&action) {
+            return self.send_error(
+                "power_denied",
+                format!("power action not allowed: {}", action),
+            );
+        }
+        match request_power_action(&action) {
+            Ok(()) => {
+                self.log.log(&format!("power action dispatched: {}", action));
+                Ok(())
             }
-            protocol::UiRequest::Ack { .. } => {
-                send_error(&mut *stdout.borrow_mut(), "pam_error", "unexpected ack")?;
+            Err(err) => {
+                let code = power_error_code(&err);
+                self.send_error(code, err)
             }
-            protocol::UiRequest::Power { action } => {
-                let action = action.trim().to_ascii_lowercase();
-                log.log(&format!("request: power {}", action));
-                if action.is_empty() {
-                    send_error(&mut *stdout.borrow_mut(), "power_error", "power action missing")?;
-                    continue;
-                }
-                if !power_allowed_states.contains(current_phase.get()) {
-                    send_error(
-                        &mut *stdout.borrow_mut(),
-                        "power_denied",
-                        format!("power action not allowed during {}", current_phase.get()),
-                    )?;
-                    continue;
-                }
-                if !power_actions.contains(&action) {
-                    send_error(
-                        &mut *stdout.borrow_mut(),
-                        "power_denied",
-                        format!("power action not allowed: {}", action),
-                    )?;
-                    continue;
-                }
-                match request_power_action(&action) {
-                    Ok(()) => {
-                        log.log(&format!("power action dispatched: {}", action));
+        }
+    }
+}
+
+struct Options {
+    /// Serve UIs on this socket instead of stdin/stdout.
+    listen: Option<PathBuf>,
+}
+
+fn parse_args() -> Result<Options> {
+    let mut options = Options { listen: None };
+    let mut args = env::args().skip(1).peekable();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                print_usage();
+                std::process::exit(0);
+            }
+            "--listen" => {
+                let path = match args.peek() {
+                    Some(next) if !next.starts_with("--") => PathBuf::from(args.next().unwrap_or_default()),
+                    _ => transport::default_socket_path(),
+                };
+                options.listen = Some(path);
+            }
+            "--print-schema" => {
+                println!("{}", serde_json::to_string_pretty(&protocol::schema())?);
+                std::process::exit(0);
+            }
+            "--verify-audit" => {
+                let path = match args.peek() {
+                    Some(next) if !next.starts_with("--") => PathBuf::from(args.next().unwrap_or_default()),
+                    _ => audit_path(),
+                };
+                match audit::AuditLog::open_existing(path.clone()).and_then(|audit| audit.verify()) {
+                    Ok(count) => {
+                        println!("{}: {} records, chain intact", path.display(), count);
+                        std::process::exit(0);
                     }
                     Err(err) => {
-                        let code = power_error_code(&err);
-                        send_error(&mut *stdout.borrow_mut(), code, err)?;
+                        eprintln!("{}: {:#}", path.display(), err);
+                        std::process::exit(1);
                     }
                 }
             }
+            _ => anyhow::bail!("unknown option: {}", arg),
         }
     }
+    Ok(options)
+}
 
-    Ok(())
+fn print_usage() {
+    println!(
+        "Usage: tiss-greetd-backend [options]\n\
+      --listen [path]          Serve the UI on a Unix socket (default:\n\
+                               $XDG_RUNTIME_DIR/tiss-greetd/backend.sock)\n\
+      --print-schema           Print the UI protocol JSON Schema and exit\n\
+      --verify-audit [path]    Check the audit log hash chain and exit\n\
+  -h, --help                   Show this help\n\
+\n\
+Without --listen the UI protocol runs over stdin/stdout."
+    );
+}
+
+fn main() -> Result<()> {
+    let options = parse_args()?;
+    let mut log = logging::Logger::new("backend");
+
+    log.log("backend start");
+    match hardening::apply(hardening::root_allowed()) {
+        Ok(report) => log.log(&report.to_string()),
+        Err(err) => {
+            log.error(&format!("{:#}", err));
+            return Err(err);
+        }
+    }
+    if log.privacy() != logging::Privacy::Off {
+        let key_path = state_path().with_file_name("log-hash.key");
+        let key = key_path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| logging::load_key(&key_path));
+        match key {
+            Ok(key) => log.set_hash_key(key),
+            Err(err) => log.warn(&format!(
+                "failed to load {}: {}; user hashes change on restart",
+                key_path.display(),
+                err
+            )),
+        }
+    }
+    let (ui, listener) = match options.listen {
+        Some(path) => {
+            let listener = transport::UiListener::bind(&path)?;
+            log.log(&format!("listening on {}", listener.path().display()));
+            (None, Some(listener))
+        }
+        None => (Some(transport::UiConnection::stdio()), None),
+    };
+    let settings = Settings::load(&mut log);
+    migrate_state(&mut log);
+    let autologin_rule = autologin::Rule::load(&mut log).filter(|rule| !rule.used(&mut log));
+    let audit = match audit::AuditLog::open(audit_path()) {
+        Ok(audit) => Some(audit),
+        Err(err) => {
+            log.error(&format!("audit: {:#}; outcomes are not recorded", err));
+            None
+        }
+    };
+    if sandbox::enabled() {
+        let mut paths = sandbox::Paths::new();
+        paths.writable.push(logging::default_log_dir());
+        if let Some(dir) = state_path().parent() {
+            if let Err(err) = fs::create_dir_all(dir) {
+                log.error(&format!("failed to create state dir {}: {}", dir.display(), err));
+            }
+            paths.writable.push(dir.to_path_buf());
+        }
+        if let Some(dir) = listener.as_ref().and_then(|listener| listener.path().parent()) {
+            paths.writable.push(dir.to_path_buf());
+        }
+        // The marker is written when the countdown starts, after this.
+        if let Some(dir) = autologin_rule.as_ref().and_then(|rule| rule.marker.parent()) {
+            if let Err(err) = fs::create_dir_all(dir) {
+                log.error(&format!("failed to create autologin marker dir {}: {}", dir.display(), err));
+            }
+            paths.writable.push(dir.to_path_buf());
+        }
+        sandbox::apply(&paths, &mut log);
+    }
+    let mut backend = Backend {
+        ui,
+        listener,
+        log,
+        settings,
+        phase: Phase::Idle,
+        auth_attempts: 0,
+        auth: None,
+        completed: None,
+        audit,
+        ui_rejected: false,
+        autologin_rule,
+        autologin: None,
+    };
+    backend.send_state()?;
+    backend.run()
 }
@@ -0,0 +1,126 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tiss_greetd_config::state::FailureRecord;
+use tiss_greetd_logging::Logger;
+
+/// Brute-force policy, from `TISS_GREETD_RATE_LIMIT_JSON`. Off unless that
+/// is set (the launcher sets it for a `[rate_limit]` section) and valid; keys
+/// it leaves out take the defaults below.
+///
+/// Up to `max_attempts` failures are allowed within `window_secs`. After
+/// that every further failure locks the user out for `backoff_secs`, doubling
+/// per failure up to `max_backoff_secs`. A user with no failures for
+/// `cooldown_secs` starts from zero again. `enabled = false` or
+/// `max_attempts = 0` disables it.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Policy {
+    pub enabled: bool,
+    pub max_attempts: u32,
+    pub window_secs: u64,
+    pub backoff_secs: u64,
+    pub max_backoff_secs: u64,
+    pub cooldown_secs: u64,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy {
+            enabled: true,
+            max_attempts: 5,
+            window_secs: 300,
+            backoff_secs: 30,
+            max_backoff_secs: 3600,
+            cooldown_secs: 900,
+        }
+    }
+}
+
+impl Policy {
+    pub fn load(log: &mut Logger) -> Self {
+        let off = Policy {
+            enabled: false,
+            ..Policy::default()
+        };
+        let raw = env::var("TISS_GREETD_RATE_LIMIT_JSON").unwrap_or_default();
+        if raw.trim().is_empty() {
+            return off;
+        }
+        match serde_json::from_str(&raw) {
+            Ok(policy) => policy,
+            Err(err) => {
+                log.warn(&format!(
+                    "invalid TISS_GREETD_RATE_LIMIT_JSON: {}; rate limiting is off",
+                    err
+                ));
+                off
+            }
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled && self.max_attempts > 0
+    }
+
+    /// Seconds until `record` may try again, if it is locked out at `now`.
+    pub fn retry_after(&self, record: &FailureRecord, now: u64) -> Option<u64> {
+        if !self.enabled() || record.locked_until <= now {
+            return None;
+        }
+        Some(record.locked_until - now)
+    }
+
+    /// Counts a failure at `now`. Returns the lockout length it triggered.
+    pub fn record_failure(&self, record: &mut FailureRecord, now: u64) -> Option<u64> {
+        if !self.enabled() {
+            return None;
+        }
+        let cooled_down = now.saturating_sub(quiet_since(record)) >= self.cooldown_secs;
+        let window_over = record.failures < self.max_attempts
+            && now.saturating_sub(record.first_failure) >= self.window_secs;
+        if record.failures == 0 || cooled_down || window_over {
+            *record = FailureRecord {
+                failures: 0,
+                first_failure: now,
+                last_failure: now,
+                locked_until: 0,
+            };
+        }
+        record.failures += 1;
+        record.last_failure = now;
+        if record.failures < self.max_attempts {
+            return None;
+        }
+        let exponent = (record.failures - self.max_attempts).min(32);
+        let delay = self
+            .backoff_secs
+            .saturating_mul(1u64 << exponent)
+            .min(self.max_backoff_secs);
+        record.locked_until = now + delay;
+        Some(delay)
+    }
+
+    /// Drops records that no longer influence anything.
+    pub fn prune(&self, records: &mut BTreeMap<String, FailureRecord>, now: u64) {
+        records.retain(|_, record| {
+            record.locked_until > now
+                || now.saturating_sub(quiet_since(record)) < self.cooldown_secs
+        });
+    }
+}
+
+/// Cooldown runs from the later of the last failure and the lockout end, so
+/// long lockouts do not reset the backoff on their own.
+fn quiet_since(record: &FailureRecord) -> u64 {
+    record.last_failure.max(record.locked_until)
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
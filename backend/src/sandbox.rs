@@ -0,0 +1,208 @@
+use landlock::{
+    path_beneath_rules, Access, AccessFs, Ruleset, RulesetAttr, RulesetCreatedAttr, RulesetStatus,
+    ABI,
+};
+use seccompiler::{BpfProgram, SeccompAction, SeccompFilter, TargetArch};
+use std::collections::BTreeMap;
+use std::env;
+use std::path::PathBuf;
+
+use tiss_greetd_logging::Logger;
+
+/// `TISS_GREETD_SANDBOX=1` (from `[security] sandbox`) turns the sandbox on.
+pub fn enabled() -> bool {
+    matches!(
+        env::var("TISS_GREETD_SANDBOX").unwrap_or_default().trim(),
+        "1" | "true" | "yes"
+    )
+}
+
+/// Paths the backend still needs once sandboxed. Sockets (greetd, system
+/// D-Bus) are reached with `connect`, which Landlock does not restrict.
+pub struct Paths {
+    /// Full access: state dir, log dir, UI socket dir.
+    pub writable: Vec<PathBuf>,
+    /// Read-only files.
+    pub readable: Vec<PathBuf>,
+}
+
+impl Paths {
+    pub fn new() -> Self {
+        Paths {
+            writable: Vec::new(),
+            // zbus reads the machine id when talking to the system bus.
+            readable: vec![
+                PathBuf::from("/etc/machine-id"),
+                PathBuf::from("/var/lib/dbus/machine-id"),
+            ],
+        }
+    }
+}
+
+/// Applies Landlock, then seccomp. Either one that the kernel cannot enforce
+/// is logged and skipped; the backend keeps running.
+pub fn apply(paths: &Paths, log: &mut Logger) {
+    let landlock = match apply_landlock(paths) {
+        Ok(RulesetStatus::FullyEnforced) => "enforced",
+        Ok(RulesetStatus::PartiallyEnforced) => "partial",
+        Ok(RulesetStatus::NotEnforced) => "unsupported",
+        Err(err) => {
+            log.warn(&format!("sandbox: landlock failed: {}", err));
+            "failed"
+        }
+    };
+    let seccomp = match apply_seccomp() {
+        Ok(()) => "enforced",
+        Err(err) => {
+            log.warn(&format!("sandbox: seccomp failed: {}", err));
+            "failed"
+        }
+    };
+    log.log(&format!(
+        "sandbox: landlock={} seccomp={} writable={:?}",
+        landlock, seccomp, paths.writable
+    ));
+}
+
+fn apply_landlock(paths: &Paths) -> Result<RulesetStatus, landlock::RulesetError> {
+    let abi = ABI::V3;
+    let readable = paths.readable.iter().filter(|path| path.exists());
+    let status = Ruleset::default()
+        .handle_access(AccessFs::from_all(abi))?
+        .create()?
+        .add_rules(path_beneath_rules(&paths.writable, AccessFs::from_all(abi)))?
+        .add_rules(path_beneath_rules(readable, AccessFs::from_read(abi)))?
+        .restrict_self()?;
+    Ok(status.ruleset)
+}
+
+/// Syscalls the backend, std and zbus' worker threads use. Anything else
+/// fails with `EPERM` rather than killing the greeter.
+const ALLOWED_SYSCALLS: &[i64] = &[
+    libc::SYS_read,
+    libc::SYS_readv,
+    libc::SYS_pread64,
+    libc::SYS_write,
+    libc::SYS_writev,
+    libc::SYS_pwrite64,
+    libc::SYS_close,
+    libc::SYS_lseek,
+    libc::SYS_openat,
+    libc::SYS_newfstatat,
+    libc::SYS_fstat,
+    libc::SYS_statx,
+    libc::SYS_getdents64,
+    libc::SYS_readlinkat,
+    libc::SYS_fcntl,
+    libc::SYS_ioctl,
+    libc::SYS_fsync,
+    libc::SYS_fdatasync,
+    libc::SYS_ftruncate,
+    libc::SYS_mkdirat,
+    libc::SYS_unlinkat,
+    libc::SYS_renameat,
+    libc::SYS_renameat2,
+    libc::SYS_fchmod,
+    libc::SYS_fchmodat,
+    libc::SYS_dup,
+    libc::SYS_dup3,
+    libc::SYS_pipe2,
+    libc::SYS_ppoll,
+    libc::SYS_pselect6,
+    libc::SYS_epoll_create1,
+    libc::SYS_epoll_ctl,
+    libc::SYS_epoll_pwait,
+    libc::SYS_eventfd2,
+    libc::SYS_socket,
+    libc::SYS_socketpair,
+    libc::SYS_connect,
+    libc::SYS_accept4,
+    libc::SYS_shutdown,
+    libc::SYS_sendto,
+    libc::SYS_sendmsg,
+    libc::SYS_recvfrom,
+    libc::SYS_recvmsg,
+    libc::SYS_getsockopt,
+    libc::SYS_setsockopt,
+    libc::SYS_getsockname,
+    libc::SYS_getpeername,
+    libc::SYS_mmap,
+    libc::SYS_munmap,
+    libc::SYS_mremap,
+    libc::SYS_mprotect,
+    libc::SYS_madvise,
+    libc::SYS_brk,
+    libc::SYS_mlock,
+    libc::SYS_munlock,
+    libc::SYS_futex,
+    libc::SYS_set_robust_list,
+    libc::SYS_rseq,
+    libc::SYS_clone,
+    libc::SYS_clone3,
+    libc::SYS_sched_yield,
+    libc::SYS_sched_getaffinity,
+    libc::SYS_nanosleep,
+    libc::SYS_clock_nanosleep,
+    libc::SYS_clock_gettime,
+    libc::SYS_gettimeofday,
+    libc::SYS_getrandom,
+    libc::SYS_rt_sigaction,
+    libc::SYS_rt_sigprocmask,
+    libc::SYS_rt_sigreturn,
+    libc::SYS_sigaltstack,
+    libc::SYS_tgkill,
+    libc::SYS_getpid,
+    libc::SYS_gettid,
+    libc::SYS_getuid,
+    libc::SYS_geteuid,
+    libc::SYS_getgid,
+    libc::SYS_getegid,
+    libc::SYS_uname,
+    libc::SYS_prctl,
+    libc::SYS_prlimit64,
+    libc::SYS_exit,
+    libc::SYS_exit_group,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_poll,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_epoll_wait,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_open,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_stat,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_lstat,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_access,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_readlink,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_mkdir,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_unlink,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_rename,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_chmod,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_arch_prctl,
+];
+
+fn apply_seccomp() -> Result<(), String> {
+    let arch = TargetArch::try_from(env::consts::ARCH).map_err(|err| err.to_string())?;
+    let rules: BTreeMap<i64, Vec<seccompiler::SeccompRule>> = ALLOWED_SYSCALLS
+        .iter()
+        .map(|syscall| (*syscall, Vec::new()))
+        .collect();
+    let filter = SeccompFilter::new(
+        rules,
+        SeccompAction::Errno(libc::EPERM as u32),
+        SeccompAction::Allow,
+        arch,
+    )
+    .map_err(|err| err.to_string())?;
+    let program: BpfProgram = filter
+        .try_into()
+        .map_err(|err: seccompiler::BackendError| err.to_string())?;
+    seccompiler::apply_filter_all_threads(&program).map_err(|err| err.to_string())
+}
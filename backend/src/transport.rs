@@ -0,0 +1,305 @@
+use anyhow::{bail, Context, Result};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tiss_greetd_ipc::Secret;
+use zeroize::Zeroize;
+
+/// Longest line a UI may send. Requests are small; a peer that never sends a
+/// newline is cut off here instead of growing the buffer forever.
+pub const MAX_LINE_BYTES: usize = 64 * 1024;
+
+/// Non-blocking line splitter over a raw UI fd.
+///
+/// `std::io::Stdin` keeps its own buffer, which would hide pending lines from
+/// `poll`; reading the fd directly keeps poll readiness and buffered data in
+/// sync. Lines can carry passwords, so every byte that leaves the buffer (or
+/// is left behind by a reallocation) is wiped.
+pub struct LineReader {
+    file: File,
+    buf: Vec<u8>,
+    overflowed: bool,
+}
+
+impl LineReader {
+    pub fn stdin() -> Self {
+        LineReader::new(unsafe { File::from_raw_fd(libc::STDIN_FILENO) })
+    }
+
+    fn new(file: File) -> Self {
+        LineReader {
+            file,
+            buf: Vec::new(),
+            overflowed: false,
+        }
+    }
+
+    pub fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+
+    /// Reads whatever is available; returns false on EOF, and also once an
+    /// unfinished line exceeds [`MAX_LINE_BYTES`] (see [`Self::overflowed`]).
+    pub fn fill(&mut self) -> Result<bool> {
+        let mut chunk = [0u8; 4096];
+        let read = loop {
+            match self.file.read(&mut chunk) {
+                Ok(read) => break read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) if err.kind() == io::ErrorKind::ConnectionReset => break 0,
+                Err(err) => return Err(err).context("read line"),
+            }
+        };
+        self.append(&chunk[..read]);
+        chunk.zeroize();
+        let partial = match self.buf.iter().rposition(|byte| *byte == b'\n') {
+            Some(pos) => self.buf.len() - (pos + 1),
+            None => self.buf.len(),
+        };
+        if partial > MAX_LINE_BYTES {
+            self.buf.zeroize();
+            self.overflowed = true;
+            return Ok(false);
+        }
+        Ok(read > 0)
+    }
+
+    /// Whether `fill` gave up on an overlong line.
+    pub fn overflowed(&self) -> bool {
+        self.overflowed
+    }
+
+    /// Grows the buffer by hand so the old allocation can be wiped.
+    fn append(&mut self, data: &[u8]) {
+        let needed = self.buf.len() + data.len();
+        if needed > self.buf.capacity() {
+            let mut grown = Vec::with_capacity(needed.max(self.buf.capacity() * 2).max(4096));
+            tiss_greetd_ipc::lock_capacity(&mut grown);
+            grown.extend_from_slice(&self.buf);
+            self.buf.zeroize();
+            self.buf = grown;
+        }
+        self.buf.extend_from_slice(data);
+    }
+
+    /// Next complete line, without the newline.
+    pub fn next_line(&mut self) -> Option<Secret> {
+        let pos = self.buf.iter().position(|byte| *byte == b'\n')?;
+        let line = Secret::from_utf8(&self.buf[..pos]);
+        let rest = self.buf.len() - (pos + 1);
+        self.buf.copy_within(pos + 1.., 0);
+        self.buf[rest..].zeroize();
+        self.buf.truncate(rest);
+        Some(line)
+    }
+}
+
+impl Drop for LineReader {
+    fn drop(&mut self) {
+        self.buf.zeroize();
+    }
+}
+
+/// A connected UI: line input plus the matching output stream.
+pub struct UiConnection {
+    pub reader: LineReader,
+    pub writer: Box<dyn Write>,
+}
+
+impl UiConnection {
+    pub fn stdio() -> Self {
+        UiConnection {
+            reader: LineReader::stdin(),
+            writer: Box::new(io::stdout()),
+        }
+    }
+
+    fn socket(stream: UnixStream) -> Result<Self> {
+        let writer = stream.try_clone().context("clone ui socket")?;
+        Ok(UiConnection {
+            reader: LineReader::new(File::from(OwnedFd::from(stream))),
+            writer: Box::new(writer),
+        })
+    }
+}
+
+/// Someone else's directory, or one others can write to, would let them
+/// swap the socket for their own.
+fn check_socket_dir(dir: &Path, meta: &fs::Metadata) -> Result<()> {
+    if !meta.is_dir() {
+        bail!("socket dir {} is not a directory", dir.display());
+    }
+    let euid = unsafe { libc::geteuid() };
+    if meta.uid() != euid {
+        bail!(
+            "socket dir {} is owned by uid {}, not {}",
+            dir.display(),
+            meta.uid(),
+            euid
+        );
+    }
+    if meta.mode() & 0o022 != 0 {
+        bail!(
+            "socket dir {} is writable by group or others (mode {:o})",
+            dir.display(),
+            meta.mode() & 0o777
+        );
+    }
+    Ok(())
+}
+
+/// Listening socket for UIs that attach (and reattach) to a running backend.
+pub struct UiListener {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl UiListener {
+    /// Binds `path` with mode 0600. A missing directory is created 0700; an
+    /// existing one is left as it is, but must be ours and not writable by
+    /// group or others. A socket that still accepts connections belongs to a
+    /// live backend and is an error; a dead one is replaced.
+    pub fn bind(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            match fs::metadata(parent) {
+                Ok(meta) => check_socket_dir(parent, &meta)?,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                    fs::DirBuilder::new()
+                        .recursive(true)
+                        .mode(0o700)
+                        .create(parent)
+                        .with_context(|| format!("create socket dir {}", parent.display()))?;
+                }
+                Err(err) => {
+                    return Err(err).with_context(|| format!("stat socket dir {}", parent.display()));
+                }
+            }
+        }
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                bail!("backend already listening on {}", path.display());
+            }
+            fs::remove_file(path).with_context(|| format!("remove stale {}", path.display()))?;
+        }
+        let listener =
+            UnixListener::bind(path).with_context(|| format!("bind {}", path.display()))?;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+            .with_context(|| format!("chmod {}", path.display()))?;
+        Ok(UiListener {
+            listener,
+            path: path.to_path_buf(),
+        })
+    }
+
+    pub fn as_raw_fd(&self) -> RawFd {
+        self.listener.as_raw_fd()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Accepts one pending client. Peers running as another uid are refused.
+    pub fn accept(&self) -> Result<UiConnection> {
+        let (stream, _) = self.listener.accept().context("accept ui")?;
+        let uid = peer_uid(&stream)?;
+        let own = unsafe { libc::geteuid() };
+        if uid != own {
+            bail!("rejected ui peer uid={} (backend uid={})", uid, own);
+        }
+        UiConnection::socket(stream)
+    }
+}
+
+impl Drop for UiListener {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn peer_uid(stream: &UnixStream) -> Result<libc::uid_t> {
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let res = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if res < 0 {
+        return Err(io::Error::last_os_error()).context("SO_PEERCRED");
+    }
+    Ok(cred.uid)
+}
+
+/// Default socket path: `$XDG_RUNTIME_DIR/tiss-greetd/backend.sock`.
+pub fn default_socket_path() -> PathBuf {
+    if let Ok(path) = std::env::var("TISS_GREETD_BACKEND_SOCKET") {
+        if !path.trim().is_empty() {
+            return PathBuf::from(path);
+        }
+    }
+    if let Ok(dir) = std::env::var("XDG_RUNTIME_DIR") {
+        if !dir.trim().is_empty() {
+            return PathBuf::from(dir).join("tiss-greetd/backend.sock");
+        }
+    }
+    PathBuf::from(format!("/tmp/tiss-greetd-{}/backend.sock", unsafe {
+        libc::geteuid()
+    }))
+}
+
+/// Polls every present fd for input. Returns one readiness flag per entry;
+/// all false means the timeout expired.
+pub fn poll_inputs(fds: &[Option<RawFd>], timeout: Option<Duration>) -> Result<Vec<bool>> {
+    let millis = match timeout {
+        Some(timeout) => timeout
+            .as_millis()
+            .min(i32::MAX as u128)
+            .try_into()
+            .unwrap_or(i32::MAX),
+        None => -1,
+    };
+    let mut pollfds: Vec<libc::pollfd> = fds
+        .iter()
+        .map(|fd| libc::pollfd {
+            fd: fd.unwrap_or(-1),
+            events: libc::POLLIN,
+            revents: 0,
+        })
+        .collect();
+    loop {
+        let res = unsafe {
+            libc::poll(
+                pollfds.as_mut_ptr(),
+                pollfds.len() as libc::nfds_t,
+                millis,
+            )
+        };
+        if res < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err).context("poll inputs");
+        }
+        break;
+    }
+    Ok(pollfds
+        .iter()
+        .map(|pollfd| {
+            pollfd.fd >= 0 && pollfd.revents & (libc::POLLIN | libc::POLLHUP | libc::POLLERR) != 0
+        })
+        .collect())
+}
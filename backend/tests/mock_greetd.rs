@@ -0,0 +1,1217 @@
+//! End-to-end runs of the real backend against `tiss-greetd-mock`.
+
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use std::sync::{Arc, Mutex};
+use tiss_greetd_mock::{Scenario, Server, StartedSession};
+
+struct Harness {
+    child: Child,
+    stdin: ChildStdin,
+    lines: mpsc::Receiver<Value>,
+    dir: PathBuf,
+    /// What the mock was asked to start.
+    started: Arc<Mutex<Vec<StartedSession>>>,
+}
+
+/// Starts the mock with `scenario` in a fresh temp dir; returns the dir and
+/// the sessions the mock gets asked to start.
+fn start_mock(name: &str, scenario: &str) -> (PathBuf, Arc<Mutex<Vec<StartedSession>>>) {
+    let dir = std::env::temp_dir().join(format!("tiss-greetd-e2e-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let scenario_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("../mock/scenarios")
+        .join(scenario);
+    let scenario = Scenario::load(&scenario_path).unwrap();
+    let server = Server::bind(dir.join("greetd.sock"), scenario).unwrap();
+    let started = server.started();
+    server.spawn();
+    (dir, started)
+}
+
+/// Starts the mock and returns a backend command pointed at it.
+fn backend_command(name: &str, scenario: &str) -> (PathBuf, Command) {
+    let (dir, _) = start_mock(name, scenario);
+    let command = backend_in(&dir);
+    (dir, command)
+}
+
+/// A backend command using the mock, state and log dir under `dir`.
+fn backend_in(dir: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_tiss-greetd-backend"));
+    command
+        .env_clear()
+        .env("GREETD_SOCK", dir.join("greetd.sock"))
+        .env("TISS_GREETD_LOG_DIR", dir.join("log"))
+        .env("XDG_STATE_HOME", dir.join("state"))
+        .env("TISS_GREETD_AUTOLOGIN_MARKER", dir.join("run/autologin"))
+        .env("TISS_GREETD_ALLOW_ROOT", "1");
+    command
+}
+
+impl Harness {
+    fn start(name: &str, scenario: &str) -> Harness {
+        Harness::start_with(name, scenario, &[])
+    }
+
+    fn start_with(name: &str, scenario: &str, env: &[(&str, &str)]) -> Harness {
+        let (dir, started) = start_mock(name, scenario);
+        Harness::spawn(dir, started, env)
+    }
+
+    /// Like `start_with`, with `state` written as the backend's state file.
+    fn start_with_state(name: &str, env: &[(&str, &str)], state: Value) -> Harness {
+        let (dir, started) = start_mock(name, "password.toml");
+        let path = dir.join("state/tiss-greetd/state.json");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, state.to_string()).unwrap();
+        Harness::spawn(dir, started, env)
+    }
+
+    /// Runs a backend against the mock already serving `dir`.
+    fn spawn(dir: PathBuf, started: Arc<Mutex<Vec<StartedSession>>>, env: &[(&str, &str)]) -> Harness {
+        let mut command = backend_in(&dir);
+        let mut child = command
+            .envs(env.iter().copied())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let (tx, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if tx.send(serde_json::from_str(&line).unwrap()).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut harness = Harness {
+            child,
+            stdin,
+            lines,
+            dir,
+            started,
+        };
+        harness.expect(json!({"type": "state", "phase": "idle"}));
+        harness
+    }
+
+    fn send(&mut self, value: Value) {
+        writeln!(self.stdin, "{}", value).unwrap();
+    }
+
+    fn recv(&mut self) -> Value {
+        self.lines
+            .recv_timeout(Duration::from_secs(5))
+            .expect("backend response")
+    }
+
+    fn expect(&mut self, expected: Value) {
+        assert_eq!(self.recv(), expected);
+    }
+
+    /// `hello` at the current protocol version, up to the `state` answer.
+    /// Sends `hello`; returns the advertised capabilities.
+    fn hello(&mut self) -> Vec<Value> {
+        self.send(json!({"type": "hello", "ui_version": 3}));
+        let hello = self.recv();
+        assert_eq!(hello["type"], "hello");
+        self.expect(json!({"type": "state", "phase": "idle"}));
+        hello["capabilities"].as_array().unwrap().clone()
+    }
+
+    fn auth(&mut self) {
+        self.send(json!({"type": "auth", "username": "alice", "command": ["true"]}));
+        self.expect(json!({"type": "state", "phase": "auth"}));
+    }
+
+    /// Sent right after PAM accepts the credentials.
+    fn expect_last_login(&mut self) -> Value {
+        let last_login = self.recv();
+        assert_eq!(last_login["type"], "last_login", "{}", last_login);
+        last_login
+    }
+
+    /// The one session the mock started.
+    fn started_session(&self) -> StartedSession {
+        let started = self.started.lock().unwrap();
+        assert_eq!(started.len(), 1, "{:?}", started);
+        started[0].clone()
+    }
+
+    fn finish(&mut self) {
+        self.expect(json!({"type": "state", "phase": "waiting"}));
+        self.expect(json!({"type": "state", "phase": "success"}));
+        self.expect(json!({"type": "success"}));
+        self.send(json!({"type": "ack", "kind": "success"}));
+        assert!(self.child.wait().unwrap().success());
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn secret_prompt(id: u64, message: &str) -> Value {
+    json!({"type": "prompt", "id": id, "kind": "secret", "message": message, "echo": false})
+}
+
+/// `result` of every record in the audit log under `dir`.
+fn audit_results(dir: &Path) -> Vec<String> {
+    let content = std::fs::read_to_string(dir.join("state/tiss-greetd/audit.jsonl")).unwrap();
+    content
+        .lines()
+        .map(|line| serde_json::from_str::<Value>(line).unwrap()["result"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn otp_flow_needs_two_prompts() {
+    let mut h = Harness::start("otp", "otp.toml");
+    h.auth();
+    h.expect(secret_prompt(1, "Password:"));
+    h.send(json!({"type": "prompt_response", "id": 1, "response": "hunter2"}));
+    h.expect(json!({"type": "prompt", "id": 2, "kind": "visible", "message": "Verification code:", "echo": true}));
+    h.send(json!({"type": "prompt_response", "id": 2, "response": "123456"}));
+    h.expect_last_login();
+    h.finish();
+}
+
+#[test]
+fn wrong_password_is_auth_failed() {
+    let mut h = Harness::start("wrong", "password.toml");
+    h.auth();
+    h.expect(secret_prompt(1, "Password:"));
+    h.send(json!({"type": "prompt_response", "id": 1, "response": "nope"}));
+    h.expect(json!({"type": "state", "phase": "error"}));
+    assert_eq!(h.recv()["code"], "auth_failed");
+
+    // Transitions are logged at the default level.
+    let log = std::fs::read_to_string(h.dir.join("log/tiss-greetd-backend.log")).unwrap();
+    assert!(log.contains("phase: idle -> auth"), "{}", log);
+    assert!(log.contains("phase: auth -> error"), "{}", log);
+}
+
+#[test]
+fn escaped_password_is_decoded() {
+    let mut h = Harness::start("escaped", "password.toml");
+    h.auth();
+    h.expect(secret_prompt(1, "Password:"));
+    writeln!(h.stdin, r#"{{"type": "prompt_response", "id": 1, "response": "hunt\u0065r2"}}"#).unwrap();
+    h.expect_last_login();
+    h.finish();
+}
+
+#[test]
+fn password_change_forwards_pam_error_message() {
+    let mut h = Harness::start("change", "password-change.toml");
+    h.auth();
+    h.expect(secret_prompt(1, "Password:"));
+    h.send(json!({"type": "prompt_response", "id": 1, "response": "hunter2"}));
+    let message = h.recv();
+    assert_eq!(message["type"], "message");
+    assert_eq!(message["kind"], "error");
+    h.expect(secret_prompt(2, "Current password:"));
+    h.send(json!({"type": "prompt_response", "id": 2, "response": "hunter2"}));
+    h.expect(secret_prompt(3, "New password:"));
+    h.send(json!({"type": "prompt_response", "id": 3, "response": "correct horse"}));
+    h.expect(secret_prompt(4, "Retype new password:"));
+    h.send(json!({"type": "prompt_response", "id": 4, "response": "correct horse"}));
+    h.expect_last_login();
+    h.finish();
+}
+
+#[test]
+fn info_messages_do_not_need_answers() {
+    let mut h = Harness::start("info", "info-messages.json");
+    h.auth();
+    assert_eq!(h.recv()["kind"], "info");
+    h.expect(secret_prompt(1, "Password:"));
+    h.send(json!({"type": "prompt_response", "id": 1, "response": "hunter2"}));
+    assert_eq!(h.recv()["kind"], "error");
+    h.expect_last_login();
+    h.finish();
+}
+
+#[test]
+fn pam_maxtries_maps_to_account_locked() {
+    let mut h = Harness::start("locked", "locked.toml");
+    h.auth();
+    assert_eq!(h.recv()["type"], "message");
+    h.expect(secret_prompt(1, "Password:"));
+    h.send(json!({"type": "prompt_response", "id": 1, "response": "hunter2"}));
+    h.expect(json!({"type": "state", "phase": "error"}));
+    assert_eq!(h.recv()["code"], "account_locked");
+}
+
+#[test]
+fn start_session_failure_is_reported() {
+    let mut h = Harness::start("start", "start-failure.toml");
+    h.auth();
+    h.expect(secret_prompt(1, "Password:"));
+    h.send(json!({"type": "prompt_response", "id": 1, "response": "hunter2"}));
+    h.expect_last_login();
+    h.expect(json!({"type": "state", "phase": "waiting"}));
+    h.expect(json!({"type": "state", "phase": "error"}));
+    let error = h.recv();
+    assert_eq!(error["code"], "pam_error");
+    assert!(error["message"].as_str().unwrap().contains("exec failed"));
+    assert_eq!(audit_results(&h.dir), ["authenticated", "pam_error"]);
+}
+
+#[test]
+fn cancel_is_handled_while_pam_is_busy() {
+    let mut h = Harness::start("slow", "slow-pam.toml");
+    h.auth();
+    let started = Instant::now();
+    h.send(json!({"type": "ping"}));
+    h.expect(json!({"type": "pong"}));
+    h.send(json!({"type": "cancel"}));
+    let error = h.recv();
+    assert_eq!(error["code"], "cancelled");
+    assert_eq!(error["message"], "authentication cancelled");
+    h.expect(json!({"type": "state", "phase": "idle"}));
+    assert!(started.elapsed() < Duration::from_secs(2));
+    assert_eq!(audit_results(&h.dir), ["cancelled"]);
+}
+
+#[test]
+fn auth_timeout_returns_to_idle() {
+    let mut h = Harness::start_with("timeout", "password.toml", &[("TISS_GREETD_AUTH_TIMEOUT_SECS", "1")]);
+    h.auth();
+    h.expect(secret_prompt(1, "Password:"));
+    let error = h.recv();
+    assert_eq!(error["code"], "timeout");
+    assert_eq!(error["message"], "authentication timed out");
+    h.expect(json!({"type": "state", "phase": "idle"}));
+    assert_eq!(audit_results(&h.dir), ["timeout"]);
+}
+
+#[test]
+fn accepted_credentials_are_audited_before_the_start() {
+    let mut h = Harness::start("deferred-cancel", "password.toml");
+    h.send(json!({"type": "auth", "username": "alice", "defer_start": true}));
+    h.expect(json!({"type": "state", "phase": "auth"}));
+    h.expect(secret_prompt(1, "Password:"));
+    h.send(json!({"type": "prompt_response", "id": 1, "response": "hunter2"}));
+    h.expect_last_login();
+    h.expect(json!({"type": "state", "phase": "authenticated"}));
+    h.send(json!({"type": "cancel"}));
+    assert_eq!(h.recv()["code"], "cancelled");
+    h.expect(json!({"type": "state", "phase": "idle"}));
+    assert_eq!(audit_results(&h.dir), ["authenticated", "cancelled"]);
+}
+
+#[test]
+fn deferred_start_picks_session_after_auth() {
+    let mut h = Harness::start("deferred", "password.toml");
+    h.send(json!({"type": "auth", "username": "alice", "defer_start": true}));
+    h.expect(json!({"type": "state", "phase": "auth"}));
+    h.expect(secret_prompt(1, "Password:"));
+    h.send(json!({"type": "start", "command": ["true"]}));
+    assert_eq!(h.recv()["message"], "auth in progress");
+    h.send(json!({"type": "prompt_response", "id": 1, "response": "hunter2"}));
+    h.expect_last_login();
+    h.expect(json!({"type": "state", "phase": "authenticated"}));
+    h.send(json!({"type": "start", "command": ["true"], "locale": "en_US.UTF-8"}));
+    let state = h.dir.join("state/tiss-greetd/state.json");
+    h.finish();
+    let persisted: Value = serde_json::from_str(&std::fs::read_to_string(state).unwrap()).unwrap();
+    assert_eq!(persisted["global"]["locale"], "en_US.UTF-8");
+    assert_eq!(persisted["users"]["alice"]["locale"], "en_US.UTF-8");
+}
+
+const TWO_SESSIONS: &str = r#"[{"id": "sway", "exec": ["true"]}, {"id": "niri", "exec": ["true"]}]"#;
+
+#[test]
+fn old_state_is_migrated_and_selection_remembered_per_user() {
+    let mut h = Harness::start_with_state(
+        "migrate",
+        &[("TISS_GREETD_SESSIONS_JSON", TWO_SESSIONS)],
+        json!({"last_session_id": "sway", "last_locale": "de_DE.UTF-8"}),
+    );
+    let state = h.dir.join("state/tiss-greetd/state.json");
+    let persisted: Value = serde_json::from_str(&std::fs::read_to_string(&state).unwrap()).unwrap();
+    assert_eq!(persisted["version"], 2);
+    assert_eq!(persisted["global"]["session_id"], "sway");
+    assert!(persisted.get("last_session_id").is_none());
+
+    h.send(json!({"type": "select_user", "username": "bob"}));
+    h.expect(json!({"type": "user_selection", "username": "bob", "session_id": "sway", "locale": "de_DE.UTF-8"}));
+
+    h.send(json!({"type": "auth", "username": "alice", "session_id": "niri"}));
+    h.expect(json!({"type": "state", "phase": "auth"}));
+    h.expect(secret_prompt(1, "Password:"));
+    h.send(json!({"type": "prompt_response", "id": 1, "response": "hunter2"}));
+    h.expect_last_login();
+    h.finish();
+    let persisted: Value = serde_json::from_str(&std::fs::read_to_string(&state).unwrap()).unwrap();
+    assert_eq!(persisted["last_user"], "alice");
+    assert_eq!(persisted["users"]["alice"], json!({"session_id": "niri"}));
+    assert_eq!(persisted["global"], json!({"session_id": "niri", "locale": "de_DE.UTF-8"}));
+}
+
+#[test]
+fn select_user_falls_back_to_global_selection() {
+    let mut h = Harness::start_with_state(
+        "selectuser",
+        &[("TISS_GREETD_SESSIONS_JSON", TWO_SESSIONS)],
+        json!({
+            "version": 2,
+            "last_user": "alice",
+            "global": {"session_id": "sway", "locale": "de_DE.UTF-8"},
+            "users": {
+                "alice": {"session_id": "niri"},
+                "carol": {"session_id": "gnome", "locale": "fr_FR.UTF-8"},
+            },
+        }),
+    );
+    h.send(json!({"type": "select_user", "username": "alice"}));
+    h.expect(json!({"type": "user_selection", "username": "alice", "session_id": "niri", "locale": "de_DE.UTF-8"}));
+    h.send(json!({"type": "select_user", "username": "carol"}));
+    h.expect(json!({"type": "user_selection", "username": "carol", "locale": "fr_FR.UTF-8"}));
+    h.send(json!({"type": "select_user", "username": "dave"}));
+    h.expect(json!({"type": "user_selection", "username": "dave", "session_id": "sway", "locale": "de_DE.UTF-8"}));
+}
+
+#[test]
+fn autologin_starts_session_after_countdown() {
+    let mut h = Harness::start_with(
+        "autologin",
+        "autologin.toml",
+        &[
+            ("TISS_GREETD_AUTOLOGIN_USER", "kiosk"),
+            ("TISS_GREETD_AUTOLOGIN_DELAY_SECS", "1"),
+        ],
+    );
+    // The countdown waits for a UI to show it, and a greeter that dies
+    // before that does not use up the autologin.
+    std::thread::sleep(Duration::from_millis(1500));
+    assert!(h.lines.try_recv().is_err());
+    assert!(!h.dir.join("run/autologin").exists());
+    h.hello();
+    h.expect(json!({"type": "autologin", "username": "kiosk", "remaining_secs": 1}));
+    assert!(h.dir.join("run/autologin").exists());
+    h.expect(json!({"type": "state", "phase": "auth"}));
+    h.expect_last_login();
+    h.finish();
+}
+
+#[test]
+fn autologin_is_cancelled_by_input_and_fires_once_per_boot() {
+    let env = [
+        ("TISS_GREETD_AUTOLOGIN_USER", "kiosk"),
+        ("TISS_GREETD_AUTOLOGIN_DELAY_SECS", "30"),
+    ];
+    let mut h = Harness::start_with("autologincancel", "autologin.toml", &env);
+    assert!(h.hello().contains(&json!("autologin")));
+    h.expect(json!({"type": "autologin", "username": "kiosk", "remaining_secs": 30}));
+    h.send(json!({"type": "cancel"}));
+    h.expect(json!({"type": "autologin_cancelled"}));
+    h.send(json!({"type": "ping"}));
+    h.expect(json!({"type": "pong"}));
+
+    // A restarted greeter does not count down again this boot.
+    let mut again = Harness::spawn(h.dir.clone(), h.started.clone(), &env);
+    assert!(!again.hello().contains(&json!("autologin")));
+    again.send(json!({"type": "ping"}));
+    again.expect(json!({"type": "pong"}));
+}
+
+#[test]
+fn autologin_gives_up_when_pam_asks_for_a_password() {
+    let mut h = Harness::start_with(
+        "autologinpw",
+        "password.toml",
+        &[
+            ("TISS_GREETD_AUTOLOGIN_USER", "alice"),
+            ("TISS_GREETD_AUTOLOGIN_DELAY_SECS", "0"),
+        ],
+    );
+    h.hello();
+    h.expect(json!({"type": "autologin", "username": "alice", "remaining_secs": 0}));
+    h.expect(json!({"type": "state", "phase": "auth"}));
+    let error = h.recv();
+    assert_eq!(error["message"], "autologin needs interactive authentication");
+    h.expect(json!({"type": "state", "phase": "idle"}));
+}
+
+#[test]
+fn session_env_from_config_is_applied() {
+    let sessions = json!([
+        {"id": "niri-debug", "name": "niri (debug)", "exec": ["niri", "--session"], "type": "wayland",
+         "env": {"RUST_LOG": "debug", "LANG": "C"}},
+    ]);
+    let mut h = Harness::start_with(
+        "sessionenv",
+        "password.toml",
+        &[("TISS_GREETD_SESSIONS_JSON", &sessions.to_string())],
+    );
+    h.send(json!({"type": "auth", "username": "alice", "session_id": "niri-debug", "locale": "de_DE.UTF-8"}));
+    h.expect(json!({"type": "state", "phase": "auth"}));
+    h.expect(secret_prompt(1, "Password:"));
+    h.send(json!({"type": "prompt_response", "id": 1, "response": "hunter2"}));
+    h.expect_last_login();
+    h.finish();
+    let started = h.started_session();
+    assert_eq!(started.cmd, ["niri", "--session"]);
+    assert!(started.env.contains(&"RUST_LOG=debug".to_string()), "{:?}", started.env);
+    // The selected locale still wins over the session's LANG.
+    assert!(started.env.contains(&"LANG=de_DE.UTF-8".to_string()), "{:?}", started.env);
+    assert!(!started.env.contains(&"LANG=C".to_string()));
+}
+
+#[test]
+fn desktop_env_follows_selected_session() {
+    let sessions = json!([
+        {"id": "gnome", "name": "GNOME", "exec": ["gnome-session"], "type": "wayland",
+         "desktop_names": ["GNOME", "GNOME-Classic"]},
+    ]);
+    let mut h = Harness::start_with(
+        "desktopenv",
+        "password.toml",
+        &[("TISS_GREETD_SESSIONS_JSON", &sessions.to_string())],
+    );
+    h.send(json!({"type": "auth", "username": "alice", "session_id": "gnome"}));
+    h.expect(json!({"type": "state", "phase": "auth"}));
+    h.expect(secret_prompt(1, "Password:"));
+    h.send(json!({"type": "prompt_response", "id": 1, "response": "hunter2"}));
+    h.expect_last_login();
+    h.finish();
+    let env = h.started_session().env;
+    for expected in [
+        "XDG_SESSION_TYPE=wayland",
+        "XDG_CURRENT_DESKTOP=GNOME:GNOME-Classic",
+        "XDG_SESSION_DESKTOP=gnome",
+    ] {
+        assert!(env.contains(&expected.to_string()), "{} missing from {:?}", expected, env);
+    }
+}
+
+#[test]
+fn x11_session_runs_through_wrapper() {
+    let sessions = json!([
+        {"id": "i3", "name": "i3", "exec": ["i3"], "type": "x11", "desktop_names": ["i3"]},
+    ]);
+    let mut h = Harness::start_with(
+        "x11",
+        "password.toml",
+        &[
+            ("TISS_GREETD_SESSIONS_JSON", &sessions.to_string()),
+            ("TISS_GREETD_X11_WRAPPER_JSON", r#"["/opt/xwrap", "--quiet"]"#),
+        ],
+    );
+    // A Wayland type left in the session env must not leak into X sessions.
+    h.send(json!({"type": "auth", "username": "alice", "session_id": "i3",
+                  "env": {"XDG_SESSION_TYPE": "wayland"}}));
+    h.expect(json!({"type": "state", "phase": "auth"}));
+    h.expect(secret_prompt(1, "Password:"));
+    h.send(json!({"type": "prompt_response", "id": 1, "response": "hunter2"}));
+    h.expect_last_login();
+    h.finish();
+    let started = h.started_session();
+    assert_eq!(started.cmd, ["/opt/xwrap", "--quiet", "i3"]);
+    assert!(started.env.contains(&"XDG_SESSION_TYPE=x11".to_string()), "{:?}", started.env);
+    assert!(started.env.contains(&"XDG_CURRENT_DESKTOP=i3".to_string()), "{:?}", started.env);
+}
+
+#[test]
+fn renamed_x11_session_keeps_its_desktop_id() {
+    let sessions = json!([
+        {"id": "plasma", "exec": ["startplasma-wayland"], "type": "wayland", "desktop_id": "plasma",
+         "desktop_names": ["KDE"]},
+        {"id": "plasma-x11", "exec": ["startplasmax11"], "type": "x11", "desktop_id": "plasma",
+         "desktop_names": ["KDE"]},
+    ]);
+    let mut h = Harness::start_with(
+        "x11rename",
+        "password.toml",
+        &[
+            ("TISS_GREETD_SESSIONS_JSON", &sessions.to_string()),
+            ("TISS_GREETD_X11_WRAPPER_JSON", r#"["/opt/xwrap"]"#),
+        ],
+    );
+    h.send(json!({"type": "auth", "username": "alice", "session_id": "plasma-x11"}));
+    h.expect(json!({"type": "state", "phase": "auth"}));
+    h.expect(secret_prompt(1, "Password:"));
+    h.send(json!({"type": "prompt_response", "id": 1, "response": "hunter2"}));
+    h.expect_last_login();
+    h.finish();
+    let started = h.started_session();
+    assert_eq!(started.cmd, ["/opt/xwrap", "startplasmax11"]);
+    assert!(started.env.contains(&"XDG_SESSION_DESKTOP=plasma".to_string()), "{:?}", started.env);
+    assert!(started.env.contains(&"XDG_CURRENT_DESKTOP=KDE".to_string()), "{:?}", started.env);
+}
+
+#[test]
+fn desktop_env_falls_back_to_command_name() {
+    let mut h = Harness::start("desktopcmd", "password.toml");
+    h.send(json!({"type": "auth", "username": "alice", "command": ["/usr/bin/sway", "-d"]}));
+    h.expect(json!({"type": "state", "phase": "auth"}));
+    h.expect(secret_prompt(1, "Password:"));
+    h.send(json!({"type": "prompt_response", "id": 1, "response": "hunter2"}));
+    h.expect_last_login();
+    h.finish();
+    let env = h.started_session().env;
+    assert!(env.contains(&"XDG_CURRENT_DESKTOP=sway".to_string()), "{:?}", env);
+    assert!(env.contains(&"XDG_SESSION_DESKTOP=sway".to_string()), "{:?}", env);
+    assert!(!env.iter().any(|var| var.contains("niri")), "{:?}", env);
+}
+
+#[test]
+fn start_without_auth_is_rejected() {
+    let mut h = Harness::start("nostart", "password.toml");
+    h.send(json!({"type": "start", "session_id": "niri"}));
+    assert_eq!(h.recv()["message"], "no authenticated session");
+}
+
+#[test]
+fn repeated_failures_are_rate_limited() {
+    let policy = r#"{"max_attempts": 2, "backoff_secs": 60}"#;
+    let mut h = Harness::start_with("ratelimit", "password.toml", &[("TISS_GREETD_RATE_LIMIT_JSON", policy)]);
+    for _ in 0..2 {
+        h.auth();
+        h.expect(secret_prompt(1, "Password:"));
+        h.send(json!({"type": "prompt_response", "id": 1, "response": "nope"}));
+        h.expect(json!({"type": "state", "phase": "error"}));
+        assert_eq!(h.recv()["code"], "auth_failed");
+    }
+    h.auth();
+    h.expect(json!({"type": "state", "phase": "error"}));
+    let error = h.recv();
+    assert_eq!(error["code"], "rate_limited");
+    let retry = error["retry_after_secs"].as_u64().unwrap();
+    assert!(retry > 0 && retry <= 60);
+
+    let state = h.dir.join("state/tiss-greetd/state.json");
+    let persisted: Value = serde_json::from_str(&std::fs::read_to_string(state).unwrap()).unwrap();
+    assert_eq!(persisted["auth_failures"]["alice"]["failures"], 2);
+}
+
+#[test]
+fn successful_login_clears_failures() {
+    let policy = [("TISS_GREETD_RATE_LIMIT_JSON", r#"{"enabled": true}"#)];
+    let mut h = Harness::start_with("ratereset", "password.toml", &policy);
+    h.auth();
+    h.expect(secret_prompt(1, "Password:"));
+    h.send(json!({"type": "prompt_response", "id": 1, "response": "nope"}));
+    h.expect(json!({"type": "state", "phase": "error"}));
+    assert_eq!(h.recv()["code"], "auth_failed");
+    let state = h.dir.join("state/tiss-greetd/state.json");
+    let persisted: Value = serde_json::from_str(&std::fs::read_to_string(&state).unwrap()).unwrap();
+    assert_eq!(persisted["auth_failures"]["alice"]["failures"], 1);
+
+    h.auth();
+    h.expect(secret_prompt(1, "Password:"));
+    h.send(json!({"type": "prompt_response", "id": 1, "response": "hunter2"}));
+    h.expect_last_login();
+    h.finish();
+    let persisted: Value = serde_json::from_str(&std::fs::read_to_string(&state).unwrap()).unwrap();
+    assert!(persisted.get("auth_failures").is_none());
+}
+
+#[test]
+fn rate_limit_is_off_unless_configured() {
+    let mut h = Harness::start("ratedefault", "password.toml");
+    for _ in 0..6 {
+        h.auth();
+        h.expect(secret_prompt(1, "Password:"));
+        h.send(json!({"type": "prompt_response", "id": 1, "response": "nope"}));
+        h.expect(json!({"type": "state", "phase": "error"}));
+        assert_eq!(h.recv()["code"], "auth_failed");
+    }
+    // Nothing is counted, so there is no state to write either.
+    let state = std::fs::read_to_string(h.dir.join("state/tiss-greetd/state.json")).unwrap_or_default();
+    assert!(!state.contains("auth_failures"), "{}", state);
+
+    let policy = [("TISS_GREETD_RATE_LIMIT_JSON", r#"{"enabled": false, "max_attempts": 1}"#)];
+    let mut h = Harness::start_with("rateoff", "password.toml", &policy);
+    h.send(json!({"type": "hello", "ui_version": 3}));
+    let caps = h.recv()["capabilities"].as_array().unwrap().clone();
+    assert!(!caps.contains(&json!("rate_limit")), "{:?}", caps);
+
+    // A policy that does not parse, or names a key that does not exist,
+    // leaves it off rather than falling back to the defaults.
+    for (name, raw) in [("ratebroken", r#"{"enabled": true,"#), ("ratetypo", r#"{"max_atempts": 3}"#)] {
+        let mut h = Harness::start_with(name, "password.toml", &[("TISS_GREETD_RATE_LIMIT_JSON", raw)]);
+        h.send(json!({"type": "hello", "ui_version": 3}));
+        let caps = h.recv()["capabilities"].as_array().unwrap().clone();
+        assert!(!caps.contains(&json!("rate_limit")), "{}: {:?}", raw, caps);
+        let log = std::fs::read_to_string(h.dir.join("log/tiss-greetd-backend.log")).unwrap();
+        assert!(log.contains("rate limiting is off"), "{}", log);
+    }
+}
+
+#[test]
+fn startup_reports_hardening() {
+    let h = Harness::start("hardening", "password.toml");
+    let log = std::fs::read_to_string(h.dir.join("log/tiss-greetd-backend.log")).unwrap();
+    let report = log.lines().find(|line| line.contains("hardening:")).unwrap();
+    assert!(report.contains("no_dump=on"), "{}", report);
+    assert!(report.contains("core_limit_0=on"), "{}", report);
+}
+
+#[test]
+fn json_log_records_carry_auth_fields() {
+    let env = [("TISS_GREETD_LOG_FORMAT", "json"), ("TISS_GREETD_LOG_LEVEL", "debug")];
+    let mut h = Harness::start_with("jsonlog", "password.toml", &env);
+    h.auth();
+    h.expect(secret_prompt(1, "Password:"));
+    h.send(json!({"type": "prompt_response", "id": 1, "response": "nope"}));
+    h.expect(json!({"type": "state", "phase": "error"}));
+    assert_eq!(h.recv()["code"], "auth_failed");
+
+    let log = std::fs::read_to_string(h.dir.join("log/tiss-greetd-backend.log")).unwrap();
+    let records: Vec<Value> = log.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert!(records.iter().any(|record| record["level"] == "debug"));
+    let failed = records
+        .iter()
+        .find(|record| record["msg"].as_str().unwrap().starts_with("auth failed"))
+        .unwrap();
+    assert_eq!(failed["level"], "warn");
+    assert_eq!(failed["component"], "backend");
+    assert_eq!(failed["attempt"], 1);
+    assert_eq!(failed["user"], "alice");
+    assert_eq!(failed["phase"], "auth");
+    assert!(failed["duration_ms"].is_u64());
+}
+
+#[test]
+fn log_level_drops_lower_severities() {
+    let mut h = Harness::start_with("loglevel", "password.toml", &[("TISS_GREETD_LOG_LEVEL", "warn")]);
+    h.auth();
+    h.expect(secret_prompt(1, "Password:"));
+    h.send(json!({"type": "prompt_response", "id": 1, "response": "nope"}));
+    h.expect(json!({"type": "state", "phase": "error"}));
+    assert_eq!(h.recv()["code"], "auth_failed");
+
+    let log = std::fs::read_to_string(h.dir.join("log/tiss-greetd-backend.log")).unwrap();
+    assert!(!log.contains("backend start"), "{}", log);
+    let failed = log.lines().find(|line| line.contains("auth failed")).unwrap();
+    assert!(failed.contains(" warn backend: "), "{}", failed);
+    assert!(failed.contains("attempt=1 user=alice"), "{}", failed);
+}
+
+#[test]
+fn logs_rotate_with_private_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let env = [
+        ("TISS_GREETD_LOG_LEVEL", "debug"),
+        ("TISS_GREETD_LOG_MAX_SIZE", "300"),
+        ("TISS_GREETD_LOG_KEEP", "2"),
+        ("TISS_GREETD_LOG_COMPRESS", "1"),
+    ];
+    let mut h = Harness::start_with("rotate", "password.toml", &env);
+    for _ in 0..2 {
+        h.auth();
+        h.expect(secret_prompt(1, "Password:"));
+        h.send(json!({"type": "prompt_response", "id": 1, "response": "nope"}));
+        h.expect(json!({"type": "state", "phase": "error"}));
+        assert_eq!(h.recv()["code"], "auth_failed");
+    }
+
+    let dir = h.dir.join("log");
+    let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+    assert_eq!(mode(&dir), 0o700);
+    let mut names: Vec<String> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    assert_eq!(
+        names,
+        ["tiss-greetd-backend.log", "tiss-greetd-backend.log.1.gz", "tiss-greetd-backend.log.2.gz"]
+    );
+    for name in &names {
+        let path = dir.join(name);
+        assert_eq!(mode(&path), 0o600, "{}", name);
+        assert!(std::fs::metadata(&path).unwrap().len() <= 300, "{}", name);
+    }
+    let rotated = std::fs::read(dir.join("tiss-greetd-backend.log.1.gz")).unwrap();
+    assert_eq!(rotated[..2], [0x1f, 0x8b]);
+}
+
+#[test]
+fn symlinked_log_dir_in_shared_tmp_is_refused() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let (dir, mut command) = backend_command("logsymlink", "password.toml");
+    let shared = dir.join("tmp");
+    let target = dir.join("elsewhere");
+    std::fs::create_dir_all(&shared).unwrap();
+    std::fs::create_dir_all(&target).unwrap();
+    std::fs::set_permissions(&shared, std::fs::Permissions::from_mode(0o1777)).unwrap();
+    let link = shared.join("tiss-greetd-logs");
+    std::os::unix::fs::symlink(&target, &link).unwrap();
+
+    let output = command
+        .env("TISS_GREETD_LOG_DIR", &link)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("refusing symlinked log dir"), "{}", stderr);
+    // Records still reach stderr instead.
+    assert!(stderr.contains("backend start"), "{}", stderr);
+    assert_eq!(std::fs::read_dir(&target).unwrap().count(), 0);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+/// Runs one failed login with the given log sink pointed at a datagram
+/// socket standing in for journald or `/dev/log`; returns what it received.
+fn failed_login_via_socket_sink(name: &str, sink: &str, socket_env: &str) -> Vec<Vec<u8>> {
+    let socket_dir = std::env::temp_dir().join(format!("tiss-greetd-e2e-{}-{}-sock", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&socket_dir);
+    std::fs::create_dir_all(&socket_dir).unwrap();
+    let socket_path = socket_dir.join("log.sock");
+    let socket = std::os::unix::net::UnixDatagram::bind(&socket_path).unwrap();
+    socket.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+
+    let socket_str = socket_path.to_str().unwrap();
+    let env = [("TISS_GREETD_LOG_SINK", sink), (socket_env, socket_str)];
+    let mut h = Harness::start_with(name, "password.toml", &env);
+    h.auth();
+    h.expect(secret_prompt(1, "Password:"));
+    h.send(json!({"type": "prompt_response", "id": 1, "response": "nope"}));
+    h.expect(json!({"type": "state", "phase": "error"}));
+    assert_eq!(h.recv()["code"], "auth_failed");
+    assert!(!h.dir.join("log/tiss-greetd-backend.log").exists());
+
+    let mut datagrams = Vec::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    while let Ok(len) = socket.recv(&mut buf) {
+        datagrams.push(buf[..len].to_vec());
+    }
+    let _ = std::fs::remove_dir_all(&socket_dir);
+    datagrams
+}
+
+#[test]
+fn journald_sink_sends_structured_fields() {
+    let datagrams = failed_login_via_socket_sink("journald", "journald", "TISS_GREETD_JOURNAL_SOCKET");
+    let records: Vec<std::collections::HashMap<String, String>> = datagrams
+        .iter()
+        .map(|datagram| {
+            String::from_utf8(datagram.clone())
+                .unwrap()
+                .lines()
+                .map(|line| {
+                    let (key, value) = line.split_once('=').unwrap();
+                    (key.to_string(), value.to_string())
+                })
+                .collect()
+        })
+        .collect();
+    assert!(records.iter().all(|record| record["SYSLOG_IDENTIFIER"] == "tiss-greetd"));
+    let failed = records
+        .iter()
+        .find(|record| record["MESSAGE"].starts_with("auth failed"))
+        .unwrap();
+    assert_eq!(failed["PRIORITY"], "4");
+    assert_eq!(failed["TISS_ATTEMPT"], "1");
+    assert_eq!(failed["TISS_PHASE"], "auth");
+    assert_eq!(failed["TISS_COMPONENT"], "backend");
+}
+
+#[test]
+fn syslog_sink_writes_tagged_datagrams() {
+    let datagrams = failed_login_via_socket_sink("syslog", "syslog", "TISS_GREETD_SYSLOG_SOCKET");
+    let lines: Vec<String> = datagrams
+        .into_iter()
+        .map(|datagram| String::from_utf8(datagram).unwrap())
+        .collect();
+    assert!(lines.iter().any(|line| line.starts_with("<86>tiss-greetd[") && line.contains("]: backend: backend start")));
+    let failed = lines.iter().find(|line| line.contains("backend: auth failed")).unwrap();
+    // authpriv (10) * 8 + warning (4)
+    assert!(failed.starts_with("<84>tiss-greetd["), "{}", failed);
+    assert!(failed.contains("attempt=1 user=alice"), "{}", failed);
+}
+
+#[test]
+fn privacy_mode_hashes_users_and_drops_pam_text() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let env = [("TISS_GREETD_LOG_PRIVACY", "drop"), ("TISS_GREETD_LOG_LEVEL", "debug")];
+    let mut h = Harness::start_with("privacy", "password.toml", &env);
+    h.auth();
+    h.expect(secret_prompt(1, "Password:"));
+    h.send(json!({"type": "prompt_response", "id": 1, "response": "nope"}));
+    h.expect(json!({"type": "state", "phase": "error"}));
+    assert_eq!(h.recv()["code"], "auth_failed");
+
+    let log = std::fs::read_to_string(h.dir.join("log/tiss-greetd-backend.log")).unwrap();
+    assert!(!log.contains("alice"), "{}", log);
+    assert!(!log.contains("Password:"), "{}", log);
+    let hashes: Vec<&str> = log
+        .split_whitespace()
+        .filter_map(|word| word.strip_prefix("user="))
+        .collect();
+    assert!(hashes.len() >= 2, "{}", log);
+    assert!(hashes.iter().all(|hash| *hash == hashes[0] && hash.starts_with("u:")));
+    let failed = log.lines().find(|line| line.contains("auth failed")).unwrap();
+    assert!(failed.contains("attempt=1"), "{}", failed);
+    assert!(failed.contains("phase=auth"), "{}", failed);
+
+    let key = h.dir.join("state/tiss-greetd/log-hash.key");
+    assert_eq!(std::fs::metadata(&key).unwrap().permissions().mode() & 0o777, 0o600);
+}
+
+#[test]
+fn privacy_mode_drops_greetd_error_descriptions() {
+    let env = [("TISS_GREETD_LOG_PRIVACY", "drop")];
+    let mut h = Harness::start_with("privacy-error", "locked.toml", &env);
+    h.auth();
+    assert_eq!(h.recv()["type"], "message");
+    h.expect(secret_prompt(1, "Password:"));
+    h.send(json!({"type": "prompt_response", "id": 1, "response": "hunter2"}));
+    h.expect(json!({"type": "state", "phase": "error"}));
+    assert_eq!(h.recv()["code"], "account_locked");
+
+    let log = std::fs::read_to_string(h.dir.join("log/tiss-greetd-backend.log")).unwrap();
+    let line = log.lines().find(|line| line.contains("greetd error: auth_error")).unwrap();
+    assert!(line.contains("bytes>"), "{}", line);
+    assert!(!log.contains("PAM_MAXTRIES"), "{}", log);
+    assert!(!log.contains("failed logins"), "{}", log);
+}
+
+#[test]
+fn audit_log_chains_outcomes_and_reports_last_login() {
+    let mut first = Harness::start("audit", "password.toml");
+    first.auth();
+    first.expect(secret_prompt(1, "Password:"));
+    first.send(json!({"type": "prompt_response", "id": 1, "response": "nope"}));
+    first.expect(json!({"type": "state", "phase": "error"}));
+    assert_eq!(first.recv()["code"], "auth_failed");
+    first.auth();
+    first.expect(secret_prompt(1, "Password:"));
+    first.send(json!({"type": "prompt_response", "id": 1, "response": "hunter2"}));
+    first.expect(json!({"type": "last_login", "failed_attempts": 1}));
+    first.finish();
+
+    let state = first.dir.join("state");
+    let state_env = state.to_str().unwrap();
+    let mut second = Harness::start_with("audit2", "password.toml", &[("XDG_STATE_HOME", state_env)]);
+    second.auth();
+    second.expect(secret_prompt(1, "Password:"));
+    second.send(json!({"type": "prompt_response", "id": 1, "response": "hunter2"}));
+    let last_login = second.recv();
+    assert_eq!(last_login["type"], "last_login");
+    assert!(last_login["previous_login"].is_u64());
+    assert_eq!(last_login["failed_attempts"], 0);
+    second.finish();
+
+    let audit = state.join("tiss-greetd/audit.jsonl");
+    let content = std::fs::read_to_string(&audit).unwrap();
+    let records: Vec<Value> = content.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    let results: Vec<&str> = records.iter().map(|record| record["result"].as_str().unwrap()).collect();
+    assert_eq!(results, ["auth_failed", "authenticated", "success", "authenticated", "success"]);
+    assert_eq!(records[1]["prev"], records[0]["hash"]);
+    assert_eq!(records[4]["user"], "alice");
+
+    let verify = |path: &Path| {
+        Command::new(env!("CARGO_BIN_EXE_tiss-greetd-backend"))
+            .arg("--verify-audit")
+            .arg(path)
+            .output()
+            .unwrap()
+    };
+    let output = verify(&audit);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("5 records"));
+
+    std::fs::write(&audit, content.replacen("\"auth_failed\"", "\"cancelled\"", 1)).unwrap();
+    let output = verify(&audit);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("line 1: hash mismatch"));
+
+    // Rewriting a record and recomputing the chain without the key does
+    // not pass either.
+    let key = state.join("tiss-greetd/audit.key");
+    assert_eq!(std::fs::metadata(&key).unwrap().mode() & 0o777, 0o600);
+    let mut prev = records[0]["prev"].as_str().unwrap().to_string();
+    let mut forged = String::new();
+    for (index, record) in records.iter().enumerate() {
+        let mut record = record.clone();
+        let body = record.as_object_mut().unwrap();
+        body.remove("hash");
+        body.insert("prev".to_string(), json!(prev));
+        if index == 0 {
+            body.insert("result".to_string(), json!("cancelled"));
+        }
+        let unhashed = serde_json::to_vec(&record).unwrap();
+        let mut hasher = Sha256::new();
+        hasher.update(prev.as_bytes());
+        hasher.update(b"\n");
+        hasher.update(&unhashed);
+        prev = hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect();
+        record["hash"] = json!(prev);
+        forged.push_str(&format!("{}\n", record));
+    }
+    std::fs::write(&audit, forged).unwrap();
+    let output = verify(&audit);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("line 1: hash mismatch"));
+
+    std::fs::write(&audit, content).unwrap();
+    std::fs::remove_file(&key).unwrap();
+    let output = verify(&audit);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("audit key"));
+}
+
+#[test]
+fn sandboxed_backend_can_still_log_in() {
+    let env = [("TISS_GREETD_SANDBOX", "1"), ("TISS_GREETD_RATE_LIMIT_JSON", r#"{"enabled": true}"#)];
+    let mut h = Harness::start_with("sandbox", "password.toml", &env);
+    h.auth();
+    h.expect(secret_prompt(1, "Password:"));
+    h.send(json!({"type": "prompt_response", "id": 1, "response": "nope"}));
+    h.expect(json!({"type": "state", "phase": "error"}));
+    assert_eq!(h.recv()["code"], "auth_failed");
+    // The state dir stays writable inside the sandbox.
+    assert!(h.dir.join("state/tiss-greetd/state.json").exists());
+
+    h.auth();
+    h.expect(secret_prompt(1, "Password:"));
+    h.send(json!({"type": "prompt_response", "id": 1, "response": "hunter2"}));
+    h.expect_last_login();
+    h.finish();
+    let log = std::fs::read_to_string(h.dir.join("log/tiss-greetd-backend.log")).unwrap();
+    let report = log.lines().find(|line| line.contains("sandbox: landlock=")).unwrap();
+    assert!(report.contains("seccomp="), "{}", report);
+}
+
+#[test]
+fn sandboxed_backend_can_still_autologin() {
+    let env = [
+        ("TISS_GREETD_SANDBOX", "1"),
+        ("TISS_GREETD_AUTOLOGIN_USER", "kiosk"),
+        ("TISS_GREETD_AUTOLOGIN_DELAY_SECS", "0"),
+    ];
+    let mut h = Harness::start_with("sandboxautologin", "autologin.toml", &env);
+    h.hello();
+    // The marker is written after the sandbox is up.
+    h.expect(json!({"type": "autologin", "username": "kiosk", "remaining_secs": 0}));
+    assert!(h.dir.join("run/autologin").exists());
+    h.expect(json!({"type": "state", "phase": "auth"}));
+    h.expect_last_login();
+    h.finish();
+}
+
+#[test]
+fn root_is_refused_unless_allowed() {
+    if unsafe { libc::geteuid() } != 0 {
+        return;
+    }
+    let (dir, mut command) = backend_command("root", "password.toml");
+    let output = command
+        .env_remove("TISS_GREETD_ALLOW_ROOT")
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("refusing to run as root"));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn hello_advertises_version_and_capabilities() {
+    let mut h = Harness::start("hello", "password.toml");
+    h.send(json!({"type": "hello", "ui_version": 3}));
+    let hello = h.recv();
+    assert_eq!(hello["type"], "hello");
+    assert_eq!(hello["protocol_version"], 3);
+    assert!(hello["min_ui_version"].as_u64().unwrap() <= 3);
+    let caps = hello["capabilities"].as_array().unwrap();
+    assert!(caps.contains(&json!("two_phase_start")));
+    assert!(!caps.contains(&json!("reattach")));
+    assert!(!caps.contains(&json!("rate_limit")));
+    // Nothing configured for these.
+    assert!(caps.contains(&json!("last_login")));
+    assert!(!caps.contains(&json!("users")));
+    assert!(!caps.contains(&json!("user_selection")));
+    assert!(!caps.contains(&json!("autologin")));
+    h.expect(json!({"type": "state", "phase": "idle"}));
+}
+
+#[test]
+fn users_follow_hello() {
+    let users = json!([
+        {"name": "alice", "real_name": "Alice Liddell", "uid": 1000, "home": "/home/alice"},
+        {"name": "bob", "uid": 1001, "home": "/home/bob", "avatar": "/var/lib/AccountsService/icons/bob"},
+    ]);
+    let mut h = Harness::start_with(
+        "users",
+        "password.toml",
+        &[("TISS_GREETD_USERS_JSON", &users.to_string())],
+    );
+    h.send(json!({"type": "hello", "ui_version": 3}));
+    let hello = h.recv();
+    let caps = hello["capabilities"].as_array().unwrap();
+    assert!(caps.contains(&json!("users")));
+    assert!(caps.contains(&json!("user_selection")));
+    h.expect(json!({"type": "users", "users": users}));
+    h.expect(json!({"type": "state", "phase": "idle"}));
+}
+
+#[test]
+fn too_old_ui_is_rejected() {
+    let mut h = Harness::start("oldui", "password.toml");
+    h.send(json!({"type": "hello", "ui_version": 1}));
+    assert_eq!(h.recv()["type"], "hello");
+    assert_eq!(h.recv()["code"], "unsupported_version");
+    h.send(json!({"type": "auth", "username": "alice"}));
+    assert_eq!(h.recv()["code"], "unsupported_version");
+    h.send(json!({"type": "ping"}));
+    h.expect(json!({"type": "pong"}));
+}
+
+struct SocketUi {
+    stream: UnixStream,
+    reader: BufReader<UnixStream>,
+}
+
+impl SocketUi {
+    fn connect(path: &Path) -> SocketUi {
+        let started = Instant::now();
+        let stream = loop {
+            match UnixStream::connect(path) {
+                Ok(stream) => break stream,
+                Err(_) if started.elapsed() < Duration::from_secs(5) => {
+                    std::thread::sleep(Duration::from_millis(20));
+                }
+                Err(err) => panic!("connect {}: {}", path.display(), err),
+            }
+        };
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let reader = BufReader::new(stream.try_clone().unwrap());
+        SocketUi { stream, reader }
+    }
+
+    fn send(&mut self, value: Value) {
+        writeln!(self.stream, "{}", value).unwrap();
+    }
+
+    fn recv(&mut self) -> Value {
+        let mut line = String::new();
+        self.reader.read_line(&mut line).unwrap();
+        serde_json::from_str(&line).unwrap()
+    }
+}
+
+#[test]
+fn socket_ui_can_reattach_mid_prompt() {
+    let (dir, mut command) = backend_command("reattach", "otp.toml");
+    let ui_socket = dir.join("run/backend.sock");
+    let mut child = command.arg("--listen").arg(&ui_socket).spawn().unwrap();
+
+    let mut ui = SocketUi::connect(&ui_socket);
+    assert_eq!(ui.recv(), json!({"type": "state", "phase": "idle"}));
+    ui.send(json!({"type": "auth", "username": "alice", "command": ["true"]}));
+    assert_eq!(ui.recv(), json!({"type": "state", "phase": "auth"}));
+    assert_eq!(ui.recv(), secret_prompt(1, "Password:"));
+    drop(ui);
+
+    let mut ui = SocketUi::connect(&ui_socket);
+    assert_eq!(ui.recv(), json!({"type": "state", "phase": "auth"}));
+    assert_eq!(ui.recv(), secret_prompt(1, "Password:"));
+    ui.send(json!({"type": "prompt_response", "id": 1, "response": "hunter2"}));
+    assert_eq!(ui.recv()["id"], 2);
+    ui.send(json!({"type": "prompt_response", "id": 2, "response": "123456"}));
+    assert_eq!(ui.recv()["type"], "last_login");
+    assert_eq!(ui.recv(), json!({"type": "state", "phase": "waiting"}));
+    assert_eq!(ui.recv(), json!({"type": "state", "phase": "success"}));
+    assert_eq!(ui.recv(), json!({"type": "success"}));
+    ui.send(json!({"type": "ack", "kind": "success"}));
+    assert!(child.wait().unwrap().success());
+    assert!(!ui_socket.exists());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn socket_dir_is_created_private_and_shared_dirs_are_left_alone() {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+    // A pre-existing dir keeps its mode.
+    let (dir, mut command) = backend_command("sockdir", "password.toml");
+    let existing = dir.join("existing");
+    std::fs::create_dir(&existing).unwrap();
+    std::fs::set_permissions(&existing, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let mut child = command.arg("--listen").arg(existing.join("backend.sock")).spawn().unwrap();
+    let mut ui = SocketUi::connect(&existing.join("backend.sock"));
+    assert_eq!(ui.recv(), json!({"type": "state", "phase": "idle"}));
+    assert_eq!(mode(&existing), 0o755);
+    let _ = child.kill();
+    let _ = child.wait();
+
+    // A missing one is created 0700.
+    let mut command = backend_in(&dir);
+    let created = dir.join("created/nested");
+    let mut child = command.arg("--listen").arg(created.join("backend.sock")).spawn().unwrap();
+    let mut ui = SocketUi::connect(&created.join("backend.sock"));
+    assert_eq!(ui.recv(), json!({"type": "state", "phase": "idle"}));
+    assert_eq!(mode(&created), 0o700);
+    let _ = child.kill();
+    let _ = child.wait();
+
+    // A dir others can write to is refused and not re-moded.
+    let shared = dir.join("shared");
+    std::fs::create_dir(&shared).unwrap();
+    std::fs::set_permissions(&shared, std::fs::Permissions::from_mode(0o777)).unwrap();
+    let output = backend_in(&dir)
+        .arg("--listen")
+        .arg(shared.join("backend.sock"))
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert_eq!(mode(&shared), 0o777);
+    assert!(!shared.join("backend.sock").exists());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn overlong_ui_line_drops_the_connection() {
+    use std::io::Read;
+    let (dir, mut command) = backend_command("longline", "password.toml");
+    let ui_socket = dir.join("run/backend.sock");
+    let mut child = command.arg("--listen").arg(&ui_socket).spawn().unwrap();
+
+    let mut ui = SocketUi::connect(&ui_socket);
+    assert_eq!(ui.recv(), json!({"type": "state", "phase": "idle"}));
+    // No newline, ever. The backend may close the socket before all of it
+    // is written.
+    let _ = ui.stream.write_all(&vec![b'x'; 256 * 1024]);
+    let mut rest = Vec::new();
+    let _ = ui.reader.read_to_end(&mut rest);
+    assert!(rest.is_empty(), "{:?}", String::from_utf8_lossy(&rest));
+
+    // The backend itself keeps running for the next UI.
+    let mut ui = SocketUi::connect(&ui_socket);
+    assert_eq!(ui.recv(), json!({"type": "state", "phase": "idle"}));
+    ui.send(json!({"type": "ping"}));
+    assert_eq!(ui.recv(), json!({"type": "pong"}));
+    let _ = child.kill();
+    let _ = child.wait();
+    let log = std::fs::read_to_string(dir.join("log/tiss-greetd-backend.log")).unwrap_or_default();
+    assert!(log.contains("longer than 65536 bytes"), "{}", log);
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,101 @@
+//! Keeps `protocol/schema.json` and the examples in `protocol/README.md` in
+//! step with the Rust protocol types.
+
+use jsonschema::JSONSchema;
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::process::Command;
+
+fn protocol_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../protocol")
+}
+
+fn printed_schema() -> Value {
+    let output = Command::new(env!("CARGO_BIN_EXE_tiss-greetd-backend"))
+        .arg("--print-schema")
+        .env_clear()
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+/// Compiles a validator for one direction (`UiRequest` or `BackendResponse`).
+fn validator(schema: &Value, definition: &str) -> JSONSchema {
+    let root = json!({
+        "$schema": schema["$schema"],
+        "$ref": format!("#/definitions/{}", definition),
+        "definitions": schema["definitions"],
+    });
+    JSONSchema::compile(&root).unwrap()
+}
+
+/// JSON lines of the first ```json block after `heading`.
+fn examples(readme: &str, heading: &str) -> Vec<Value> {
+    let section = &readme[readme.find(heading).unwrap()..];
+    let block = &section[section.find("```json").unwrap() + "```json".len()..];
+    let block = &block[..block.find("```").unwrap()];
+    block
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+#[test]
+fn checked_in_schema_is_current() {
+    let committed = std::fs::read_to_string(protocol_dir().join("schema.json")).unwrap();
+    let committed: Value = serde_json::from_str(&committed).unwrap();
+    assert!(
+        committed == printed_schema(),
+        "protocol/schema.json is stale; regenerate with `tiss-greetd-backend --print-schema`"
+    );
+}
+
+#[test]
+fn readme_examples_match_schema() {
+    let schema = printed_schema();
+    let readme = std::fs::read_to_string(protocol_dir().join("README.md")).unwrap();
+    for (heading, definition) in [
+        ("## UI -> Backend", "UiRequest"),
+        ("## Backend -> UI", "BackendResponse"),
+    ] {
+        let validator = validator(&schema, definition);
+        let examples = examples(&readme, heading);
+        assert!(!examples.is_empty());
+        for example in examples {
+            if let Err(errors) = validator.validate(&example) {
+                let errors: Vec<String> = errors.map(|err| err.to_string()).collect();
+                panic!("{} example {} is invalid: {:?}", definition, example, errors);
+            }
+        }
+    }
+}
+
+#[test]
+fn every_request_type_is_documented() {
+    let schema = printed_schema();
+    let readme = std::fs::read_to_string(protocol_dir().join("README.md")).unwrap();
+    for (heading, definition) in [
+        ("## UI -> Backend", "UiRequest"),
+        ("## Backend -> UI", "BackendResponse"),
+    ] {
+        let documented: Vec<Value> = examples(&readme, heading)
+            .into_iter()
+            .map(|example| example["type"].clone())
+            .collect();
+        for variant in schema["definitions"][definition]["oneOf"].as_array().unwrap() {
+            let name = &variant["properties"]["type"]["enum"][0];
+            assert!(documented.contains(name), "{} {} has no example", definition, name);
+        }
+    }
+}
+
+#[test]
+fn malformed_messages_are_rejected() {
+    let schema = printed_schema();
+    let ui = validator(&schema, "UiRequest");
+    assert!(!ui.is_valid(&json!({"type": "auth"})));
+    assert!(!ui.is_valid(&json!({"type": "prompt_response", "id": "one"})));
+    assert!(!ui.is_valid(&json!({"type": "reboot"})));
+}
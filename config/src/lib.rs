@@ -1,7 +1,9 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 
+pub mod state;
+
 #[derive(Debug, Clone, Deserialize, Default)]
 pub struct Config {
     #[serde(default)]
@@ -24,6 +26,12 @@ pub struct Config {
     pub seat: Seat,
     #[serde(default)]
     pub ui: Ui,
+    #[serde(default)]
+    pub rate_limit: RateLimit,
+    #[serde(default)]
+    pub security: Security,
+    #[serde(default)]
+    pub users: Users,
 }
 
 #[derive(Debug, Clone, Deserialize, Default)]
@@ -39,6 +47,10 @@ pub struct Paths {
 pub struct Login {
     pub default_user: Option<String>,
     pub lock_user: Option<bool>,
+    /// Log this user in after a countdown, once per boot.
+    pub autologin_user: Option<String>,
+    /// Countdown length; any input in the UI cancels it.
+    pub autologin_delay_secs: Option<u64>,
 }
 
 #[derive(Debug, Clone, Deserialize, Default)]
@@ -47,17 +59,59 @@ pub struct Session {
     pub command: Vec<String>,
     #[serde(default)]
     pub env: BTreeMap<String, String>,
+    /// Runs `type = "x11"` sessions: started with the session command
+    /// appended, it brings up an X server and sets DISPLAY/XAUTHORITY.
+    #[serde(default)]
+    pub x11_wrapper: Vec<String>,
+    /// Data dirs searched for `wayland-sessions/` and `xsessions/` before
+    /// `XDG_DATA_DIRS`.
+    #[serde(default)]
+    pub search_paths: Vec<PathBuf>,
 }
 
+/// A `[[sessions]]` entry. With the id of a discovered session it overrides
+/// that session's name, comment, icon, command or type and adds `env`;
+/// otherwise it is a new session and needs a `command`.
 #[derive(Debug, Clone, Deserialize, Default)]
 pub struct SessionEntry {
+    /// Defaults to `name` lowercased, with runs of other characters as `-`.
+    pub id: Option<String>,
+    #[serde(default)]
     pub name: String,
+    pub comment: Option<String>,
+    /// Icon file, or an icon name looked up like a desktop entry's `Icon`.
+    pub icon: Option<String>,
     #[serde(default)]
     pub command: Vec<String>,
+    /// "wayland" (default for new entries) or "x11".
+    #[serde(rename = "type")]
+    pub session_type: Option<String>,
+    /// Like `DesktopNames` in a desktop entry: `XDG_CURRENT_DESKTOP`, joined
+    /// with `:`.
+    #[serde(default)]
+    pub desktop_names: Vec<String>,
     #[serde(default)]
     pub env: BTreeMap<String, String>,
 }
 
+impl SessionEntry {
+    /// `id`, or one derived from `name`; empty if both are.
+    pub fn effective_id(&self) -> String {
+        if let Some(id) = self.id.as_ref().map(|id| id.trim()).filter(|id| !id.is_empty()) {
+            return id.to_string();
+        }
+        let mut id = String::new();
+        for ch in self.name.trim().chars() {
+            if ch.is_alphanumeric() {
+                id.extend(ch.to_lowercase());
+            } else if !id.is_empty() && !id.ends_with('-') {
+                id.push('-');
+            }
+        }
+        id.trim_end_matches('-').to_string()
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Default)]
 pub struct Profile {
     pub id: String,
@@ -86,6 +140,22 @@ pub struct Power {
 pub struct Logging {
     pub dir: Option<PathBuf>,
     pub level: Option<String>,
+    /// "text" (default) or "json" (one object per line).
+    pub format: Option<String>,
+    /// "file" (default), "journald" or "syslog".
+    pub sink: Option<String>,
+    /// "off" (default), "truncate" or "drop": hash usernames and cut or drop
+    /// PAM text.
+    pub privacy: Option<String>,
+    /// Rotate a log file once it grows past this many bytes (0 = never).
+    pub max_size: Option<u64>,
+    /// Rotate a log file once it is this many days old (0 = never); rotated
+    /// files older than this are deleted.
+    pub max_age_days: Option<u64>,
+    /// Rotated files to keep per component.
+    pub keep: Option<u32>,
+    /// Gzip rotated files.
+    pub compress: Option<bool>,
 }
 
 #[derive(Debug, Clone, Deserialize, Default)]
@@ -96,9 +166,56 @@ pub struct Seat {
     pub cage_args: Vec<String>,
 }
 
+#[derive(Debug, Clone, Deserialize, Default)]
+pub struct Security {
+    /// Let the backend run as root (it refuses by default).
+    pub allow_root: Option<bool>,
+    /// Confine the backend with Landlock and seccomp where the kernel allows.
+    pub sandbox: Option<bool>,
+}
+
+/// Which accounts the user picker lists.
+#[derive(Debug, Clone, Deserialize, Default)]
+pub struct Users {
+    /// List login-capable users at all (default true).
+    pub enabled: Option<bool>,
+    /// Only these usernames, if set.
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// Never these usernames.
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+/// Brute-force policy passed to the backend as `TISS_GREETD_RATE_LIMIT_JSON`.
+/// Off unless at least one key is set; `enabled = true` alone turns it on
+/// with the backend's defaults.
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
+pub struct RateLimit {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_attempts: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window_secs: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backoff_secs: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_backoff_secs: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cooldown_secs: Option<u64>,
+    /// Keys not listed above, kept so the launcher can reject typos instead
+    /// of quietly using the defaults.
+    #[serde(flatten, skip_serializing)]
+    pub unknown: BTreeMap<String, toml::Value>,
+}
+
 #[derive(Debug, Clone, Deserialize, Default)]
 pub struct Ui {
     pub show_password_toggle: Option<bool>,
+    /// "stdio" (default) or "socket".
+    pub backend_transport: Option<String>,
+    pub backend_socket: Option<PathBuf>,
 }
 
 impl Config {
@@ -114,6 +231,9 @@ impl Config {
             logging: self.logging.merge(other.logging),
             seat: self.seat.merge(other.seat),
             ui: self.ui.merge(other.ui),
+            rate_limit: self.rate_limit.merge(other.rate_limit),
+            security: self.security.merge(other.security),
+            users: self.users.merge(other.users),
         }
     }
 
@@ -140,6 +260,8 @@ impl Login {
         Login {
             default_user: other.default_user.or(self.default_user),
             lock_user: other.lock_user.or(self.lock_user),
+            autologin_user: other.autologin_user.or(self.autologin_user),
+            autologin_delay_secs: other.autologin_delay_secs.or(self.autologin_delay_secs),
         }
     }
 }
@@ -153,7 +275,22 @@ impl Session {
         } else {
             other.command
         };
-        Session { command, env }
+        let x11_wrapper = if other.x11_wrapper.is_empty() {
+            self.x11_wrapper
+        } else {
+            other.x11_wrapper
+        };
+        let search_paths = if other.search_paths.is_empty() {
+            self.search_paths
+        } else {
+            other.search_paths
+        };
+        Session {
+            command,
+            env,
+            x11_wrapper,
+            search_paths,
+        }
     }
 }
 
@@ -184,6 +321,13 @@ impl Logging {
         Logging {
             dir: other.dir.or(self.dir),
             level: other.level.or(self.level),
+            format: other.format.or(self.format),
+            sink: other.sink.or(self.sink),
+            privacy: other.privacy.or(self.privacy),
+            max_size: other.max_size.or(self.max_size),
+            max_age_days: other.max_age_days.or(self.max_age_days),
+            keep: other.keep.or(self.keep),
+            compress: other.compress.or(self.compress),
         }
     }
 }
@@ -198,10 +342,55 @@ impl Seat {
     }
 }
 
+impl Security {
+    fn merge(self, other: Security) -> Security {
+        Security {
+            allow_root: other.allow_root.or(self.allow_root),
+            sandbox: other.sandbox.or(self.sandbox),
+        }
+    }
+}
+
+impl Users {
+    fn merge(self, other: Users) -> Users {
+        Users {
+            enabled: other.enabled.or(self.enabled),
+            allow: if other.allow.is_empty() { self.allow } else { other.allow },
+            deny: if other.deny.is_empty() { self.deny } else { other.deny },
+        }
+    }
+}
+
+impl RateLimit {
+    fn merge(self, other: RateLimit) -> RateLimit {
+        RateLimit {
+            enabled: other.enabled.or(self.enabled),
+            max_attempts: other.max_attempts.or(self.max_attempts),
+            window_secs: other.window_secs.or(self.window_secs),
+            backoff_secs: other.backoff_secs.or(self.backoff_secs),
+            max_backoff_secs: other.max_backoff_secs.or(self.max_backoff_secs),
+            cooldown_secs: other.cooldown_secs.or(self.cooldown_secs),
+            unknown: self.unknown.into_iter().chain(other.unknown).collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.enabled.is_none()
+            && self.max_attempts.is_none()
+            && self.window_secs.is_none()
+            && self.backoff_secs.is_none()
+            && self.max_backoff_secs.is_none()
+            && self.cooldown_secs.is_none()
+            && self.unknown.is_empty()
+    }
+}
+
 impl Ui {
     fn merge(self, other: Ui) -> Ui {
         Ui {
             show_password_toggle: other.show_password_toggle.or(self.show_password_toggle),
+            backend_transport: other.backend_transport.or(self.backend_transport),
+            backend_socket: other.backend_socket.or(self.backend_socket),
         }
     }
 }
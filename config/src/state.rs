@@ -0,0 +1,126 @@
+//! Layout of the backend's `state.json`. The backend writes it; the launcher
+//! reads it to preselect the last user and their session.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Version 1 (no `version` key) kept a single `last_session_id` /
+/// `last_profile_id` / `last_locale` at the top level.
+pub const STATE_VERSION: u32 = 2;
+
+/// Session, profile and locale last started, for one user or globally.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct RememberedSelection {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+}
+
+impl RememberedSelection {
+    /// Trims every field; blank ones become `None`.
+    pub fn normalize(&mut self) {
+        for value in [&mut self.session_id, &mut self.profile_id, &mut self.locale] {
+            *value = value
+                .take()
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty());
+        }
+    }
+
+    /// `self`, with missing fields taken from `fallback`.
+    pub fn or(&self, fallback: &RememberedSelection) -> RememberedSelection {
+        RememberedSelection {
+            session_id: self.session_id.clone().or_else(|| fallback.session_id.clone()),
+            profile_id: self.profile_id.clone().or_else(|| fallback.profile_id.clone()),
+            locale: self.locale.clone().or_else(|| fallback.locale.clone()),
+        }
+    }
+}
+
+/// Failure history of one user, for the backend's brute-force policy.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FailureRecord {
+    pub failures: u32,
+    pub first_failure: u64,
+    pub last_failure: u64,
+    #[serde(default)]
+    pub locked_until: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PersistedState {
+    /// Missing in version 1 files, hence 0 when absent.
+    #[serde(default)]
+    pub version: u32,
+    /// Whoever logged in last.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_user: Option<String>,
+    /// Most recent selection of anyone; the fallback for users without one.
+    #[serde(default)]
+    pub global: RememberedSelection,
+    /// Per-username selection.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub users: BTreeMap<String, RememberedSelection>,
+    /// Version 1 fields, moved into `global` by [`PersistedState::migrate`].
+    #[serde(default, skip_serializing)]
+    last_session_id: Option<String>,
+    #[serde(default, skip_serializing)]
+    last_profile_id: Option<String>,
+    #[serde(default, skip_serializing)]
+    last_locale: Option<String>,
+    /// Failed logins per username.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub auth_failures: BTreeMap<String, FailureRecord>,
+}
+
+impl Default for PersistedState {
+    fn default() -> Self {
+        PersistedState {
+            version: STATE_VERSION,
+            last_user: None,
+            global: RememberedSelection::default(),
+            users: BTreeMap::new(),
+            last_session_id: None,
+            last_profile_id: None,
+            last_locale: None,
+            auth_failures: BTreeMap::new(),
+        }
+    }
+}
+
+impl PersistedState {
+    /// Brings an older layout up to `STATE_VERSION`; true if anything changed.
+    pub fn migrate(&mut self) -> bool {
+        if self.version >= STATE_VERSION {
+            return false;
+        }
+        let legacy = RememberedSelection {
+            session_id: self.last_session_id.take(),
+            profile_id: self.last_profile_id.take(),
+            locale: self.last_locale.take(),
+        };
+        self.global = self.global.or(&legacy);
+        self.version = STATE_VERSION;
+        true
+    }
+
+    /// Drops blank names and ids, as hand-edited files may have them.
+    pub fn normalize(&mut self) {
+        self.global.normalize();
+        for selection in self.users.values_mut() {
+            selection.normalize();
+        }
+        self.last_user = self.last_user.take().filter(|user| !user.trim().is_empty());
+    }
+
+    /// What `username` chose last, falling back to the global selection.
+    pub fn remembered(&self, username: &str) -> RememberedSelection {
+        match self.users.get(username) {
+            Some(selection) => selection.or(&self.global),
+            None => self.global.clone(),
+        }
+    }
+}
@@ -0,0 +1,118 @@
+/// Characters that need double quotes in an `Exec` argument. Space, tab and
+/// newline separate arguments, `"` starts a quote.
+const RESERVED: &[char] = &[
+    '\'', '\\', '>', '<', '~', '|', '&', ';', '$', '*', '?', '#', '(', ')', '`',
+];
+
+/// Field codes. A session has no files, URLs or icon to pass, so all of
+/// them expand to nothing.
+const FIELD_CODES: &[char] = &[
+    'f', 'F', 'u', 'U', 'i', 'c', 'k', 'd', 'D', 'n', 'N', 'v', 'm',
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExecError {
+    /// Nothing left to run.
+    Empty,
+    UnterminatedQuote,
+    /// A reserved character outside double quotes.
+    Reserved(char),
+    /// `%` followed by something that is not a field code (`None`: at the
+    /// end of the value).
+    UnknownFieldCode(Option<char>),
+    /// Field codes may not appear inside a quoted argument.
+    QuotedFieldCode(char),
+}
+
+impl std::fmt::Display for ExecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExecError::Empty => write!(f, "empty Exec"),
+            ExecError::UnterminatedQuote => write!(f, "unterminated quote in Exec"),
+            ExecError::Reserved(ch) => {
+                write!(f, "reserved character {:?} outside quotes in Exec", ch)
+            }
+            ExecError::UnknownFieldCode(Some(ch)) => write!(f, "unknown field code %{} in Exec", ch),
+            ExecError::UnknownFieldCode(None) => write!(f, "lone % at the end of Exec"),
+            ExecError::QuotedFieldCode(ch) => write!(f, "field code %{} inside quotes in Exec", ch),
+        }
+    }
+}
+
+impl std::error::Error for ExecError {}
+
+/// Splits an `Exec` value, already unescaped as a string, into arguments.
+///
+/// Inside double quotes `\"`, `` \` ``, `\$` and `\\` stand for the character
+/// itself (so a literal backslash is `\\\\` in the file); a backslash before
+/// anything else is kept. `%%` is a literal `%`, also inside quotes. An
+/// unquoted argument that consisted only of field codes is dropped.
+pub fn parse_exec(value: &str) -> Result<Vec<String>, ExecError> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    // Whether `current` is an argument yet: `""` is an empty argument, a
+    // lone `%U` is none.
+    let mut started = false;
+    let mut in_quotes = false;
+    let mut chars = value.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        if in_quotes {
+            match ch {
+                '"' => in_quotes = false,
+                '\\' => match chars.peek() {
+                    Some(&next @ ('"' | '`' | '$' | '\\')) => {
+                        current.push(next);
+                        chars.next();
+                    }
+                    _ => current.push('\\'),
+                },
+                '%' => match chars.next() {
+                    Some('%') => current.push('%'),
+                    Some(code) if FIELD_CODES.contains(&code) => {
+                        return Err(ExecError::QuotedFieldCode(code));
+                    }
+                    other => return Err(ExecError::UnknownFieldCode(other)),
+                },
+                other => current.push(other),
+            }
+            continue;
+        }
+        match ch {
+            ' ' | '\t' | '\n' => {
+                if started {
+                    args.push(std::mem::take(&mut current));
+                    started = false;
+                }
+            }
+            '"' => {
+                in_quotes = true;
+                started = true;
+            }
+            '%' => match chars.next() {
+                Some('%') => {
+                    current.push('%');
+                    started = true;
+                }
+                Some(code) if FIELD_CODES.contains(&code) => {}
+                other => return Err(ExecError::UnknownFieldCode(other)),
+            },
+            other if RESERVED.contains(&other) => return Err(ExecError::Reserved(other)),
+            other => {
+                current.push(other);
+                started = true;
+            }
+        }
+    }
+
+    if in_quotes {
+        return Err(ExecError::UnterminatedQuote);
+    }
+    if started {
+        args.push(current);
+    }
+    if args.first().is_none_or(|program| program.is_empty()) {
+        return Err(ExecError::Empty);
+    }
+    Ok(args)
+}
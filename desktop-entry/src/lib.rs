@@ -0,0 +1,303 @@
+//! Desktop entries as described by the freedesktop.org Desktop Entry
+//! Specification, as far as session files need them.
+//!
+//! [`DesktopEntry::parse`] reads the `[Desktop Entry]` group; the accessors
+//! apply the value escapes, pick localized keys for a [`Locale`] and split
+//! `Exec` into arguments with [`parse_exec`]. Parsing is lenient where real
+//! files are sloppy (unknown escapes are kept, a repeated key replaces the
+//! earlier one, other groups are skipped unread) and strict where the result
+//! gets run: a malformed line in `[Desktop Entry]` or an `Exec` that breaks
+//! the quoting rules is an error, not a guess.
+
+use std::collections::BTreeMap;
+use std::env;
+
+mod exec;
+
+pub use exec::{parse_exec, ExecError};
+
+const GROUP: &str = "[Desktop Entry]";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// The file has no `[Desktop Entry]` group.
+    MissingGroup,
+    /// A line in `[Desktop Entry]` that is not a comment, a group header or
+    /// `key=value`, or a key with characters outside `A-Za-z0-9-`. `line`
+    /// counts from 1.
+    InvalidLine { line: usize },
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::MissingGroup => write!(f, "no [Desktop Entry] group"),
+            ParseError::InvalidLine { line } => {
+                write!(f, "line {}: not a comment, group header or key=value", line)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// The `[Desktop Entry]` group of a desktop file. Other groups (actions,
+/// vendor extensions) are skipped without being checked, so a broken one
+/// does not hide the entry.
+#[derive(Debug, Clone, Default)]
+pub struct DesktopEntry {
+    /// Keys as written, including any `[locale]` suffix; values still
+    /// escaped.
+    values: BTreeMap<String, String>,
+}
+
+impl DesktopEntry {
+    pub fn parse(content: &str) -> Result<DesktopEntry, ParseError> {
+        let mut values = BTreeMap::new();
+        let mut found = false;
+        let mut in_entry = false;
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with('[') && line.ends_with(']') {
+                in_entry = line == GROUP;
+                found |= in_entry;
+                continue;
+            }
+            if !in_entry {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(ParseError::InvalidLine { line: index + 1 });
+            };
+            let key = key.trim_end();
+            if !valid_key(key) {
+                return Err(ParseError::InvalidLine { line: index + 1 });
+            }
+            values.insert(key.to_string(), value.trim_start().to_string());
+        }
+        if !found {
+            return Err(ParseError::MissingGroup);
+        }
+        Ok(DesktopEntry { values })
+    }
+
+    /// The value exactly as written.
+    pub fn raw(&self, key: &str) -> Option<&str> {
+        self.values.get(key).map(String::as_str)
+    }
+
+    /// A `string` value with `\s`, `\n`, `\t`, `\r` and `\\` decoded.
+    pub fn string(&self, key: &str) -> Option<String> {
+        self.raw(key).map(unescape)
+    }
+
+    /// A `localestring` value: the best `key[...]` for `locale`, else the
+    /// plain `key`.
+    pub fn locale_string(&self, key: &str, locale: Option<&Locale>) -> Option<String> {
+        if let Some(locale) = locale {
+            for candidate in locale.candidates() {
+                if let Some(value) = self.string(&format!("{}[{}]", key, candidate)) {
+                    return Some(value);
+                }
+            }
+        }
+        self.string(key)
+    }
+
+    /// `true` or `false`; `1` and `0` are accepted as well, anything else
+    /// reads as missing.
+    pub fn boolean(&self, key: &str) -> Option<bool> {
+        match self.raw(key)? {
+            "true" | "1" => Some(true),
+            "false" | "0" => Some(false),
+            _ => None,
+        }
+    }
+
+    /// A `;`-separated list; `\;` is a literal semicolon. Empty items are
+    /// dropped.
+    pub fn strings(&self, key: &str) -> Vec<String> {
+        self.raw(key).map(split_list).unwrap_or_default()
+    }
+
+    /// `Type`: `Application`, `Link` or `Directory` (session files also use
+    /// `XSession`).
+    pub fn entry_type(&self) -> Option<String> {
+        self.string("Type")
+    }
+
+    pub fn name(&self, locale: Option<&Locale>) -> Option<String> {
+        self.locale_string("Name", locale)
+    }
+
+    pub fn comment(&self, locale: Option<&Locale>) -> Option<String> {
+        self.locale_string("Comment", locale)
+    }
+
+    /// An absolute path or an icon theme name.
+    pub fn icon(&self) -> Option<String> {
+        self.string("Icon")
+    }
+
+    /// `Exec` split into arguments; `None` if the key is missing.
+    pub fn exec(&self) -> Option<Result<Vec<String>, ExecError>> {
+        self.string("Exec").map(|value| parse_exec(&value))
+    }
+
+    pub fn try_exec(&self) -> Option<String> {
+        self.string("TryExec")
+    }
+
+    pub fn hidden(&self) -> bool {
+        self.boolean("Hidden").unwrap_or(false)
+    }
+
+    pub fn no_display(&self) -> bool {
+        self.boolean("NoDisplay").unwrap_or(false)
+    }
+
+    pub fn desktop_names(&self) -> Vec<String> {
+        self.strings("DesktopNames")
+    }
+
+    /// `X-*` keys without a locale suffix, values unescaped.
+    pub fn extensions(&self) -> BTreeMap<String, String> {
+        self.values
+            .iter()
+            .filter(|(key, _)| key.starts_with("X-") && !key.contains('['))
+            .map(|(key, value)| (key.clone(), unescape(value)))
+            .collect()
+    }
+}
+
+/// `lang_COUNTRY.ENCODING@MODIFIER`, as in `LC_MESSAGES`. The encoding plays
+/// no part in matching keys.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Locale {
+    pub lang: String,
+    pub country: Option<String>,
+    pub modifier: Option<String>,
+}
+
+impl Locale {
+    /// `None` for an empty value and for `C`/`POSIX`, which have no
+    /// translations.
+    pub fn parse(value: &str) -> Option<Locale> {
+        let value = value.trim();
+        let (rest, modifier) = match value.split_once('@') {
+            Some((rest, modifier)) => (rest, Some(modifier)),
+            None => (value, None),
+        };
+        let rest = rest.split('.').next().unwrap_or_default();
+        let (lang, country) = match rest.split_once('_') {
+            Some((lang, country)) => (lang, Some(country)),
+            None => (rest, None),
+        };
+        if lang.is_empty() || lang == "C" || lang == "POSIX" {
+            return None;
+        }
+        let non_empty = |part: Option<&str>| part.filter(|part| !part.is_empty()).map(str::to_string);
+        Some(Locale {
+            lang: lang.to_string(),
+            country: non_empty(country),
+            modifier: non_empty(modifier),
+        })
+    }
+
+    /// From `LC_ALL`, `LC_MESSAGES` or `LANG`, whichever is set first.
+    pub fn from_env() -> Option<Locale> {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|name| env::var(name).ok())
+            .find(|value| !value.trim().is_empty())
+            .and_then(|value| Locale::parse(&value))
+    }
+
+    /// Key suffixes to try, best match first: `lang_COUNTRY@MODIFIER`,
+    /// `lang_COUNTRY`, `lang@MODIFIER`, `lang`.
+    pub fn candidates(&self) -> Vec<String> {
+        let mut candidates = Vec::new();
+        if let (Some(country), Some(modifier)) = (&self.country, &self.modifier) {
+            candidates.push(format!("{}_{}@{}", self.lang, country, modifier));
+        }
+        if let Some(country) = &self.country {
+            candidates.push(format!("{}_{}", self.lang, country));
+        }
+        if let Some(modifier) = &self.modifier {
+            candidates.push(format!("{}@{}", self.lang, modifier));
+        }
+        candidates.push(self.lang.clone());
+        candidates
+    }
+}
+
+/// `A-Za-z0-9-`, optionally followed by `[locale]`.
+fn valid_key(key: &str) -> bool {
+    let (name, locale) = match key.split_once('[') {
+        Some((name, rest)) => match rest.strip_suffix(']') {
+            Some(locale) => (name, Some(locale)),
+            None => return false,
+        },
+        None => (key, None),
+    };
+    !name.is_empty()
+        && name.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '-')
+        && locale.is_none_or(|locale| {
+            !locale.is_empty() && !locale.contains(|ch: char| ch == '[' || ch == ']' || ch.is_whitespace())
+        })
+}
+
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some(next) => push_escape(&mut out, next),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+fn push_escape(out: &mut String, ch: char) {
+    match ch {
+        's' => out.push(' '),
+        'n' => out.push('\n'),
+        't' => out.push('\t'),
+        'r' => out.push('\r'),
+        '\\' => out.push('\\'),
+        // Not a string escape; leave it for whoever reads the value next
+        // (`Exec` quoting uses `\"`, `\$` and friends).
+        other => {
+            out.push('\\');
+            out.push(other);
+        }
+    }
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut current = String::new();
+    let mut chars = value.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => match chars.next() {
+                Some(';') => current.push(';'),
+                Some(next) => push_escape(&mut current, next),
+                None => current.push('\\'),
+            },
+            ';' => items.push(std::mem::take(&mut current)),
+            other => current.push(other),
+        }
+    }
+    items.push(current);
+    items.retain(|item| !item.is_empty());
+    items
+}
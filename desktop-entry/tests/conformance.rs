@@ -0,0 +1,92 @@
+use serde_json::{json, Map, Value};
+use std::fs;
+use std::path::PathBuf;
+use tiss_greetd_desktop_entry::{parse_exec, DesktopEntry, ExecError, Locale};
+
+fn corpus() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/corpus")
+}
+
+/// What the parser makes of `content`, in the shape of the `.json` files.
+fn observe(content: &str, locale: Option<&Locale>) -> Value {
+    let entry = match DesktopEntry::parse(content) {
+        Ok(entry) => entry,
+        Err(err) => return json!({ "error": err.to_string() }),
+    };
+    let mut observed = json!({
+        "type": entry.entry_type(),
+        "name": entry.name(locale),
+        "comment": entry.comment(locale),
+        "icon": entry.icon(),
+        "try_exec": entry.try_exec(),
+        "hidden": entry.hidden(),
+        "no_display": entry.no_display(),
+        "desktop_names": entry.desktop_names(),
+        "extensions": entry.extensions(),
+    });
+    match entry.exec() {
+        Some(Ok(args)) => observed["exec"] = json!(args),
+        Some(Err(err)) => observed["exec_error"] = json!(err.to_string()),
+        None => observed["exec"] = Value::Null,
+    }
+    observed
+}
+
+/// Every `corpus/*.desktop` is parsed and compared with the keys listed in
+/// its `.json` twin; `locale` there is the input `LC_MESSAGES`.
+#[test]
+fn corpus_matches_expectations() {
+    let mut cases: Vec<PathBuf> = fs::read_dir(corpus())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().and_then(|ext| ext.to_str()) == Some("desktop"))
+        .collect();
+    cases.sort();
+    assert!(cases.len() >= 20, "corpus went missing: {:?}", cases);
+
+    let mut failures = Vec::new();
+    for case in &cases {
+        let content = fs::read_to_string(case).unwrap();
+        let expected: Map<String, Value> =
+            serde_json::from_str(&fs::read_to_string(case.with_extension("json")).unwrap())
+                .unwrap_or_else(|err| panic!("{}: {}", case.display(), err));
+        let locale = expected
+            .get("locale")
+            .and_then(Value::as_str)
+            .and_then(Locale::parse);
+        let observed = observe(&content, locale.as_ref());
+        for (key, want) in expected.iter().filter(|(key, _)| *key != "locale") {
+            let got = observed.get(key).unwrap_or(&Value::Null);
+            if got != want {
+                failures.push(format!(
+                    "{}: {}: expected {}, got {}",
+                    case.file_name().unwrap().to_string_lossy(),
+                    key,
+                    want,
+                    got
+                ));
+            }
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+fn locales_parse_into_lookup_order() {
+    let locale = Locale::parse("sr_RS.UTF-8@latin").unwrap();
+    assert_eq!(locale.candidates(), ["sr_RS@latin", "sr_RS", "sr@latin", "sr"]);
+    assert_eq!(Locale::parse("de").unwrap().candidates(), ["de"]);
+    assert_eq!(Locale::parse("C"), None);
+    assert_eq!(Locale::parse("POSIX"), None);
+    assert_eq!(Locale::parse(""), None);
+}
+
+#[test]
+fn exec_reserved_characters_need_quotes() {
+    for reserved in ['\'', '\\', '>', '<', '~', '|', '&', ';', '$', '*', '?', '#', '(', ')', '`'] {
+        let unquoted = format!("app a{}b", reserved);
+        assert_eq!(parse_exec(&unquoted), Err(ExecError::Reserved(reserved)), "{}", unquoted);
+        let quoted = format!("app \"a{}b\"", reserved);
+        assert!(parse_exec(&quoted).is_ok(), "{}", quoted);
+    }
+}
@@ -0,0 +1,365 @@
+//! greetd IPC: the length-prefixed JSON wire format and a typed client.
+//!
+//! Every message is a native-endian `u32` length followed by that many bytes
+//! of JSON. [`Client`] works over any `Read + Write` transport and tracks the
+//! session state so that requests sent out of order are rejected before they
+//! reach greetd.
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use zeroize::Zeroize;
+
+mod secret;
+
+pub use secret::{lock_capacity, lock_memory, Secret};
+
+/// Largest frame [`read_request`] and [`read_response`] accept. greetd's
+/// messages are small; a bigger length prefix is refused before anything is
+/// allocated for it.
+pub const MAX_FRAME_BYTES: usize = 64 * 1024;
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Request {
+    CreateSession {
+        username: String,
+    },
+    PostAuthMessageResponse {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        response: Option<Secret>,
+    },
+    StartSession {
+        cmd: Vec<String>,
+        #[serde(default)]
+        env: Vec<String>,
+    },
+    CancelSession,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Response {
+    Success,
+    Error {
+        error_type: String,
+        description: String,
+    },
+    AuthMessage {
+        auth_message_type: AuthMessageType,
+        auth_message: String,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Copy, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMessageType {
+    Visible,
+    Secret,
+    Info,
+    Error,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    PayloadTooLarge(usize),
+    /// The call is not legal in the current [`SessionState`].
+    InvalidState {
+        operation: &'static str,
+        state: SessionState,
+    },
+    /// greetd answered with a response that cannot follow the request.
+    UnexpectedResponse {
+        request: RequestKind,
+        response: Response,
+    },
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "greetd io: {}", err),
+            Error::Json(err) => write!(f, "greetd json: {}", err),
+            Error::PayloadTooLarge(len) => write!(f, "greetd payload too large: {} bytes", len),
+            Error::InvalidState { operation, state } => {
+                write!(f, "{} not allowed in state {:?}", operation, state)
+            }
+            Error::UnexpectedResponse { request, response } => {
+                write!(f, "unexpected greetd response to {:?}: {:?}", request, response)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Json(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Json(err)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Serializes into a buffer sized for the worst case up front, so it is never
+/// reallocated (leaving copies behind), and wipes it after writing.
+fn write_frame<T: Serialize>(writer: &mut impl Write, message: &T, capacity: usize) -> Result<()> {
+    let mut payload = Vec::with_capacity(capacity);
+    lock_capacity(&mut payload);
+    let result = (|| {
+        serde_json::to_writer(&mut payload, message)?;
+        let len = u32::try_from(payload.len()).map_err(|_| Error::PayloadTooLarge(payload.len()))?;
+        writer.write_all(&len.to_ne_bytes())?;
+        writer.write_all(&payload)?;
+        writer.flush()?;
+        Ok(())
+    })();
+    payload.zeroize();
+    result
+}
+
+fn read_frame<T: for<'de> Deserialize<'de>>(reader: &mut impl Read) -> Result<T> {
+    let mut len_buf = [0u8; 4];
+    reader.read_exact(&mut len_buf)?;
+    let len = u32::from_ne_bytes(len_buf) as usize;
+    if len > MAX_FRAME_BYTES {
+        return Err(Error::PayloadTooLarge(len));
+    }
+    let mut payload = vec![0u8; len];
+    let result = reader.read_exact(&mut payload);
+    let message = result.map(|_| serde_json::from_slice(&payload));
+    payload.zeroize();
+    Ok(message??)
+}
+
+/// Writes one request frame. A [`Secret`] in `req` is wiped when `req` is
+/// dropped here.
+pub fn write_request(writer: &mut impl Write, req: Request) -> Result<()> {
+    // Escaping can turn one byte into six (`\u00XX`).
+    let secret_len = match &req {
+        Request::PostAuthMessageResponse {
+            response: Some(response),
+        } => response.len(),
+        _ => 0,
+    };
+    write_frame(writer, &req, 256 + secret_len * 6)
+}
+
+pub fn read_request(reader: &mut impl Read) -> Result<Request> {
+    read_frame(reader)
+}
+
+pub fn write_response(writer: &mut impl Write, resp: &Response) -> Result<()> {
+    write_frame(writer, resp, 256)
+}
+
+pub fn read_response(reader: &mut impl Read) -> Result<Response> {
+    read_frame(reader)
+}
+
+/// Where a [`Client`] is in the greetd session lifecycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionState {
+    /// No session; `create_session` is allowed.
+    Idle,
+    /// A request is in flight; only `read_response` (or `cancel_session`).
+    AwaitingResponse,
+    /// Session created and greetd sent an auth message that needs a
+    /// `post_auth_message_response`.
+    Created,
+    /// Authentication succeeded; `start_session` is allowed.
+    Authenticated,
+    /// greetd accepted `start_session`.
+    Started,
+    /// greetd reported an error; only `cancel_session` is allowed.
+    Failed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestKind {
+    CreateSession,
+    PostAuthMessageResponse,
+    StartSession,
+    CancelSession,
+}
+
+/// Typed greetd client over any byte stream.
+///
+/// Requests are written immediately; responses are read with
+/// [`Client::read_response`], which applies the state transition. This split
+/// lets callers poll the underlying transport between the two.
+pub struct Client<S> {
+    stream: S,
+    state: SessionState,
+    pending: VecDeque<RequestKind>,
+}
+
+impl Client<UnixStream> {
+    pub fn connect(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Client::new(UnixStream::connect(path)?))
+    }
+
+    /// Connects to the socket named by `GREETD_SOCK`.
+    pub fn connect_env() -> Result<Self> {
+        let path = std::env::var_os("GREETD_SOCK").ok_or_else(|| {
+            Error::Io(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "GREETD_SOCK not set",
+            ))
+        })?;
+        Client::connect(path)
+    }
+}
+
+impl<S: Read + Write> Client<S> {
+    pub fn new(stream: S) -> Self {
+        Client {
+            stream,
+            state: SessionState::Idle,
+            pending: VecDeque::new(),
+        }
+    }
+
+    pub fn state(&self) -> SessionState {
+        self.state
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    pub fn create_session(&mut self, username: &str) -> Result<()> {
+        self.expect_state("create_session", &[SessionState::Idle])?;
+        self.send(
+            RequestKind::CreateSession,
+            Request::CreateSession {
+                username: username.to_string(),
+            },
+        )
+    }
+
+    /// Answers the pending auth message. The response is wiped once it has
+    /// been written (or rejected).
+    pub fn post_auth_message_response(&mut self, response: Option<Secret>) -> Result<()> {
+        self.expect_state("post_auth_message_response", &[SessionState::Created])?;
+        self.send(
+            RequestKind::PostAuthMessageResponse,
+            Request::PostAuthMessageResponse { response },
+        )
+    }
+
+    pub fn start_session(&mut self, cmd: Vec<String>, env: Vec<String>) -> Result<()> {
+        self.expect_state("start_session", &[SessionState::Authenticated])?;
+        self.send(RequestKind::StartSession, Request::StartSession { cmd, env })
+    }
+
+    /// Cancels the session. Allowed in every state except `Idle`, including
+    /// while another response is still outstanding.
+    pub fn cancel_session(&mut self) -> Result<()> {
+        if self.state == SessionState::Idle {
+            return Err(Error::InvalidState {
+                operation: "cancel_session",
+                state: self.state,
+            });
+        }
+        self.send(RequestKind::CancelSession, Request::CancelSession)
+    }
+
+    /// Reads the response to the oldest outstanding request.
+    pub fn read_response(&mut self) -> Result<Response> {
+        let Some(request) = self.pending.front().copied() else {
+            return Err(Error::InvalidState {
+                operation: "read_response",
+                state: self.state,
+            });
+        };
+        let response = read_response(&mut self.stream).map_err(|err| {
+            // The rest of the frame is still unread; the stream is out of step.
+            if matches!(err, Error::PayloadTooLarge(_)) {
+                self.state = SessionState::Failed;
+            }
+            err
+        })?;
+        self.pending.pop_front();
+        let next = transition(request, &response).ok_or_else(|| {
+            self.state = SessionState::Failed;
+            Error::UnexpectedResponse {
+                request,
+                response: response.clone(),
+            }
+        })?;
+        if self.pending.is_empty() {
+            self.state = next;
+        }
+        Ok(response)
+    }
+
+    fn expect_state(&self, operation: &'static str, allowed: &[SessionState]) -> Result<()> {
+        if allowed.contains(&self.state) {
+            Ok(())
+        } else {
+            Err(Error::InvalidState {
+                operation,
+                state: self.state,
+            })
+        }
+    }
+
+    fn send(&mut self, kind: RequestKind, req: Request) -> Result<()> {
+        write_request(&mut self.stream, req)?;
+        self.pending.push_back(kind);
+        self.state = SessionState::AwaitingResponse;
+        Ok(())
+    }
+}
+
+fn transition(request: RequestKind, response: &Response) -> Option<SessionState> {
+    match (request, response) {
+        (RequestKind::CreateSession | RequestKind::PostAuthMessageResponse, Response::Success) => {
+            Some(SessionState::Authenticated)
+        }
+        (
+            RequestKind::CreateSession | RequestKind::PostAuthMessageResponse,
+            Response::AuthMessage { .. },
+        ) => Some(SessionState::Created),
+        (
+            RequestKind::CreateSession
+            | RequestKind::PostAuthMessageResponse
+            | RequestKind::StartSession,
+            Response::Error { .. },
+        ) => Some(SessionState::Failed),
+        (RequestKind::StartSession, Response::Success) => Some(SessionState::Started),
+        (RequestKind::CancelSession, Response::Success | Response::Error { .. }) => {
+            Some(SessionState::Idle)
+        }
+        (RequestKind::StartSession | RequestKind::CancelSession, Response::AuthMessage { .. }) => {
+            None
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! A string that is wiped from memory when dropped.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use zeroize::Zeroize;
+
+/// Locks the pages under `buf` into RAM so they cannot be swapped out.
+/// Best effort: returns false if `mlock` fails (e.g. `RLIMIT_MEMLOCK`).
+///
+/// Pages are never unlocked again. Allocations share pages, so unlocking one
+/// buffer could unlock another secret's page; the contents are wiped on drop
+/// either way.
+pub fn lock_memory(buf: &[u8]) -> bool {
+    if buf.is_empty() {
+        return true;
+    }
+    unsafe { libc::mlock(buf.as_ptr() as *const libc::c_void, buf.len()) == 0 }
+}
+
+/// [`lock_memory`] for the whole capacity of `buf`, not just its length.
+pub fn lock_capacity(buf: &mut Vec<u8>) -> bool {
+    let spare = buf.spare_capacity_mut();
+    let end = spare.as_ptr() as usize + spare.len();
+    let start = buf.as_ptr() as usize;
+    if end == start {
+        return true;
+    }
+    unsafe { libc::mlock(start as *const libc::c_void, end - start) == 0 }
+}
+
+/// Password or other PAM answer. The buffer is locked in RAM where possible,
+/// zeroed on drop, and `Debug` never shows the contents. There is
+/// deliberately no `Clone` or `Display`.
+#[derive(Default, PartialEq, Eq)]
+pub struct Secret(String);
+
+impl Secret {
+    /// Takes ownership of `value` without copying it.
+    pub fn new(value: String) -> Self {
+        Secret::locked(value)
+    }
+
+    fn locked(mut value: String) -> Self {
+        unsafe {
+            lock_capacity(value.as_mut_vec());
+        }
+        Secret(value)
+    }
+
+    /// Copies `bytes` into a new secret; invalid UTF-8 is replaced.
+    pub fn from_utf8(bytes: &[u8]) -> Self {
+        match std::str::from_utf8(bytes) {
+            Ok(text) => Secret::copy_of(text),
+            Err(_) => Secret::locked(String::from_utf8_lossy(bytes).into_owned()),
+        }
+    }
+
+    fn copy_of(text: &str) -> Self {
+        let mut secret = Secret::locked(String::with_capacity(text.len()));
+        secret.0.push_str(text);
+        secret
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Decodes a JSON string literal (including the quotes) straight into a
+    /// secret, so escaped characters never pass through serde's scratch
+    /// buffers. Use it with `serde_json::value::RawValue`.
+    pub fn from_json_literal(raw: &str) -> Result<Self, String> {
+        let inner = raw
+            .trim()
+            .strip_prefix('"')
+            .and_then(|rest| rest.strip_suffix('"'))
+            .ok_or_else(|| "expected a JSON string".to_string())?;
+        // Decoding never grows the text, so `out` is never reallocated; on
+        // error it is dropped (and wiped) like any other secret.
+        let mut out = Secret::locked(String::with_capacity(inner.len()));
+        let mut chars = inner.chars();
+        while let Some(ch) = chars.next() {
+            if ch != '\\' {
+                out.0.push(ch);
+                continue;
+            }
+            let decoded = match chars.next() {
+                Some('"') => '"',
+                Some('\\') => '\\',
+                Some('/') => '/',
+                Some('b') => '\u{8}',
+                Some('f') => '\u{c}',
+                Some('n') => '\n',
+                Some('r') => '\r',
+                Some('t') => '\t',
+                Some('u') => {
+                    let high = read_hex4(&mut chars)?;
+                    let code = if (0xD800..0xDC00).contains(&high) {
+                        if chars.next() != Some('\\') || chars.next() != Some('u') {
+                            return Err("unpaired surrogate in string".to_string());
+                        }
+                        let low = read_hex4(&mut chars)?;
+                        if !(0xDC00..0xE000).contains(&low) {
+                            return Err("invalid surrogate pair in string".to_string());
+                        }
+                        0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+                    } else {
+                        high
+                    };
+                    char::from_u32(code)
+                        .ok_or_else(|| "invalid unicode escape in string".to_string())?
+                }
+                _ => {
+                    return Err("invalid escape in string".to_string());
+                }
+            };
+            out.0.push(decoded);
+        }
+        Ok(out)
+    }
+}
+
+fn read_hex4(chars: &mut std::str::Chars<'_>) -> Result<u32, String> {
+    let mut code = 0;
+    for _ in 0..4 {
+        let digit = chars
+            .next()
+            .and_then(|ch| ch.to_digit(16))
+            .ok_or_else(|| "invalid unicode escape in string".to_string())?;
+        code = code * 16 + digit;
+    }
+    Ok(code)
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Secret(<redacted>)")
+    }
+}
+
+impl From<String> for Secret {
+    fn from(value: String) -> Self {
+        Secret::new(value)
+    }
+}
+
+impl From<&str> for Secret {
+    fn from(value: &str) -> Self {
+        Secret::copy_of(value)
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+/// Generic deserialization copies from whatever buffer the deserializer
+/// hands out; callers that control parsing should prefer
+/// [`Secret::from_json_literal`].
+impl<'de> Deserialize<'de> for Secret {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl serde::de::Visitor<'_> for Visitor {
+            type Value = Secret;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str("a string")
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<Secret, E> {
+                Ok(Secret::copy_of(value))
+            }
+
+            fn visit_string<E>(self, value: String) -> Result<Secret, E> {
+                Ok(Secret::new(value))
+            }
+        }
+
+        deserializer.deserialize_string(Visitor)
+    }
+}
@@ -0,0 +1,167 @@
+use std::io::{Cursor, Read, Write};
+use tiss_greetd_ipc::{
+    read_request, read_response, write_response, AuthMessageType, Client, Error, Request, Response,
+    Secret, SessionState, MAX_FRAME_BYTES,
+};
+
+/// greetd's side of the socket: canned responses to read, requests written.
+#[derive(Default)]
+struct Pipe {
+    responses: Cursor<Vec<u8>>,
+    requests: Vec<u8>,
+}
+
+impl Pipe {
+    fn with(responses: &[Response]) -> Pipe {
+        let mut buf = Vec::new();
+        for response in responses {
+            write_response(&mut buf, response).unwrap();
+        }
+        Pipe {
+            responses: Cursor::new(buf),
+            requests: Vec::new(),
+        }
+    }
+
+    fn raw(bytes: Vec<u8>) -> Pipe {
+        Pipe {
+            responses: Cursor::new(bytes),
+            requests: Vec::new(),
+        }
+    }
+
+    fn sent(&self) -> Vec<Request> {
+        let mut reader = Cursor::new(&self.requests);
+        let mut sent = Vec::new();
+        while (reader.position() as usize) < self.requests.len() {
+            sent.push(read_request(&mut reader).unwrap());
+        }
+        sent
+    }
+}
+
+impl Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.responses.read(buf)
+    }
+}
+
+impl Write for Pipe {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.requests.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn password_prompt() -> Response {
+    Response::AuthMessage {
+        auth_message_type: AuthMessageType::Secret,
+        auth_message: "Password:".to_string(),
+    }
+}
+
+fn assert_invalid(result: tiss_greetd_ipc::Result<impl std::fmt::Debug>, op: &str, in_state: SessionState) {
+    match result {
+        Err(Error::InvalidState { operation, state }) => {
+            assert_eq!(operation, op);
+            assert_eq!(state, in_state);
+        }
+        other => panic!("{}: expected InvalidState, got {:?}", op, other),
+    }
+}
+
+#[test]
+fn calls_out_of_order_are_rejected() {
+    let mut client = Client::new(Pipe::default());
+    assert_eq!(client.state(), SessionState::Idle);
+
+    assert_invalid(client.start_session(vec!["sway".to_string()], Vec::new()), "start_session", SessionState::Idle);
+    assert_invalid(
+        client.post_auth_message_response(Some(Secret::from("hunter2"))),
+        "post_auth_message_response",
+        SessionState::Idle,
+    );
+    assert_invalid(client.read_response(), "read_response", SessionState::Idle);
+    assert_invalid(client.cancel_session(), "cancel_session", SessionState::Idle);
+
+    // Nothing reached greetd and the client is still usable.
+    assert!(client.get_ref().sent().is_empty());
+    assert_eq!(client.state(), SessionState::Idle);
+}
+
+#[test]
+fn start_session_needs_authentication() {
+    let mut client = Client::new(Pipe::with(&[password_prompt()]));
+    client.create_session("alice").unwrap();
+    assert_eq!(client.state(), SessionState::AwaitingResponse);
+    assert_invalid(client.create_session("bob"), "create_session", SessionState::AwaitingResponse);
+    client.read_response().unwrap();
+    assert_eq!(client.state(), SessionState::Created);
+
+    assert_invalid(client.start_session(vec!["sway".to_string()], Vec::new()), "start_session", SessionState::Created);
+    assert_invalid(client.read_response(), "read_response", SessionState::Created);
+}
+
+#[test]
+fn created_authenticated_started() {
+    let mut client = Client::new(Pipe::with(&[password_prompt(), Response::Success, Response::Success]));
+
+    client.create_session("alice").unwrap();
+    assert_eq!(client.read_response().unwrap(), password_prompt());
+    assert_eq!(client.state(), SessionState::Created);
+
+    client.post_auth_message_response(Some(Secret::from("hunter2"))).unwrap();
+    assert_eq!(client.read_response().unwrap(), Response::Success);
+    assert_eq!(client.state(), SessionState::Authenticated);
+
+    client
+        .start_session(vec!["sway".to_string()], vec!["XDG_SESSION_TYPE=wayland".to_string()])
+        .unwrap();
+    assert_eq!(client.read_response().unwrap(), Response::Success);
+    assert_eq!(client.state(), SessionState::Started);
+
+    let sent = client.get_ref().sent();
+    assert_eq!(sent.len(), 3);
+    assert_eq!(
+        sent[0],
+        Request::CreateSession {
+            username: "alice".to_string()
+        }
+    );
+    assert_eq!(
+        sent[1],
+        Request::PostAuthMessageResponse {
+            response: Some(Secret::from("hunter2"))
+        }
+    );
+    assert_eq!(
+        sent[2],
+        Request::StartSession {
+            cmd: vec!["sway".to_string()],
+            env: vec!["XDG_SESSION_TYPE=wayland".to_string()],
+        }
+    );
+}
+
+#[test]
+fn oversized_frame_is_refused() {
+    // A length prefix just over the cap, with no payload behind it: the
+    // error must come from the prefix, not from a short read.
+    let too_big = (MAX_FRAME_BYTES as u32 + 1).to_ne_bytes().to_vec();
+    match read_response(&mut Pipe::raw(too_big.clone())) {
+        Err(Error::PayloadTooLarge(len)) => assert_eq!(len, MAX_FRAME_BYTES + 1),
+        other => panic!("expected PayloadTooLarge, got {:?}", other),
+    }
+    assert!(matches!(
+        read_response(&mut Pipe::raw(u32::MAX.to_ne_bytes().to_vec())),
+        Err(Error::PayloadTooLarge(_))
+    ));
+
+    let mut client = Client::new(Pipe::raw(too_big));
+    client.create_session("alice").unwrap();
+    assert!(matches!(client.read_response(), Err(Error::PayloadTooLarge(_))));
+    assert_eq!(client.state(), SessionState::Failed);
+}
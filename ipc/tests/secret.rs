@@ -0,0 +1,56 @@
+use tiss_greetd_ipc::{Request, Secret};
+
+#[test]
+fn debug_never_shows_the_value() {
+    let secret = Secret::from("hunter2");
+    assert_eq!(format!("{:?}", secret), "Secret(<redacted>)");
+    let req = Request::PostAuthMessageResponse {
+        response: Some(secret),
+    };
+    assert!(!format!("{:?}", req).contains("hunter2"));
+}
+
+#[test]
+fn json_literals_decode_like_serde_json() {
+    for literal in [
+        r#""hunter2""#,
+        r#""quote \" backslash \\ slash \/""#,
+        r#""\b\f\n\r\t""#,
+        r#""café €""#,
+        r#""emoji 😀""#,
+        r#""plain ünïcödé""#,
+    ] {
+        let expected: String = serde_json::from_str(literal).unwrap();
+        let secret = Secret::from_json_literal(literal).unwrap();
+        assert_eq!(secret.expose(), expected, "{}", literal);
+    }
+}
+
+#[test]
+fn malformed_literals_are_rejected() {
+    for literal in [
+        "hunter2",
+        r#""bad \x escape""#,
+        r#""short \u12""#,
+        r#""lone \ud83d""#,
+        "42",
+    ] {
+        assert!(Secret::from_json_literal(literal).is_err(), "{}", literal);
+    }
+}
+
+#[test]
+fn requests_serialize_the_secret_value() {
+    let mut buf = Vec::new();
+    let req = Request::PostAuthMessageResponse {
+        response: Some(Secret::from("hunter2")),
+    };
+    tiss_greetd_ipc::write_request(&mut buf, req).unwrap();
+    let read = tiss_greetd_ipc::read_request(&mut buf.as_slice()).unwrap();
+    match read {
+        Request::PostAuthMessageResponse {
+            response: Some(response),
+        } => assert_eq!(response.expose(), "hunter2"),
+        other => panic!("unexpected request {:?}", other),
+    }
+}
@@ -7,6 +7,8 @@ use std::path::PathBuf;
 use std::thread;
 use std::time::{Duration, SystemTime};
 
+use tiss_greetd_logging::Logger;
+
 #[derive(Debug, Serialize)]
 struct Appearance {
     accent: String,
@@ -51,15 +53,14 @@ fn main() {
         }
     };
 
-    if options.watch {
-        if let Err(err) = run_watch(&options) {
-            eprintln!("tiss-greetd-appearance: {}", err);
-            std::process::exit(1);
-        }
-        return;
-    }
-
-    if let Err(err) = run_once(&options) {
+    let mut log = Logger::new("appearance");
+    let result = if options.watch {
+        run_watch(&options, &mut log)
+    } else {
+        run_once(&options, &mut log)
+    };
+    if let Err(err) = result {
+        log.error(&err);
         eprintln!("tiss-greetd-appearance: {}", err);
         std::process::exit(1);
     }
@@ -237,13 +238,13 @@ struct WallpaperSignature {
     modified: Option<SystemTime>,
 }
 
-fn run_once(options: &Options) -> Result<(), String> {
+fn run_once(options: &Options, log: &mut Logger) -> Result<(), String> {
     let wallpaper = resolve_wallpaper(options);
-    let json = build_json(options, wallpaper)?;
+    let json = build_json(options, wallpaper, log)?;
     emit_output(options, &json)
 }
 
-fn run_watch(options: &Options) -> Result<(), String> {
+fn run_watch(options: &Options, log: &mut Logger) -> Result<(), String> {
     let interval = if options.interval_secs == 0 {
         Duration::from_secs(2)
     } else {
@@ -254,7 +255,7 @@ fn run_watch(options: &Options) -> Result<(), String> {
         let wallpaper = resolve_wallpaper(options);
         let sig = signature_for(&wallpaper);
         if last_sig.as_ref() != Some(&sig) {
-            let json = build_json(options, wallpaper)?;
+            let json = build_json(options, wallpaper, log)?;
             emit_output(options, &json)?;
             last_sig = Some(sig);
         }
@@ -275,15 +276,15 @@ fn signature_for(wallpaper: &Option<String>) -> WallpaperSignature {
     }
 }
 
-fn build_json(options: &Options, wallpaper: Option<String>) -> Result<String, String> {
+fn build_json(options: &Options, wallpaper: Option<String>, log: &mut Logger) -> Result<String, String> {
     let mut palette = if let Some(path) = wallpaper.as_ref() {
         match load_palette(path) {
             Some(palette) => palette,
             None => {
-                eprintln!(
-                    "tiss-greetd-appearance: failed to read wallpaper {}, using defaults",
+                log.warn(&format!(
+                    "failed to read wallpaper {}, using defaults",
                     path
-                );
+                ));
                 default_palette()
             }
         }
@@ -0,0 +1,4 @@
+//! Launcher pieces that are tested on their own; the binaries live in
+//! `main.rs` and `appearance.rs`.
+
+pub mod users;
@@ -1,5 +1,7 @@
-use tiss_greetd_config::Config;
-use serde::{Deserialize, Serialize};
+use tiss_greetd_config::state::{PersistedState, RememberedSelection};
+use tiss_greetd_config::{Config, RateLimit};
+use tiss_greetd_desktop_entry::{DesktopEntry, Locale};
+use serde::Serialize;
 use std::env;
 use std::fs;
 use std::path::{Path, PathBuf};
@@ -7,6 +9,12 @@ use std::process::Command;
 use std::os::unix::fs::{FileTypeExt, PermissionsExt};
 use std::os::unix::process::CommandExt;
 
+use tiss_greetd_launcher::users;
+use tiss_greetd_logging as logging;
+use tiss_greetd_protocol::Phase;
+
+use logging::Logger;
+
 fn main() {
     if let Err(err) = run() {
         eprintln!("tiss-greetd-launcher: {}", err);
@@ -15,24 +23,44 @@ fn main() {
 }
 
 fn run() -> Result<(), String> {
-    let config = load_config();
+    let (config, config_errors) = load_config();
     let session_json_explicit = !env_missing("TISS_GREETD_SESSION_JSON");
     apply_config_env(&config)?;
-    let state = load_state();
-    configure_sessions(session_json_explicit, &state);
-    configure_profiles_locales(&config, &state);
-    configure_power(&config);
-    configure_appearance();
-    ensure_seat_backend(&config);
     ensure_log_dir();
+    let mut log = Logger::new("launcher");
+    for err in &config_errors {
+        log.error(err);
+    }
+    launch(&config, session_json_explicit, &mut log).inspect_err(|err| log.error(err))
+}
+
+fn launch(config: &Config, session_json_explicit: bool, log: &mut Logger) -> Result<(), String> {
+    let state = load_state(log);
+    set_env_if_missing("TISS_GREETD_LAST_USER", state.last_user.clone());
+    // The configured default user wins over whoever logged in last.
+    let user = env::var("TISS_GREETD_DEFAULT_USER")
+        .ok()
+        .filter(|user| !user.trim().is_empty())
+        .or_else(|| state.last_user.clone());
+    let remembered = match user.as_deref() {
+        Some(user) => state.remembered(user),
+        None => state.global.clone(),
+    };
+    configure_sessions(config, session_json_explicit, &remembered, log);
+    configure_profiles_locales(config, &remembered, log);
+    configure_power(config, log)?;
+    configure_appearance(log);
+    ensure_seat_backend(config);
     ensure_cache_env();
+    configure_users(config, log);
     ensure_backend_path();
     ensure_qml_path();
     sanitize_env();
 
-    let cage_bin = resolve_cage_bin(&config)?;
+    let cage_bin = resolve_cage_bin(config)?;
     let ui_bin = resolve_ui_bin()?;
-    let cage_args = build_cage_args(&config);
+    let cage_args = build_cage_args(config);
+    log.log(&format!("exec {} -> {}", cage_bin.display(), ui_bin.display()));
 
     let err = Command::new(&cage_bin)
         .args(&cage_args)
@@ -50,11 +78,32 @@ fn run() -> Result<(), String> {
 #[derive(Debug, Serialize)]
 struct SessionEntry {
     id: String,
+    /// Desktop file id. Differs from `id` for an X11 session renamed to
+    /// `<id>-x11`; empty for sessions defined only in the config.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    desktop_id: String,
+    /// `Name`, translated for the greeter's locale.
     name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    comment: Option<String>,
+    /// Path of the icon file, if `Icon` could be found.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    icon: Option<String>,
     exec: Vec<String>,
     #[serde(rename = "type")]
     session_type: String,
+    /// `DesktopNames`; the backend derives `XDG_CURRENT_DESKTOP` from it.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    desktop_names: Vec<String>,
+    /// Empty for sessions defined only in the config.
+    #[serde(skip_serializing_if = "String::is_empty")]
     desktop_file: String,
+    /// Applied by the backend when this session starts.
+    #[serde(skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    env: std::collections::BTreeMap<String, String>,
+    /// `X-*` keys of the desktop entry, for themes.
+    #[serde(skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    extensions: std::collections::BTreeMap<String, String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -73,27 +122,20 @@ struct LocalesEntry {
     available: Vec<String>,
 }
 
-#[derive(Debug, Deserialize, Default)]
-struct PersistedState {
-    #[serde(default)]
-    last_session_id: Option<String>,
-    #[serde(default)]
-    last_profile_id: Option<String>,
-    #[serde(default)]
-    last_locale: Option<String>,
-}
-
-fn load_config() -> Config {
+/// Merges the system and user config. Read errors are returned rather than
+/// logged: the log dir itself comes from the config.
+fn load_config() -> (Config, Vec<String>) {
     let mut config = Config::default();
+    let mut errors = Vec::new();
     let system_path = Path::new("/etc/tiss-greetd/config.toml");
     if system_path.exists() {
         match Config::load_from_path(system_path) {
             Ok(cfg) => config = config.merge(cfg),
-            Err(err) => eprintln!(
-                "tiss-greetd-launcher: failed to read {}: {}",
+            Err(err) => errors.push(format!(
+                "failed to read {}: {}",
                 system_path.display(),
                 err
-            ),
+            )),
         }
     }
 
@@ -102,16 +144,16 @@ fn load_config() -> Config {
         if user_path.exists() {
             match Config::load_from_path(&user_path) {
                 Ok(cfg) => config = config.merge(cfg),
-                Err(err) => eprintln!(
-                    "tiss-greetd-launcher: failed to read {}: {}",
+                Err(err) => errors.push(format!(
+                    "failed to read {}: {}",
                     user_path.display(),
                     err
-                ),
+                )),
             }
         }
     }
 
-    config
+    (config, errors)
 }
 
 fn env_missing(key: &str) -> bool {
@@ -161,6 +203,11 @@ fn apply_config_env(config: &Config) -> Result<(), String> {
     set_env_if_missing("TISS_GREETD_THEME", config.paths.theme.clone());
 
     set_env_if_missing("TISS_GREETD_DEFAULT_USER", config.login.default_user.clone());
+    set_env_if_missing("TISS_GREETD_AUTOLOGIN_USER", config.login.autologin_user.clone());
+    set_env_if_missing(
+        "TISS_GREETD_AUTOLOGIN_DELAY_SECS",
+        config.login.autologin_delay_secs.map(|secs| secs.to_string()),
+    );
     set_env_if_missing(
         "TISS_GREETD_LOCK_USER",
         config
@@ -181,6 +228,12 @@ fn apply_config_env(config: &Config) -> Result<(), String> {
         env::set_var("TISS_GREETD_SESSION_ENV_JSON", json);
     }
 
+    if env_missing("TISS_GREETD_X11_WRAPPER_JSON") && !config.session.x11_wrapper.is_empty() {
+        let json = serde_json::to_string(&config.session.x11_wrapper)
+            .map_err(|err| format!("invalid session.x11_wrapper: {}", err))?;
+        env::set_var("TISS_GREETD_X11_WRAPPER_JSON", json);
+    }
+
     set_env_if_missing(
         "TISS_GREETD_LOG_DIR",
         config
@@ -189,7 +242,58 @@ fn apply_config_env(config: &Config) -> Result<(), String> {
             .as_ref()
             .map(|path| path.to_string_lossy().to_string()),
     );
+    if let Some(level) = config.logging.level.as_deref() {
+        if logging::Level::parse(level).is_none() {
+            return Err(format!(
+                "invalid logging.level {:?} (expected debug, info, warn or error)",
+                level
+            ));
+        }
+    }
     set_env_if_missing("TISS_GREETD_LOG_LEVEL", config.logging.level.clone());
+    if let Some(format) = config.logging.format.as_deref() {
+        if !matches!(format, "text" | "json") {
+            return Err(format!("invalid logging.format {:?} (expected text or json)", format));
+        }
+    }
+    set_env_if_missing("TISS_GREETD_LOG_FORMAT", config.logging.format.clone());
+    if let Some(sink) = config.logging.sink.as_deref() {
+        if !matches!(sink, "file" | "journald" | "syslog") {
+            return Err(format!(
+                "invalid logging.sink {:?} (expected file, journald or syslog)",
+                sink
+            ));
+        }
+    }
+    set_env_if_missing("TISS_GREETD_LOG_SINK", config.logging.sink.clone());
+    if let Some(privacy) = config.logging.privacy.as_deref() {
+        if logging::Privacy::parse(privacy).is_none() {
+            return Err(format!(
+                "invalid logging.privacy {:?} (expected off, truncate or drop)",
+                privacy
+            ));
+        }
+    }
+    set_env_if_missing("TISS_GREETD_LOG_PRIVACY", config.logging.privacy.clone());
+    set_env_if_missing(
+        "TISS_GREETD_LOG_MAX_SIZE",
+        config.logging.max_size.map(|value| value.to_string()),
+    );
+    set_env_if_missing(
+        "TISS_GREETD_LOG_MAX_AGE_DAYS",
+        config.logging.max_age_days.map(|value| value.to_string()),
+    );
+    set_env_if_missing(
+        "TISS_GREETD_LOG_KEEP",
+        config.logging.keep.map(|value| value.to_string()),
+    );
+    set_env_if_missing(
+        "TISS_GREETD_LOG_COMPRESS",
+        config
+            .logging
+            .compress
+            .map(|value| if value { "1".to_string() } else { "0".to_string() }),
+    );
 
     set_env_if_missing(
         "TISS_GREETD_SHOW_PASSWORD_TOGGLE",
@@ -199,22 +303,102 @@ fn apply_config_env(config: &Config) -> Result<(), String> {
             .map(|value| if value { "1".to_string() } else { "0".to_string() }),
     );
 
+    set_env_if_missing(
+        "TISS_GREETD_ALLOW_ROOT",
+        config
+            .security
+            .allow_root
+            .map(|value| if value { "1".to_string() } else { "0".to_string() }),
+    );
+    set_env_if_missing(
+        "TISS_GREETD_SANDBOX",
+        config
+            .security
+            .sandbox
+            .map(|value| if value { "1".to_string() } else { "0".to_string() }),
+    );
+
+    if !config.rate_limit.is_empty() {
+        check_rate_limit(&config.rate_limit)?;
+        match serde_json::to_string(&config.rate_limit) {
+            Ok(json) => set_env_if_missing("TISS_GREETD_RATE_LIMIT_JSON", Some(json)),
+            Err(err) => return Err(format!("failed to serialize rate_limit: {}", err)),
+        }
+    }
+
+    match config.ui.backend_transport.as_deref() {
+        None | Some("stdio") => {}
+        Some("socket") => {
+            let socket = config
+                .ui
+                .backend_socket
+                .as_ref()
+                .map(|path| path.to_string_lossy().to_string())
+                .unwrap_or_else(default_backend_socket);
+            set_env_if_missing("TISS_GREETD_BACKEND_SOCKET", Some(socket));
+        }
+        Some(other) => return Err(format!("invalid ui.backend_transport: {}", other)),
+    }
+
     Ok(())
 }
 
-fn configure_sessions(session_json_explicit: bool, state: &PersistedState) {
-    let sessions = discover_sessions();
+/// Rejects a `[rate_limit]` section the backend would not apply as written.
+fn check_rate_limit(rate_limit: &RateLimit) -> Result<(), String> {
+    if let Some(key) = rate_limit.unknown.keys().next() {
+        return Err(format!("unknown rate_limit.{}", key));
+    }
+    for (key, value) in [
+        ("window_secs", rate_limit.window_secs),
+        ("backoff_secs", rate_limit.backoff_secs),
+        ("max_backoff_secs", rate_limit.max_backoff_secs),
+    ] {
+        if value == Some(0) {
+            return Err(format!("invalid rate_limit.{} 0 (expected at least 1)", key));
+        }
+    }
+    if let (Some(backoff), Some(max_backoff)) = (rate_limit.backoff_secs, rate_limit.max_backoff_secs) {
+        if backoff > max_backoff {
+            return Err(format!(
+                "invalid rate_limit: backoff_secs {} is above max_backoff_secs {}",
+                backoff, max_backoff
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn configure_sessions(
+    config: &Config,
+    session_json_explicit: bool,
+    remembered: &RememberedSelection,
+    log: &mut Logger,
+) {
+    let locale = config
+        .locales
+        .default
+        .as_deref()
+        .and_then(Locale::parse)
+        .or_else(Locale::from_env);
+    let mut dirs = config.session.search_paths.clone();
+    for dir in data_dirs() {
+        if !dirs.contains(&dir) {
+            dirs.push(dir);
+        }
+    }
+    let mut sessions = discover_sessions(&dirs, locale.as_ref(), log);
+    merge_config_sessions(&mut sessions, &config.sessions, log);
     if let Ok(json) = serde_json::to_string(&sessions) {
         set_env_if_missing("TISS_GREETD_SESSIONS_JSON", Some(json));
     } else {
-        eprintln!("tiss-greetd-launcher: failed to serialize session list");
+        log.error("failed to serialize session list");
     }
 
     let mut selected_session_id = env::var("TISS_GREETD_LAST_SESSION_ID")
         .ok()
         .and_then(|value| if value.trim().is_empty() { None } else { Some(value) });
     if selected_session_id.is_none() {
-        selected_session_id = state.last_session_id.clone();
+        selected_session_id = remembered.session_id.clone();
     }
 
     if let Some(last_session_id) = selected_session_id.as_ref() {
@@ -231,7 +415,7 @@ fn configure_sessions(session_json_explicit: bool, state: &PersistedState) {
     }
 }
 
-fn configure_profiles_locales(config: &Config, state: &PersistedState) {
+fn configure_profiles_locales(config: &Config, remembered: &RememberedSelection, log: &mut Logger) {
     if !config.profiles.is_empty() {
         let entries: Vec<ProfileEntry> = config
             .profiles
@@ -246,7 +430,7 @@ fn configure_profiles_locales(config: &Config, state: &PersistedState) {
         if let Ok(json) = serde_json::to_string(&entries) {
             set_env_if_missing("TISS_GREETD_PROFILES_JSON", Some(json));
         } else {
-            eprintln!("tiss-greetd-launcher: failed to serialize profiles");
+            log.error("failed to serialize profiles");
         }
     }
 
@@ -260,17 +444,17 @@ fn configure_profiles_locales(config: &Config, state: &PersistedState) {
         if let Ok(json) = serde_json::to_string(&locales) {
             set_env_if_missing("TISS_GREETD_LOCALES_JSON", Some(json));
         } else {
-            eprintln!("tiss-greetd-launcher: failed to serialize locales");
+            log.error("failed to serialize locales");
         }
     }
 
-    if let Some(last_profile_id) = state.last_profile_id.as_ref() {
+    if let Some(last_profile_id) = remembered.profile_id.as_ref() {
         if config.profiles.iter().any(|profile| profile.id == *last_profile_id) {
             set_env_if_missing("TISS_GREETD_LAST_PROFILE_ID", Some(last_profile_id.clone()));
         }
     }
 
-    if let Some(last_locale) = state.last_locale.as_ref() {
+    if let Some(last_locale) = remembered.locale.as_ref() {
         if config.locales.available.is_empty()
             || config.locales.available.iter().any(|locale| locale == last_locale)
         {
@@ -279,27 +463,52 @@ fn configure_profiles_locales(config: &Config, state: &PersistedState) {
     }
 }
 
-fn configure_power(config: &Config) {
+fn configure_power(config: &Config, log: &mut Logger) -> Result<(), String> {
     if config.power.enabled.is_empty() && config.power.allowed_states.is_empty() {
-        return;
+        return Ok(());
+    }
+    for state in &config.power.allowed_states {
+        let state = state.trim().to_ascii_lowercase();
+        if Phase::parse(&state).is_none() {
+            return Err(format!(
+                "invalid power.allowed_states entry {:?} (expected one of {})",
+                state,
+                Phase::ALL.map(Phase::as_str).join(", ")
+            ));
+        }
     }
     if !config.power.allowed_states.is_empty() {
         if let Ok(json) = serde_json::to_string(&config.power.allowed_states) {
             set_env_if_missing("TISS_GREETD_POWER_ALLOWED_STATES_JSON", Some(json));
         } else {
-            eprintln!("tiss-greetd-launcher: failed to serialize power allowed states");
+            log.error("failed to serialize power allowed states");
         }
     }
     if !config.power.enabled.is_empty() {
         if let Ok(json) = serde_json::to_string(&config.power.enabled) {
             set_env_if_missing("TISS_GREETD_POWER_ACTIONS_JSON", Some(json));
         } else {
-            eprintln!("tiss-greetd-launcher: failed to serialize power actions");
+            log.error("failed to serialize power actions");
         }
     }
+    Ok(())
 }
 
-fn configure_appearance() {
+fn configure_users(config: &Config, log: &mut Logger) {
+    if !env_missing("TISS_GREETD_USERS_JSON") || config.users.enabled == Some(false) {
+        return;
+    }
+    let cache_home = env::var("XDG_CACHE_HOME").unwrap_or_else(|_| "/tmp".to_string());
+    let avatar_dir = Path::new(&cache_home).join("tiss-greetd/avatars");
+    let users = users::enumerate(&config.users, &avatar_dir, log);
+    log.debug(&format!("users: {} listed", users.len()));
+    match serde_json::to_string(&users) {
+        Ok(json) => env::set_var("TISS_GREETD_USERS_JSON", json),
+        Err(err) => log.error(&format!("failed to serialize users: {}", err)),
+    }
+}
+
+fn configure_appearance(log: &mut Logger) {
     if !env_missing("TISS_GREETD_APPEARANCE_JSON") {
         return;
     }
@@ -311,17 +520,17 @@ fn configure_appearance() {
     let value: serde_json::Value = match serde_json::from_str(&content) {
         Ok(value) => value,
         Err(err) => {
-            eprintln!(
-                "tiss-greetd-launcher: failed to parse appearance {}: {}",
+            log.warn(&format!(
+                "failed to parse appearance {}: {}",
                 path.display(),
                 err
-            );
+            ));
             return;
         }
     };
     match serde_json::to_string(&value) {
         Ok(json) => env::set_var("TISS_GREETD_APPEARANCE_JSON", json),
-        Err(err) => eprintln!("tiss-greetd-launcher: failed to serialize appearance: {}", err),
+        Err(err) => log.error(&format!("failed to serialize appearance: {}", err)),
     }
 }
 
@@ -337,180 +546,256 @@ fn appearance_path() -> PathBuf {
     PathBuf::from("/tmp/tiss-greetd-appearance.json")
 }
 
-fn discover_sessions() -> Vec<SessionEntry> {
-    let mut sessions = Vec::new();
-    sessions.extend(scan_sessions_dir("/usr/share/wayland-sessions", "wayland"));
-    sessions.extend(scan_sessions_dir("/usr/share/xsessions", "x11"));
-    sessions.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()));
-    sessions
-}
-
-fn scan_sessions_dir(dir: &str, session_type: &str) -> Vec<SessionEntry> {
-    let mut sessions = Vec::new();
-    let read_dir = match fs::read_dir(dir) {
-        Ok(read_dir) => read_dir,
-        Err(_) => return sessions,
-    };
-    for entry in read_dir.flatten() {
-        let path = entry.path();
-        if path.extension().and_then(|ext| ext.to_str()) != Some("desktop") {
-            continue;
-        }
-        if let Some(session) = parse_desktop_entry(&path, session_type) {
-            sessions.push(session);
+/// Sessions from `wayland-sessions/` and `xsessions/` under each of `dirs`.
+/// An X11 session sharing its id with a Wayland one becomes `<id>-x11`,
+/// named "<name> (X11)". Names and comments are translated for `locale`.
+fn discover_sessions(dirs: &[PathBuf], locale: Option<&Locale>, log: &mut Logger) -> Vec<SessionEntry> {
+    let mut sessions = scan_sessions(dirs, "wayland-sessions", "wayland", locale, log);
+    for mut session in scan_sessions(dirs, "xsessions", "x11", locale, log) {
+        if sessions.iter().any(|wayland| wayland.id == session.id) {
+            session.id = format!("{}-x11", session.id);
+            session.name = format!("{} (X11)", session.name);
+            if sessions.iter().any(|wayland| wayland.id == session.id) {
+                log.warn(&format!(
+                    "skipping session {}: id {} is taken",
+                    session.desktop_file, session.id
+                ));
+                continue;
+            }
         }
+        sessions.push(session);
     }
+    sessions.sort_by_key(|session| session.name.to_lowercase());
     sessions
 }
 
-fn parse_desktop_entry(path: &Path, session_type: &str) -> Option<SessionEntry> {
-    let content = fs::read_to_string(path).ok()?;
-    let mut in_entry = false;
-    let mut name = None;
-    let mut exec = None;
-    let mut hidden = false;
-    let mut nodisplay = false;
-    let mut try_exec = None;
-
-    for line in content.lines() {
-        let line = line.trim();
-        if line.is_empty() || line.starts_with('#') {
+/// Applies `[[sessions]]`: entries naming a discovered id override its
+/// fields, the rest are added. The list stays sorted by name.
+fn merge_config_sessions(
+    sessions: &mut Vec<SessionEntry>,
+    entries: &[tiss_greetd_config::SessionEntry],
+    log: &mut Logger,
+) {
+    for entry in entries {
+        let id = entry.effective_id();
+        if id.is_empty() {
+            log.warn("ignoring [[sessions]] entry without id or name");
             continue;
         }
-        if line.starts_with('[') && line.ends_with(']') {
-            in_entry = line == "[Desktop Entry]";
+        let session_type = entry.session_type.as_ref().map(|value| value.trim().to_ascii_lowercase());
+        if let Some(value) = session_type.as_deref() {
+            if value != "wayland" && value != "x11" {
+                log.warn(&format!(
+                    "ignoring [[sessions]] entry {:?}: invalid type {:?} (expected wayland or x11)",
+                    id, value
+                ));
+                continue;
+            }
+        }
+        let name = entry.name.trim();
+        if let Some(session) = sessions.iter_mut().find(|session| session.id == id) {
+            if !name.is_empty() {
+                session.name = name.to_string();
+            }
+            if let Some(comment) = entry.comment.clone() {
+                session.comment = Some(comment);
+            }
+            if let Some(icon) = entry.icon.as_deref() {
+                session.icon = resolve_icon(icon);
+            }
+            if !entry.command.is_empty() {
+                session.exec = entry.command.clone();
+            }
+            if let Some(session_type) = session_type {
+                session.session_type = session_type;
+            }
+            if !entry.desktop_names.is_empty() {
+                session.desktop_names = entry.desktop_names.clone();
+            }
+            session.env.extend(entry.env.clone());
             continue;
         }
-        if !in_entry {
+        if entry.command.is_empty() {
+            log.warn(&format!(
+                "ignoring [[sessions]] entry {:?}: no command and no discovered session with that id",
+                id
+            ));
             continue;
         }
-        let (key, value) = match line.split_once('=') {
-            Some(pair) => pair,
-            None => continue,
+        sessions.push(SessionEntry {
+            name: if name.is_empty() { id.clone() } else { name.to_string() },
+            id,
+            desktop_id: String::new(),
+            comment: entry.comment.clone(),
+            icon: entry.icon.as_deref().and_then(resolve_icon),
+            exec: entry.command.clone(),
+            session_type: session_type.unwrap_or_else(|| "wayland".to_string()),
+            desktop_names: entry.desktop_names.clone(),
+            desktop_file: String::new(),
+            env: entry.env.clone(),
+            extensions: std::collections::BTreeMap::new(),
+        });
+    }
+    sessions.sort_by_key(|session| session.name.to_lowercase());
+}
+
+/// Desktop files in `subdir` of each data dir. The first file with a given
+/// id wins, even if it is hidden or cannot run, so an earlier dir can mask a
+/// session with `Hidden=true`.
+fn scan_sessions(
+    dirs: &[PathBuf],
+    subdir: &str,
+    session_type: &str,
+    locale: Option<&Locale>,
+    log: &mut Logger,
+) -> Vec<SessionEntry> {
+    let mut sessions = Vec::new();
+    let mut seen: std::collections::HashMap<String, PathBuf> = std::collections::HashMap::new();
+    for dir in dirs {
+        let Ok(read_dir) = fs::read_dir(dir.join(subdir)) else {
+            continue;
         };
-        let key = key.trim();
-        let value = value.trim();
-        match key {
-            "Name" => name = Some(value.to_string()),
-            "Exec" => exec = Some(value.to_string()),
-            "Hidden" => hidden = parse_bool(value),
-            "NoDisplay" => nodisplay = parse_bool(value),
-            "TryExec" => try_exec = Some(value.to_string()),
-            _ => {}
+        let mut paths: Vec<PathBuf> = read_dir
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().and_then(|ext| ext.to_str()) == Some("desktop"))
+            .collect();
+        paths.sort();
+        for path in paths {
+            let id = desktop_file_id(&path);
+            if let Some(first) = seen.get(&id) {
+                log.debug(&format!(
+                    "session {} shadowed by {}",
+                    path.display(),
+                    first.display()
+                ));
+                continue;
+            }
+            seen.insert(id, path.clone());
+            if let Some(session) = parse_desktop_entry(&path, session_type, locale, log) {
+                sessions.push(session);
+            }
         }
     }
+    sessions
+}
+
+fn desktop_file_id(path: &Path) -> String {
+    path.file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or_default()
+        .to_string()
+}
 
-    if hidden || nodisplay {
+fn parse_desktop_entry(
+    path: &Path,
+    session_type: &str,
+    locale: Option<&Locale>,
+    log: &mut Logger,
+) -> Option<SessionEntry> {
+    let content = fs::read_to_string(path).ok()?;
+    let entry = match DesktopEntry::parse(&content) {
+        Ok(entry) => entry,
+        Err(err) => {
+            log.warn(&format!("skipping session {}: {}", path.display(), err));
+            return None;
+        }
+    };
+
+    if entry.hidden() || entry.no_display() {
         return None;
     }
-
-    let exec = exec?;
-    if let Some(try_exec) = try_exec {
-        let token = parse_exec(&try_exec).into_iter().next();
-        if let Some(token) = token {
-            if !try_exec_exists(&token) {
-                return None;
-            }
+    // Session files are `Application` (older ones `XSession`); links and
+    // directories cannot be started.
+    if let Some(entry_type) = entry.entry_type() {
+        if entry_type != "Application" && entry_type != "XSession" {
+            log.debug(&format!("skipping session {}: Type={}", path.display(), entry_type));
+            return None;
         }
     }
 
-    let argv = parse_exec(&exec);
-    if argv.is_empty() {
-        return None;
+    let argv = match entry.exec()? {
+        Ok(argv) => argv,
+        Err(err) => {
+            log.warn(&format!("skipping session {}: {}", path.display(), err));
+            return None;
+        }
+    };
+    if let Some(try_exec) = entry.try_exec() {
+        let try_exec = try_exec.trim();
+        if !try_exec.is_empty() && !try_exec_exists(try_exec) {
+            return None;
+        }
     }
 
-    let id = path
-        .file_stem()
-        .and_then(|stem| stem.to_str())
-        .unwrap_or_default()
-        .to_string();
-    let name = name.unwrap_or_else(|| id.clone());
+    let id = desktop_file_id(path);
+    let name = entry.name(locale).unwrap_or_else(|| id.clone());
 
     Some(SessionEntry {
+        desktop_id: id.clone(),
         id,
         name,
+        comment: entry.comment(locale),
+        icon: entry.icon().as_deref().and_then(resolve_icon),
         exec: argv,
         session_type: session_type.to_string(),
+        desktop_names: entry.desktop_names(),
         desktop_file: path.to_string_lossy().to_string(),
+        env: std::collections::BTreeMap::new(),
+        extensions: entry.extensions(),
     })
 }
 
-fn parse_bool(value: &str) -> bool {
-    matches!(value.to_ascii_lowercase().as_str(), "1" | "true" | "yes")
-}
-
-fn parse_exec(raw: &str) -> Vec<String> {
-    let mut tokens = Vec::new();
-    let mut current = String::new();
-    let mut in_single = false;
-    let mut in_double = false;
-    let mut escape = false;
+/// hicolor sizes tried for icon theme names, best first.
+const ICON_SIZES: [&str; 6] = ["scalable", "256x256", "128x128", "96x96", "64x64", "48x48"];
 
-    for ch in raw.chars() {
-        if escape {
-            current.push(ch);
-            escape = false;
-            continue;
-        }
-        if ch == '\\' && !in_single {
-            escape = true;
-            continue;
-        }
-        if ch == '\'' && !in_double {
-            in_single = !in_single;
-            continue;
-        }
-        if ch == '"' && !in_single {
-            in_double = !in_double;
-            continue;
-        }
-        if ch.is_whitespace() && !in_single && !in_double {
-            if !current.is_empty() {
-                tokens.push(current.clone());
-                current.clear();
-            }
-            continue;
-        }
-        current.push(ch);
+/// `Icon` as a file: absolute paths are kept if they exist, theme names are
+/// looked up in the hicolor theme and `pixmaps` of each data dir.
+fn resolve_icon(icon: &str) -> Option<String> {
+    let icon = icon.trim();
+    if icon.is_empty() {
+        return None;
     }
-
-    if !current.is_empty() {
-        tokens.push(current);
+    let path = Path::new(icon);
+    if path.is_absolute() {
+        return path.is_file().then(|| icon.to_string());
     }
-
-    tokens
-        .into_iter()
-        .filter_map(|token| {
-            let cleaned = strip_field_codes(&token);
-            if cleaned.is_empty() {
-                None
-            } else {
-                Some(cleaned)
-            }
-        })
-        .collect()
-}
-
-fn strip_field_codes(value: &str) -> String {
-    let mut out = String::new();
-    let mut chars = value.chars().peekable();
-    while let Some(ch) = chars.next() {
-        if ch == '%' {
-            match chars.peek() {
-                Some('%') => {
-                    out.push('%');
-                    chars.next();
-                }
-                Some(_) => {
-                    chars.next();
+    for dir in data_dirs() {
+        let themed = ICON_SIZES
+            .iter()
+            .map(|size| dir.join("icons/hicolor").join(size).join("apps"))
+            .chain(std::iter::once(dir.join("pixmaps")));
+        for base in themed {
+            for ext in ["svg", "png"] {
+                let candidate = base.join(format!("{}.{}", icon, ext));
+                if candidate.is_file() {
+                    return Some(candidate.to_string_lossy().into_owned());
                 }
-                None => {}
             }
-        } else {
-            out.push(ch);
         }
     }
-    out
+    None
+}
+
+/// `XDG_DATA_DIRS`, or its default from the base directory spec.
+/// `/usr/share` is always searched, last if the variable leaves it out.
+fn data_dirs() -> Vec<PathBuf> {
+    let raw = env::var("XDG_DATA_DIRS").unwrap_or_default();
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    for dir in raw.split(':').filter(|dir| !dir.trim().is_empty()) {
+        let dir = PathBuf::from(dir);
+        if !dirs.contains(&dir) {
+            dirs.push(dir);
+        }
+    }
+    if dirs.is_empty() {
+        dirs.push(PathBuf::from("/usr/local/share"));
+    }
+    let usr_share = PathBuf::from("/usr/share");
+    if !dirs.contains(&usr_share) {
+        dirs.push(usr_share);
+    }
+    dirs
 }
 
 fn try_exec_exists(token: &str) -> bool {
@@ -520,7 +805,7 @@ fn try_exec_exists(token: &str) -> bool {
     find_executable(token).is_some()
 }
 
-fn load_state() -> PersistedState {
+fn load_state(log: &mut Logger) -> PersistedState {
     let path = state_path();
     let content = match fs::read_to_string(&path) {
         Ok(content) => content,
@@ -529,17 +814,17 @@ fn load_state() -> PersistedState {
     let mut state: PersistedState = match serde_json::from_str(&content) {
         Ok(state) => state,
         Err(err) => {
-            eprintln!(
-                "tiss-greetd-launcher: failed to parse state {}: {}",
+            log.warn(&format!(
+                "failed to parse state {}: {}",
                 path.display(),
                 err
-            );
+            ));
             return PersistedState::default();
         }
     };
-    state.last_session_id = state.last_session_id.filter(|id| !id.trim().is_empty());
-    state.last_profile_id = state.last_profile_id.filter(|id| !id.trim().is_empty());
-    state.last_locale = state.last_locale.filter(|locale| !locale.trim().is_empty());
+    // Only in memory; the backend rewrites the file.
+    state.migrate();
+    state.normalize();
     state
 }
 
@@ -581,9 +866,9 @@ fn ensure_log_dir() {
     }
 
     let current = env::var("TISS_GREETD_LOG_DIR").unwrap_or_else(|_| "/tmp/tiss-greetd".to_string());
-    if fs::create_dir_all(&current).is_err() {
+    if let Err(err) = logging::prepare_log_dir(Path::new(&current)) {
+        eprintln!("tiss-greetd-launcher: {}", err);
         env::set_var("TISS_GREETD_LOG_DIR", "/tmp/tiss-greetd");
-        let _ = fs::create_dir_all("/tmp/tiss-greetd");
     }
 }
 
@@ -653,6 +938,13 @@ fn default_log_dir() -> String {
     format!("/tmp/tiss-greetd-{}", uid_string())
 }
 
+fn default_backend_socket() -> String {
+    match env::var("XDG_RUNTIME_DIR") {
+        Ok(dir) if !dir.trim().is_empty() => format!("{}/tiss-greetd/backend.sock", dir),
+        _ => format!("/tmp/tiss-greetd-{}/backend.sock", uid_string()),
+    }
+}
+
 fn uid_string() -> String {
     unsafe { libc::geteuid().to_string() }
 }
@@ -0,0 +1,284 @@
+use std::ffi::CStr;
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+use tiss_greetd_config::Users;
+
+use tiss_greetd_logging::Logger;
+use tiss_greetd_protocol::UserInfo;
+
+const LOGIN_DEFS: &str = "/etc/login.defs";
+const SHELLS: &str = "/etc/shells";
+const ACCOUNTS_SERVICE_ICONS: &str = "/var/lib/AccountsService/icons";
+/// `~/.face` files larger than this are not copied.
+const MAX_FACE_BYTES: u64 = 1024 * 1024;
+
+/// A passwd entry.
+#[derive(Debug, Clone)]
+pub struct Account {
+    pub name: String,
+    pub uid: u32,
+    pub gecos: String,
+    pub home: String,
+    pub shell: String,
+}
+
+/// Login-capable users from NSS, sorted by name. Avatars that live in a
+/// home directory are copied to `avatar_dir`, since the UI may not be able
+/// to read homes once it runs.
+pub fn enumerate(config: &Users, avatar_dir: &Path, log: &mut Logger) -> Vec<UserInfo> {
+    let range = uid_range(&fs::read_to_string(LOGIN_DEFS).unwrap_or_default());
+    let shells = fs::read_to_string(SHELLS).ok().map(|content| valid_shells(&content));
+    select(accounts(), config, range, shells.as_deref())
+        .into_iter()
+        .map(|account| UserInfo {
+            avatar: avatar(&account, avatar_dir, log),
+            real_name: real_name(&account.gecos),
+            name: account.name,
+            uid: account.uid,
+            home: account.home,
+        })
+        .collect()
+}
+
+/// Accounts inside `uid_min..=uid_max` with a usable shell that pass the
+/// allow/deny lists, sorted by name without duplicates. `shells` is `None`
+/// when `/etc/shells` cannot be read.
+pub fn select(
+    accounts: Vec<Account>,
+    config: &Users,
+    (uid_min, uid_max): (u32, u32),
+    shells: Option<&[String]>,
+) -> Vec<Account> {
+    let mut accounts: Vec<Account> = accounts
+        .into_iter()
+        .filter(|account| account.uid >= uid_min && account.uid <= uid_max)
+        .filter(|account| shell_allowed(&account.shell, shells))
+        .filter(|account| config.allow.is_empty() || config.allow.contains(&account.name))
+        .filter(|account| !config.deny.contains(&account.name))
+        .collect();
+    accounts.sort_by(|a, b| a.name.cmp(&b.name));
+    accounts.dedup_by(|a, b| a.name == b.name);
+    accounts
+}
+
+fn accounts() -> Vec<Account> {
+    let mut accounts = Vec::new();
+    unsafe {
+        libc::setpwent();
+        loop {
+            let entry = libc::getpwent();
+            if entry.is_null() {
+                break;
+            }
+            let entry = &*entry;
+            accounts.push(Account {
+                name: c_string(entry.pw_name),
+                uid: entry.pw_uid,
+                gecos: c_string(entry.pw_gecos),
+                home: c_string(entry.pw_dir),
+                shell: c_string(entry.pw_shell),
+            });
+        }
+        libc::endpwent();
+    }
+    accounts
+}
+
+unsafe fn c_string(ptr: *const libc::c_char) -> String {
+    if ptr.is_null() {
+        return String::new();
+    }
+    CStr::from_ptr(ptr).to_string_lossy().into_owned()
+}
+
+/// `UID_MIN`/`UID_MAX` from `/etc/login.defs` content, with the shadow-utils
+/// defaults.
+pub fn uid_range(login_defs: &str) -> (u32, u32) {
+    let mut min = 1000;
+    let mut max = 60000;
+    for line in login_defs.lines() {
+        let mut parts = line.split_whitespace();
+        let (Some(key), Some(value)) = (parts.next(), parts.next()) else {
+            continue;
+        };
+        let Ok(value) = value.parse() else {
+            continue;
+        };
+        match key {
+            "UID_MIN" => min = value,
+            "UID_MAX" => max = value,
+            _ => {}
+        }
+    }
+    (min, max)
+}
+
+/// Shells listed in `/etc/shells` content.
+pub fn valid_shells(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+pub fn shell_allowed(shell: &str, shells: Option<&[String]>) -> bool {
+    // An empty shell field means /bin/sh.
+    let shell = if shell.is_empty() { "/bin/sh" } else { shell };
+    if shell.ends_with("/nologin") || shell.ends_with("/false") {
+        return false;
+    }
+    match shells {
+        Some(shells) => shells.iter().any(|valid| valid == shell),
+        None => true,
+    }
+}
+
+/// First comma-separated GECOS field.
+pub fn real_name(gecos: &str) -> Option<String> {
+    let name = gecos.split(',').next().unwrap_or_default().trim();
+    if name.is_empty() {
+        None
+    } else {
+        Some(name.to_string())
+    }
+}
+
+fn avatar(account: &Account, avatar_dir: &Path, log: &mut Logger) -> Option<String> {
+    let icon = Path::new(ACCOUNTS_SERVICE_ICONS).join(&account.name);
+    if icon.is_file() {
+        return Some(icon.to_string_lossy().into_owned());
+    }
+    if account.home.is_empty() {
+        return None;
+    }
+    let home = Path::new(&account.home);
+    for name in [".face", ".face.icon"] {
+        let face = home.join(name);
+        match copy_face(&face, account.uid, avatar_dir, &account.name) {
+            Ok(Some(path)) => return Some(path.to_string_lossy().into_owned()),
+            Ok(None) => continue,
+            Err(err) => {
+                log.debug(&format!("users: skip avatar {}: {}", face.display(), err));
+                return None;
+            }
+        }
+    }
+    None
+}
+
+/// Copies `face` to `avatar_dir/name`; `Ok(None)` if there is no `face`.
+///
+/// The launcher can read files the user cannot, so `face` must be a regular
+/// file owned by `owner`, opened without following symlinks; otherwise a
+/// `~/.face` pointing at, say, the log hash key would be copied out for the
+/// user to read. The copy is 0600 in a 0700 directory.
+pub fn copy_face(
+    face: &Path,
+    owner: u32,
+    avatar_dir: &Path,
+    name: &str,
+) -> Result<Option<PathBuf>, String> {
+    let mut file = match OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NOFOLLOW | libc::O_NONBLOCK)
+        .open(face)
+    {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.to_string()),
+    };
+    let meta = file.metadata().map_err(|err| err.to_string())?;
+    if !meta.file_type().is_file() {
+        return Err("not a regular file".to_string());
+    }
+    if meta.uid() != owner {
+        return Err(format!("owned by uid {}, not {}", meta.uid(), owner));
+    }
+    if meta.len() > MAX_FACE_BYTES {
+        return Err(format!("larger than {} bytes", MAX_FACE_BYTES));
+    }
+    let mut bytes = Vec::new();
+    Read::by_ref(&mut file)
+        .take(MAX_FACE_BYTES + 1)
+        .read_to_end(&mut bytes)
+        .map_err(|err| err.to_string())?;
+    if bytes.len() as u64 > MAX_FACE_BYTES {
+        return Err(format!("larger than {} bytes", MAX_FACE_BYTES));
+    }
+
+    private_dir(avatar_dir)?;
+    let target = avatar_dir.join(name);
+    match fs::remove_file(&target) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err.to_string()),
+    }
+    let mut out = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(&target)
+        .map_err(|err| err.to_string())?;
+    out.write_all(&bytes).map_err(|err| err.to_string())?;
+    Ok(Some(target))
+}
+
+/// Creates `dir` 0700, or makes sure an existing one is a directory of ours
+/// that nobody else can write to. The cache may live in `/tmp`, so every
+/// component on the way is checked as well: each must be a directory owned
+/// by root or us, and one writable by others must be root's and sticky.
+/// Otherwise whoever controls a component could swap the avatars for their
+/// own files or links.
+fn private_dir(dir: &Path) -> Result<(), String> {
+    let uid = unsafe { libc::geteuid() };
+    let mut path = PathBuf::new();
+    for component in dir.components() {
+        path.push(component);
+        match fs::symlink_metadata(&path) {
+            Ok(_) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => DirBuilder::new()
+                .mode(0o700)
+                .create(&path)
+                .map_err(|err| format!("create {}: {}", path.display(), err))?,
+            Err(err) => return Err(format!("{}: {}", path.display(), err)),
+        }
+        let meta = trusted_dir(&path, uid)?;
+        if path == dir {
+            let meta = fs::symlink_metadata(dir).map_err(|err| err.to_string())?;
+            if !meta.is_dir() || meta.uid() != uid {
+                return Err(format!("{} is not a directory owned by us", dir.display()));
+            }
+        } else if meta.mode() & 0o022 != 0 && (meta.uid() != 0 || meta.mode() & 0o1000 == 0) {
+            return Err(format!("{} is writable by others", path.display()));
+        }
+    }
+    let meta = fs::symlink_metadata(dir).map_err(|err| err.to_string())?;
+    if meta.mode() & 0o077 != 0 {
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))
+            .map_err(|err| format!("chmod {}: {}", dir.display(), err))?;
+    }
+    Ok(())
+}
+
+/// Metadata of the directory at `path`, which must be owned by root or
+/// `uid`. A symlink is followed only if root or `uid` owns it too.
+fn trusted_dir(path: &Path, uid: u32) -> Result<fs::Metadata, String> {
+    let link = fs::symlink_metadata(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    if link.file_type().is_symlink() && link.uid() != 0 && link.uid() != uid {
+        return Err(format!("{} is a symlink owned by uid {}", path.display(), link.uid()));
+    }
+    let meta = fs::metadata(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    if !meta.is_dir() {
+        return Err(format!("{} is not a directory", path.display()));
+    }
+    if meta.uid() != 0 && meta.uid() != uid {
+        return Err(format!("{} is owned by uid {}", path.display(), meta.uid()));
+    }
+    Ok(meta)
+}
@@ -0,0 +1,63 @@
+//! Config checks the launcher makes before starting anything. A fake cage
+//! prints the backend's rate-limit policy instead of starting a compositor.
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+/// Runs the launcher with `config` as the user config file.
+fn launch(name: &str, config: &str) -> Output {
+    let dir = std::env::temp_dir().join(format!("tiss-greetd-config-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let config_path = dir.join("home/.config/tiss-greetd/config.toml");
+    fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+    fs::write(&config_path, config).unwrap();
+
+    let cage: PathBuf = dir.join("cage");
+    fs::write(&cage, "#!/bin/sh\nprintf '%s' \"$TISS_GREETD_RATE_LIMIT_JSON\"\n").unwrap();
+    fs::set_permissions(&cage, fs::Permissions::from_mode(0o755)).unwrap();
+
+    Command::new(env!("CARGO_BIN_EXE_tiss-greetd-launcher"))
+        .env_clear()
+        .env("PATH", "/usr/bin:/bin")
+        .env("HOME", dir.join("home"))
+        .env("XDG_STATE_HOME", dir.join("state"))
+        .env("XDG_CACHE_HOME", dir.join("cache"))
+        .env("XDG_DATA_DIRS", dir.join("data"))
+        .env("TISS_GREETD_LOG_DIR", dir.join("log"))
+        .env("TISS_GREETD_CAGE_BIN", &cage)
+        .env("TISS_GREETD_UI_BIN", &cage)
+        .output()
+        .unwrap()
+}
+
+fn rejected(name: &str, config: &str) -> String {
+    let output = launch(name, config);
+    assert!(!output.status.success(), "{} was accepted", config);
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn rate_limit_is_passed_on() {
+    let output = launch("ratelimit", "[rate_limit]\nenabled = true\nmax_attempts = 3\n");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let policy: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(policy, serde_json::json!({"enabled": true, "max_attempts": 3}));
+
+    let output = launch("noratelimit", "");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn bad_rate_limit_is_rejected() {
+    let err = rejected("ratetypo", "[rate_limit]\nenabled = true\nmax_atempts = 3\n");
+    assert!(err.contains("unknown rate_limit.max_atempts"), "{}", err);
+
+    let err = rejected("ratezero", "[rate_limit]\nwindow_secs = 0\n");
+    assert!(err.contains("rate_limit.window_secs"), "{}", err);
+
+    let err = rejected("ratebackoff", "[rate_limit]\nbackoff_secs = 600\nmax_backoff_secs = 60\n");
+    assert!(err.contains("backoff_secs 600 is above max_backoff_secs 60"), "{}", err);
+}
@@ -0,0 +1,120 @@
+//! Session discovery, observed through the session list the launcher hands
+//! to the UI. A fake cage prints `TISS_GREETD_SESSIONS_JSON` instead of
+//! starting a compositor.
+
+use serde_json::Value;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("tiss-greetd-sessions-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn desktop_file(dir: &Path, subdir: &str, id: &str, body: &str) {
+    let path = dir.join(subdir).join(format!("{}.desktop", id));
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, format!("[Desktop Entry]\nType=Application\n{}", body)).unwrap();
+}
+
+/// Runs the launcher with `search_paths` in the user config and
+/// `XDG_DATA_DIRS` set to `data_dirs`; returns the test's sessions (ids
+/// starting with `tiss-`, so the host's own sessions do not matter).
+fn sessions(dir: &Path, search_paths: &[&Path], data_dirs: &[&Path]) -> Vec<Value> {
+    let cage = dir.join("cage");
+    fs::write(&cage, "#!/bin/sh\nprintf '%s' \"$TISS_GREETD_SESSIONS_JSON\"\n").unwrap();
+    fs::set_permissions(&cage, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let config = dir.join("home/.config/tiss-greetd/config.toml");
+    fs::create_dir_all(config.parent().unwrap()).unwrap();
+    let paths: Vec<String> = search_paths
+        .iter()
+        .map(|path| format!("{:?}", path.to_str().unwrap()))
+        .collect();
+    fs::write(&config, format!("[session]\nsearch_paths = [{}]\n", paths.join(", "))).unwrap();
+
+    let data_dirs: Vec<&str> = data_dirs.iter().map(|path| path.to_str().unwrap()).collect();
+    let output = Command::new(env!("CARGO_BIN_EXE_tiss-greetd-launcher"))
+        .env_clear()
+        .env("PATH", "/usr/bin:/bin")
+        .env("HOME", dir.join("home"))
+        .env("XDG_STATE_HOME", dir.join("state"))
+        .env("XDG_CACHE_HOME", dir.join("cache"))
+        .env("XDG_DATA_DIRS", data_dirs.join(":"))
+        .env("TISS_GREETD_LOG_DIR", dir.join("log"))
+        .env("TISS_GREETD_CAGE_BIN", &cage)
+        .env("TISS_GREETD_UI_BIN", &cage)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let all: Vec<Value> = serde_json::from_slice(&output.stdout).unwrap();
+    all.into_iter()
+        .filter(|session| session["id"].as_str().unwrap().starts_with("tiss-"))
+        .collect()
+}
+
+fn ids(sessions: &[Value]) -> Vec<&str> {
+    sessions.iter().map(|session| session["id"].as_str().unwrap()).collect()
+}
+
+fn find<'a>(sessions: &'a [Value], id: &str) -> &'a Value {
+    sessions
+        .iter()
+        .find(|session| session["id"] == id)
+        .unwrap_or_else(|| panic!("no session {}: {:?}", id, sessions))
+}
+
+#[test]
+fn first_file_with_an_id_wins() {
+    let dir = temp_dir("first");
+    let search = dir.join("search");
+    let first = dir.join("data1");
+    let second = dir.join("data2");
+
+    desktop_file(&search, "wayland-sessions", "tiss-sway", "Name=Sway (custom)\nExec=sway --unsupported-gpu\n");
+    desktop_file(&first, "wayland-sessions", "tiss-sway", "Name=Sway\nExec=sway\n");
+    desktop_file(&second, "wayland-sessions", "tiss-later", "Name=Later\nExec=later\n");
+    // A hidden entry masks the same id further down the search order.
+    desktop_file(&first, "wayland-sessions", "tiss-masked", "Name=Masked\nExec=masked\nHidden=true\n");
+    desktop_file(&second, "wayland-sessions", "tiss-masked", "Name=Masked\nExec=masked\n");
+
+    // data1 listed twice in XDG_DATA_DIRS is still searched once.
+    let sessions = sessions(&dir, &[&search], &[&first, &second, &first]);
+    assert_eq!(ids(&sessions), ["tiss-later", "tiss-sway"]);
+
+    let sway = find(&sessions, "tiss-sway");
+    assert_eq!(sway["name"], "Sway (custom)");
+    assert_eq!(sway["exec"], serde_json::json!(["sway", "--unsupported-gpu"]));
+    assert!(sway["desktop_file"].as_str().unwrap().starts_with(search.to_str().unwrap()));
+    let later = find(&sessions, "tiss-later");
+    assert!(later["desktop_file"].as_str().unwrap().starts_with(second.to_str().unwrap()));
+}
+
+#[test]
+fn x11_session_sharing_an_id_is_renamed() {
+    let dir = temp_dir("x11");
+    let data = dir.join("data");
+    desktop_file(&data, "wayland-sessions", "tiss-plasma", "Name=Plasma\nExec=startplasma-wayland\nDesktopNames=KDE\n");
+    desktop_file(&data, "xsessions", "tiss-plasma", "Name=Plasma\nExec=startplasma-x11\nDesktopNames=KDE\n");
+    desktop_file(&data, "xsessions", "tiss-i3", "Name=i3\nExec=i3\n");
+
+    let sessions = sessions(&dir, &[], &[&data]);
+    assert_eq!(ids(&sessions), ["tiss-i3", "tiss-plasma", "tiss-plasma-x11"]);
+
+    let wayland = find(&sessions, "tiss-plasma");
+    assert_eq!(wayland["type"], "wayland");
+    assert_eq!(wayland["desktop_id"], "tiss-plasma");
+    // Only the selection id changes; the desktop id stays the file's.
+    let x11 = find(&sessions, "tiss-plasma-x11");
+    assert_eq!(x11["name"], "Plasma (X11)");
+    assert_eq!(x11["type"], "x11");
+    assert_eq!(x11["desktop_id"], "tiss-plasma");
+    assert_eq!(x11["exec"], serde_json::json!(["startplasma-x11"]));
+    let i3 = find(&sessions, "tiss-i3");
+    assert_eq!(i3["name"], "i3");
+    assert_eq!(i3["desktop_id"], "tiss-i3");
+}
@@ -0,0 +1,59 @@
+//! The launcher reads the backend's state file to preselect the last user
+//! and their locale. A fake cage prints what it was handed.
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::process::Command;
+
+/// Runs the launcher over `state`; returns `(last user, last locale)`.
+fn preselected(name: &str, state: &str) -> (String, String) {
+    let dir = std::env::temp_dir().join(format!("tiss-greetd-state-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let state_path = dir.join("state/tiss-greetd/state.json");
+    fs::create_dir_all(state_path.parent().unwrap()).unwrap();
+    fs::write(&state_path, state).unwrap();
+
+    let cage = dir.join("cage");
+    fs::write(
+        &cage,
+        "#!/bin/sh\nprintf '%s\\n%s\\n' \"$TISS_GREETD_LAST_USER\" \"$TISS_GREETD_LAST_LOCALE\"\n",
+    )
+    .unwrap();
+    fs::set_permissions(&cage, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_tiss-greetd-launcher"))
+        .env_clear()
+        .env("PATH", "/usr/bin:/bin")
+        .env("HOME", dir.join("home"))
+        .env("XDG_STATE_HOME", dir.join("state"))
+        .env("XDG_CACHE_HOME", dir.join("cache"))
+        .env("XDG_DATA_DIRS", dir.join("data"))
+        .env("TISS_GREETD_LOG_DIR", dir.join("log"))
+        .env("TISS_GREETD_CAGE_BIN", &cage)
+        .env("TISS_GREETD_UI_BIN", &cage)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    // Reading never rewrites the file; migrating it is the backend's job.
+    assert_eq!(fs::read_to_string(&state_path).unwrap(), state);
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut lines = stdout.lines().map(str::to_string);
+    (lines.next().unwrap_or_default(), lines.next().unwrap_or_default())
+}
+
+#[test]
+fn version_1_state_is_read_through_the_migration() {
+    let (user, locale) = preselected("v1", r#"{"last_session_id": "sway", "last_locale": "de_DE.UTF-8"}"#);
+    assert_eq!(user, "");
+    assert_eq!(locale, "de_DE.UTF-8");
+}
+
+#[test]
+fn last_user_gets_their_own_selection() {
+    let state = r#"{"version": 2, "last_user": "bob", "global": {"locale": "en_US.UTF-8"},
+        "users": {"bob": {"locale": " fr_FR.UTF-8 "}, "alice": {"locale": "de_DE.UTF-8"}}}"#;
+    let (user, locale) = preselected("v2", state);
+    assert_eq!(user, "bob");
+    assert_eq!(locale, "fr_FR.UTF-8");
+}
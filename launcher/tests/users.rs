@@ -0,0 +1,193 @@
+use std::fs;
+use std::os::unix::fs::{symlink, MetadataExt, PermissionsExt};
+use std::path::PathBuf;
+
+use tiss_greetd_config::Users;
+use tiss_greetd_launcher::users::{
+    copy_face, real_name, select, shell_allowed, uid_range, valid_shells, Account,
+};
+
+fn account(name: &str, uid: u32, shell: &str) -> Account {
+    Account {
+        name: name.to_string(),
+        uid,
+        gecos: String::new(),
+        home: format!("/home/{}", name),
+        shell: shell.to_string(),
+    }
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("tiss-greetd-users-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn euid() -> u32 {
+    unsafe { libc::geteuid() }
+}
+
+#[test]
+fn uid_range_reads_login_defs() {
+    assert_eq!(uid_range(""), (1000, 60000));
+    let defs = "# UID_MIN 1\nUID_MIN\t\t 2000\nUID_MAX 3000 # trailing\nGID_MIN 10\nUID_MAX oops\n";
+    assert_eq!(uid_range(defs), (2000, 3000));
+}
+
+#[test]
+fn shells_come_from_etc_shells() {
+    let shells = valid_shells("# /etc/shells\n/bin/bash\n\n  /usr/bin/zsh  \n/usr/bin/nologin\n");
+    assert_eq!(shells, ["/bin/bash", "/usr/bin/zsh", "/usr/bin/nologin"]);
+
+    assert!(shell_allowed("/bin/bash", Some(&shells)));
+    assert!(!shell_allowed("/bin/fish", Some(&shells)));
+    // nologin and false never count, even when listed or without a list.
+    assert!(!shell_allowed("/usr/bin/nologin", Some(&shells)));
+    assert!(!shell_allowed("/usr/sbin/nologin", None));
+    assert!(!shell_allowed("/bin/false", None));
+    // An empty shell field means /bin/sh.
+    assert!(shell_allowed("", None));
+    assert!(!shell_allowed("", Some(&shells)));
+    assert!(shell_allowed("", Some(&["/bin/sh".to_string()])));
+}
+
+#[test]
+fn select_filters_sorts_and_dedups() {
+    let shells = vec!["/bin/bash".to_string()];
+    let accounts = vec![
+        account("zoe", 1002, "/bin/bash"),
+        account("root", 0, "/bin/bash"),
+        account("daemon", 999, "/bin/bash"),
+        account("nobody", 65534, "/bin/bash"),
+        account("svc", 1003, "/usr/bin/nologin"),
+        account("fish", 1004, "/bin/fish"),
+        account("alice", 1000, "/bin/bash"),
+        account("alice", 1000, "/bin/bash"),
+        account("bob", 1001, "/bin/bash"),
+    ];
+    let names = |config: &Users| -> Vec<String> {
+        select(accounts.clone(), config, (1000, 60000), Some(&shells))
+            .into_iter()
+            .map(|account| account.name)
+            .collect()
+    };
+
+    assert_eq!(names(&Users::default()), ["alice", "bob", "zoe"]);
+    let deny = Users {
+        deny: vec!["bob".to_string()],
+        ..Users::default()
+    };
+    assert_eq!(names(&deny), ["alice", "zoe"]);
+    let allow = Users {
+        allow: vec!["zoe".to_string(), "root".to_string()],
+        ..Users::default()
+    };
+    // allow narrows the list but does not bypass the uid range.
+    assert_eq!(names(&allow), ["zoe"]);
+}
+
+#[test]
+fn real_name_is_first_gecos_field() {
+    assert_eq!(real_name("Alice Liddell,Room 1,555,,"), Some("Alice Liddell".to_string()));
+    assert_eq!(real_name("  Bob  "), Some("Bob".to_string()));
+    assert_eq!(real_name(",,,"), None);
+    assert_eq!(real_name(""), None);
+}
+
+#[test]
+fn face_is_copied_private() {
+    let dir = temp_dir("copy");
+    let face = dir.join("face.png");
+    fs::write(&face, b"png").unwrap();
+    let avatars = dir.join("cache/avatars");
+
+    let copied = copy_face(&face, euid(), &avatars, "alice").unwrap().unwrap();
+    assert_eq!(copied, avatars.join("alice"));
+    assert_eq!(fs::read(&copied).unwrap(), b"png");
+    assert_eq!(fs::metadata(&copied).unwrap().mode() & 0o777, 0o600);
+    assert_eq!(fs::metadata(&avatars).unwrap().mode() & 0o777, 0o700);
+
+    // Copying again replaces the old avatar.
+    fs::write(&face, b"new").unwrap();
+    copy_face(&face, euid(), &avatars, "alice").unwrap();
+    assert_eq!(fs::read(&copied).unwrap(), b"new");
+
+    assert_eq!(copy_face(&dir.join("missing"), euid(), &avatars, "bob").unwrap(), None);
+}
+
+#[test]
+fn face_symlinks_and_foreign_files_are_refused() {
+    let dir = temp_dir("refuse");
+    let secret = dir.join("log-hash.key");
+    fs::write(&secret, b"secret").unwrap();
+    fs::set_permissions(&secret, fs::Permissions::from_mode(0o600)).unwrap();
+    let avatars = dir.join("avatars");
+
+    let link = dir.join(".face");
+    symlink(&secret, &link).unwrap();
+    assert!(copy_face(&link, euid(), &avatars, "mallory").is_err());
+
+    // Owned by someone other than the account.
+    assert!(copy_face(&secret, euid() + 1, &avatars, "mallory").is_err());
+
+    let fifo = dir.join(".face.icon");
+    let path = std::ffi::CString::new(fifo.to_str().unwrap()).unwrap();
+    assert_eq!(unsafe { libc::mkfifo(path.as_ptr(), 0o644) }, 0);
+    assert!(copy_face(&fifo, euid(), &avatars, "mallory").is_err());
+
+    let big = dir.join("big");
+    fs::write(&big, vec![0u8; 1024 * 1024 + 1]).unwrap();
+    assert!(copy_face(&big, euid(), &avatars, "mallory").is_err());
+
+    assert!(!avatars.join("mallory").exists());
+}
+
+#[test]
+fn avatar_dir_below_a_shared_dir_is_refused() {
+    let dir = temp_dir("shared");
+    let face = dir.join("face.png");
+    fs::write(&face, b"png").unwrap();
+
+    // Anyone could swap `avatars` for a link of theirs.
+    let shared = dir.join("shared");
+    fs::create_dir(&shared).unwrap();
+    fs::set_permissions(&shared, fs::Permissions::from_mode(0o777)).unwrap();
+    let err = copy_face(&face, euid(), &shared.join("avatars"), "alice").unwrap_err();
+    assert!(err.contains("writable by others"), "{}", err);
+    assert!(!shared.join("avatars").exists());
+
+    // Sticky and root's, like /tmp: fine.
+    if euid() == 0 {
+        fs::set_permissions(&shared, fs::Permissions::from_mode(0o1777)).unwrap();
+        assert!(copy_face(&face, euid(), &shared.join("avatars"), "alice").unwrap().is_some());
+    }
+}
+
+#[test]
+fn avatar_dir_below_a_foreign_dir_is_refused() {
+    if euid() != 0 {
+        return;
+    }
+    let dir = temp_dir("foreign");
+    let face = dir.join("face.png");
+    fs::write(&face, b"png").unwrap();
+
+    // A cache dir someone else created first, say in /tmp.
+    let foreign = dir.join("tiss-greetd-cache");
+    fs::create_dir(&foreign).unwrap();
+    std::os::unix::fs::chown(&foreign, Some(4242), None).unwrap();
+    let err = copy_face(&face, euid(), &foreign.join("avatars"), "alice").unwrap_err();
+    assert!(err.contains("owned by uid 4242"), "{}", err);
+
+    // Or a link of theirs to a dir of ours.
+    let ours = dir.join("ours");
+    fs::create_dir(&ours).unwrap();
+    let link = dir.join("link");
+    symlink(&ours, &link).unwrap();
+    let path = std::ffi::CString::new(link.to_str().unwrap()).unwrap();
+    assert_eq!(unsafe { libc::lchown(path.as_ptr(), 4242, 4242) }, 0);
+    let err = copy_face(&face, euid(), &link.join("avatars"), "alice").unwrap_err();
+    assert!(err.contains("symlink owned by uid 4242"), "{}", err);
+    assert!(!ours.join("avatars").exists());
+}
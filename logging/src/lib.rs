@@ -0,0 +1,740 @@
+//! Logger shared by the backend, the launcher and `tiss-greetd-appearance`.
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use hmac::{Hmac, Mac};
+use serde_json::{Map, Value};
+use sha2::Sha256;
+use std::ffi::OsString;
+use std::fs::{self, DirBuilder, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::net::UnixDatagram;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Severity of a record. Records below `TISS_GREETD_LOG_LEVEL` are dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Level::Debug => "debug",
+            Level::Info => "info",
+            Level::Warn => "warn",
+            Level::Error => "error",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Level> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "debug" => Some(Level::Debug),
+            "info" => Some(Level::Info),
+            "warn" | "warning" => Some(Level::Warn),
+            "error" => Some(Level::Error),
+            _ => None,
+        }
+    }
+}
+
+/// Line format, from `TISS_GREETD_LOG_FORMAT`. The journald sink always
+/// uses structured fields instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// `[ts] level component: message key=value ...`
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+/// What `[logging] privacy` hides, from `TISS_GREETD_LOG_PRIVACY`.
+///
+/// Except with `Off`, usernames (in messages built with [`Logger::user`] and
+/// in the `user` field) become a keyed hash such as `u:3f2a9c0d1b4e5f60`, so
+/// records of the same user still correlate (`u:redacted` if no key could be
+/// made). PAM prompt and message bodies are cut to [`TRUNCATED_CHARS`]
+/// characters or dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Privacy {
+    Off,
+    Truncate,
+    Drop,
+}
+
+impl Privacy {
+    pub fn parse(value: &str) -> Option<Privacy> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "" | "off" => Some(Privacy::Off),
+            "truncate" => Some(Privacy::Truncate),
+            "drop" => Some(Privacy::Drop),
+            _ => None,
+        }
+    }
+}
+
+/// PAM text kept by [`Privacy::Truncate`].
+pub const TRUNCATED_CHARS: usize = 16;
+
+/// Structured fields attached to a record. Unset fields are left out.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Fields<'a> {
+    pub attempt: Option<u64>,
+    pub user: Option<&'a str>,
+    pub duration_ms: Option<u64>,
+    pub phase: Option<&'a str>,
+}
+
+/// When to rotate `tiss-greetd-<component>.log`, from `TISS_GREETD_LOG_MAX_SIZE`
+/// (bytes), `TISS_GREETD_LOG_MAX_AGE_DAYS`, `TISS_GREETD_LOG_KEEP` and
+/// `TISS_GREETD_LOG_COMPRESS`. A zero size or age disables that trigger.
+///
+/// Rotated files are `<name>.1` (newest) up to `<name>.<keep>`, gzipped to
+/// `<name>.<n>.gz` when `compress` is set. `keep = 0` just starts over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rotation {
+    pub max_size: u64,
+    pub max_age_secs: u64,
+    pub keep: u32,
+    pub compress: bool,
+}
+
+impl Default for Rotation {
+    fn default() -> Self {
+        Rotation {
+            max_size: 1024 * 1024,
+            max_age_secs: 0,
+            keep: 5,
+            compress: false,
+        }
+    }
+}
+
+impl Rotation {
+    pub fn from_env() -> Self {
+        let default = Rotation::default();
+        Rotation {
+            max_size: env_u64("TISS_GREETD_LOG_MAX_SIZE").unwrap_or(default.max_size),
+            max_age_secs: env_u64("TISS_GREETD_LOG_MAX_AGE_DAYS")
+                .map(|days| days.saturating_mul(24 * 60 * 60))
+                .unwrap_or(default.max_age_secs),
+            keep: env_u64("TISS_GREETD_LOG_KEEP")
+                .map(|keep| keep.min(u32::MAX as u64) as u32)
+                .unwrap_or(default.keep),
+            compress: match std::env::var("TISS_GREETD_LOG_COMPRESS") {
+                Ok(value) => matches!(value.trim(), "1" | "true" | "yes"),
+                Err(_) => default.compress,
+            },
+        }
+    }
+}
+
+fn env_u64(key: &str) -> Option<u64> {
+    let value = std::env::var(key).ok()?;
+    value.trim().parse().ok()
+}
+
+/// Where records go, from `TISS_GREETD_LOG_SINK`.
+enum Sink {
+    /// `tiss-greetd-<component>.log` in the log dir.
+    File(LogFile),
+    /// Native journal protocol, one datagram per record.
+    Journald(UnixDatagram),
+    /// RFC 3164 datagrams to `/dev/log`.
+    Syslog(UnixDatagram),
+    /// Fallback when nothing else could be opened.
+    Stderr,
+}
+
+/// `SYSLOG_IDENTIFIER` / syslog tag, so `journalctl -t tiss-greetd` works.
+const IDENTIFIER: &str = "tiss-greetd";
+
+/// syslog facility `LOG_AUTHPRIV`: records name users.
+const FACILITY_AUTHPRIV: u8 = 10;
+
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+const SYSLOG_SOCKET: &str = "/dev/log";
+
+pub struct Logger {
+    sink: Sink,
+    component: String,
+    level: Level,
+    format: Format,
+    privacy: Privacy,
+    /// Key for hashing usernames; see [`Logger::set_hash_key`]. `None` when
+    /// no random key could be made: usernames are then redacted outright.
+    hash_key: Option<Vec<u8>>,
+}
+
+impl Logger {
+    pub fn new(component: &str) -> Self {
+        let component = component.to_string();
+        let level = std::env::var("TISS_GREETD_LOG_LEVEL")
+            .ok()
+            .and_then(|value| Level::parse(&value))
+            .unwrap_or(Level::Info);
+        let format = match std::env::var("TISS_GREETD_LOG_FORMAT") {
+            Ok(value) if value.trim().eq_ignore_ascii_case("json") => Format::Json,
+            _ => Format::Text,
+        };
+        let sink_name = std::env::var("TISS_GREETD_LOG_SINK").unwrap_or_default();
+        let sink = match sink_name.trim() {
+            "journald" => connect_socket("TISS_GREETD_JOURNAL_SOCKET", JOURNAL_SOCKET)
+                .map(Sink::Journald),
+            "syslog" => connect_socket("TISS_GREETD_SYSLOG_SOCKET", SYSLOG_SOCKET)
+                .map(Sink::Syslog),
+            "" | "file" => Err(String::new()),
+            other => Err(format!("unknown log sink {:?}", other)),
+        };
+        let sink = sink.unwrap_or_else(|err| {
+            if !err.is_empty() {
+                eprintln!("tiss-greetd-{}: {}; logging to file", component, err);
+            }
+            match LogFile::open(&default_log_dir(), &component) {
+                Ok(file) => Sink::File(file),
+                Err(err) => {
+                    eprintln!("tiss-greetd-{}: {}", component, err);
+                    Sink::Stderr
+                }
+            }
+        });
+
+        let privacy = match std::env::var("TISS_GREETD_LOG_PRIVACY") {
+            Ok(value) => Privacy::parse(&value).unwrap_or_else(|| {
+                eprintln!("tiss-greetd-{}: unknown log privacy {:?}; using drop", component, value);
+                Privacy::Drop
+            }),
+            Err(_) => Privacy::Off,
+        };
+
+        // Until a persistent key is set, hashes only correlate within this
+        // process.
+        let hash_key = match random_bytes(32) {
+            Ok(key) => Some(key),
+            Err(err) => {
+                eprintln!("tiss-greetd-{}: no random hash key: {}; redacting usernames", component, err);
+                None
+            }
+        };
+
+        Logger {
+            sink,
+            component,
+            level,
+            format,
+            privacy,
+            hash_key,
+        }
+    }
+
+    pub fn privacy(&self) -> Privacy {
+        self.privacy
+    }
+
+    /// Uses `key` for username hashes, so they stay stable across restarts.
+    pub fn set_hash_key(&mut self, key: Vec<u8>) {
+        self.hash_key = Some(key);
+    }
+
+    /// `user` as it may appear in the log.
+    pub fn user(&self, user: &str) -> String {
+        if self.privacy == Privacy::Off {
+            return user.to_string();
+        }
+        let Some(key) = self.hash_key.as_deref() else {
+            return "u:redacted".to_string();
+        };
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac accepts any key length");
+        mac.update(user.as_bytes());
+        let digest = mac.finalize().into_bytes();
+        let hex: String = digest[..8].iter().map(|byte| format!("{:02x}", byte)).collect();
+        format!("u:{}", hex)
+    }
+
+    /// PAM prompt or message `text` as it may appear in the log.
+    pub fn pam_text(&self, text: &str) -> String {
+        match self.privacy {
+            Privacy::Off => text.to_string(),
+            Privacy::Truncate if text.chars().count() <= TRUNCATED_CHARS => text.to_string(),
+            Privacy::Truncate => {
+                let kept: String = text.chars().take(TRUNCATED_CHARS).collect();
+                format!("{}...", kept)
+            }
+            Privacy::Drop => format!("<{} bytes>", text.len()),
+        }
+    }
+
+    pub fn enabled(&self, level: Level) -> bool {
+        level >= self.level
+    }
+
+    /// Logs `message` at info level.
+    pub fn log(&mut self, message: &str) {
+        self.event(Level::Info, message, Fields::default());
+    }
+
+    pub fn debug(&mut self, message: &str) {
+        self.event(Level::Debug, message, Fields::default());
+    }
+
+    pub fn warn(&mut self, message: &str) {
+        self.event(Level::Warn, message, Fields::default());
+    }
+
+    pub fn error(&mut self, message: &str) {
+        self.event(Level::Error, message, Fields::default());
+    }
+
+    pub fn event(&mut self, level: Level, message: &str, fields: Fields<'_>) {
+        if !self.enabled(level) {
+            return;
+        }
+        let user = fields.user.map(|user| self.user(user));
+        let fields = Fields {
+            user: user.as_deref(),
+            ..fields
+        };
+        let ts = unix_now();
+        let written = match &mut self.sink {
+            Sink::File(file) => {
+                let line = match self.format {
+                    Format::Text => text_line(ts, level, &self.component, message, &fields),
+                    Format::Json => json_line(ts, level, &self.component, message, &fields),
+                };
+                file.write(&line, ts, &self.component)
+            }
+            Sink::Journald(socket) => {
+                let datagram = journal_datagram(level, &self.component, message, &fields);
+                socket.send(&datagram).is_ok()
+            }
+            Sink::Syslog(socket) => {
+                let body = match self.format {
+                    Format::Text => format!("{}: {}", self.component, text_body(message, &fields)),
+                    Format::Json => json_line(ts, level, &self.component, message, &fields),
+                };
+                let datagram = format!(
+                    "<{}>{}[{}]: {}",
+                    FACILITY_AUTHPRIV * 8 + syslog_severity(level),
+                    IDENTIFIER,
+                    std::process::id(),
+                    body
+                );
+                socket.send(datagram.as_bytes()).is_ok()
+            }
+            Sink::Stderr => false,
+        };
+        if !written {
+            eprintln!("{}", text_line(ts, level, &self.component, message, &fields));
+        }
+    }
+}
+
+/// The active log file and its rotation state.
+struct LogFile {
+    file: Option<File>,
+    path: PathBuf,
+    rotation: Rotation,
+    /// Bytes in the current file.
+    size: u64,
+    /// When the current file was started, for age-based rotation.
+    started: u64,
+}
+
+impl LogFile {
+    fn open(dir: &Path, component: &str) -> Result<LogFile, String> {
+        prepare_log_dir(dir)?;
+        let path = dir.join(format!("tiss-greetd-{}.log", component));
+        let file = open_log_file(&path)
+            .map_err(|err| format!("failed to open {}: {}", path.display(), err))?;
+        let now = unix_now();
+        let (size, started) = file
+            .metadata()
+            .map(|meta| {
+                let created = meta
+                    .created()
+                    .ok()
+                    .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                    .map(|duration| duration.as_secs())
+                    .unwrap_or(now);
+                (meta.len(), created)
+            })
+            .unwrap_or((0, now));
+        Ok(LogFile {
+            file: Some(file),
+            path,
+            rotation: Rotation::from_env(),
+            size,
+            started,
+        })
+    }
+
+    /// Appends `line`, rotating first if needed. False if no file is open.
+    fn write(&mut self, line: &str, now: u64, component: &str) -> bool {
+        if self.file.is_some() && self.needs_rotation(line.len() as u64 + 1, now) {
+            self.rotate(now, component);
+        }
+        let Some(file) = self.file.as_mut() else {
+            return false;
+        };
+        let _ = writeln!(file, "{}", line);
+        let _ = file.flush();
+        self.size += line.len() as u64 + 1;
+        true
+    }
+
+    fn needs_rotation(&self, incoming: u64, now: u64) -> bool {
+        if self.size == 0 {
+            return false;
+        }
+        let too_big = self.rotation.max_size > 0 && self.size + incoming > self.rotation.max_size;
+        let too_old = self.rotation.max_age_secs > 0
+            && now.saturating_sub(self.started) >= self.rotation.max_age_secs;
+        too_big || too_old
+    }
+
+    /// Shifts `<name>.<n>` to `<name>.<n+1>`, moves the current file to
+    /// `<name>.1` and starts a fresh one. Problems go to stderr; logging
+    /// carries on in whatever file is open afterwards.
+    fn rotate(&mut self, now: u64, component: &str) {
+        self.file = None;
+        let keep = self.rotation.keep;
+        let mut errors = Vec::new();
+        if keep == 0 {
+            if let Err(err) = fs::remove_file(&self.path) {
+                errors.push(format!("remove {}: {}", self.path.display(), err));
+            }
+        } else {
+            for gz in [false, true] {
+                let _ = fs::remove_file(self.rotated_path(keep, gz));
+            }
+            for n in (1..keep).rev() {
+                for gz in [false, true] {
+                    let from = self.rotated_path(n, gz);
+                    if from.exists() {
+                        if let Err(err) = fs::rename(&from, self.rotated_path(n + 1, gz)) {
+                            errors.push(format!("rename {}: {}", from.display(), err));
+                        }
+                    }
+                }
+            }
+            let first = self.rotated_path(1, false);
+            match fs::rename(&self.path, &first) {
+                Ok(()) if self.rotation.compress => {
+                    match gzip_file(&first, &self.rotated_path(1, true)) {
+                        Ok(()) => {
+                            let _ = fs::remove_file(&first);
+                        }
+                        Err(err) => errors.push(format!("compress {}: {}", first.display(), err)),
+                    }
+                }
+                Ok(()) => {}
+                Err(err) => errors.push(format!("rename {}: {}", self.path.display(), err)),
+            }
+        }
+        if self.rotation.max_age_secs > 0 {
+            self.remove_expired(now);
+        }
+
+        match open_log_file(&self.path) {
+            Ok(file) => {
+                self.size = file.metadata().map(|meta| meta.len()).unwrap_or(0);
+                self.file = Some(file);
+            }
+            Err(err) => errors.push(format!("reopen {}: {}", self.path.display(), err)),
+        }
+        self.started = now;
+        for err in errors {
+            eprintln!("tiss-greetd-{}: log rotation: {}", component, err);
+        }
+    }
+
+    /// Deletes rotated files not written to within `max_age_secs`.
+    fn remove_expired(&self, now: u64) {
+        for n in 1..=self.rotation.keep {
+            for gz in [false, true] {
+                let path = self.rotated_path(n, gz);
+                let Ok(meta) = fs::metadata(&path) else {
+                    continue;
+                };
+                let age = now.saturating_sub(meta.mtime().max(0) as u64);
+                if age >= self.rotation.max_age_secs {
+                    let _ = fs::remove_file(&path);
+                }
+            }
+        }
+    }
+
+    fn rotated_path(&self, n: u32, gz: bool) -> PathBuf {
+        let mut name = OsString::from(self.path.as_os_str());
+        name.push(format!(".{}", n));
+        if gz {
+            name.push(".gz");
+        }
+        PathBuf::from(name)
+    }
+}
+
+fn connect_socket(env_key: &str, default: &str) -> Result<UnixDatagram, String> {
+    let path = match std::env::var(env_key) {
+        Ok(path) if !path.trim().is_empty() => PathBuf::from(path),
+        _ => PathBuf::from(default),
+    };
+    let socket = UnixDatagram::unbound().map_err(|err| format!("socket: {}", err))?;
+    socket
+        .connect(&path)
+        .map_err(|err| format!("failed to connect to {}: {}", path.display(), err))?;
+    Ok(socket)
+}
+
+fn syslog_severity(level: Level) -> u8 {
+    match level {
+        Level::Debug => 7,
+        Level::Info => 6,
+        Level::Warn => 4,
+        Level::Error => 3,
+    }
+}
+
+/// One record in the journal's native protocol: `KEY=value` lines, or the
+/// length-prefixed form for values containing a newline.
+fn journal_datagram(level: Level, component: &str, message: &str, fields: &Fields<'_>) -> Vec<u8> {
+    let mut datagram = Vec::new();
+    let mut field = |key: &str, value: &str| {
+        datagram.extend_from_slice(key.as_bytes());
+        if value.contains('\n') {
+            datagram.push(b'\n');
+            datagram.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            datagram.push(b'=');
+        }
+        datagram.extend_from_slice(value.as_bytes());
+        datagram.push(b'\n');
+    };
+    field("MESSAGE", message);
+    field("PRIORITY", &syslog_severity(level).to_string());
+    field("SYSLOG_FACILITY", &FACILITY_AUTHPRIV.to_string());
+    field("SYSLOG_IDENTIFIER", IDENTIFIER);
+    field("TISS_COMPONENT", component);
+    if let Some(phase) = fields.phase {
+        field("TISS_PHASE", phase);
+    }
+    if let Some(attempt) = fields.attempt {
+        field("TISS_ATTEMPT", &attempt.to_string());
+    }
+    if let Some(user) = fields.user {
+        field("TISS_USER", user);
+    }
+    if let Some(duration_ms) = fields.duration_ms {
+        field("TISS_DURATION_MS", &duration_ms.to_string());
+    }
+    datagram
+}
+
+/// `message key=value ...`
+fn text_body(message: &str, fields: &Fields<'_>) -> String {
+    let mut body = message.to_string();
+    if let Some(attempt) = fields.attempt {
+        body.push_str(&format!(" attempt={}", attempt));
+    }
+    if let Some(user) = fields.user {
+        body.push_str(&format!(" user={}", user));
+    }
+    if let Some(duration_ms) = fields.duration_ms {
+        body.push_str(&format!(" duration_ms={}", duration_ms));
+    }
+    if let Some(phase) = fields.phase {
+        body.push_str(&format!(" phase={}", phase));
+    }
+    body
+}
+
+fn text_line(ts: u64, level: Level, component: &str, message: &str, fields: &Fields<'_>) -> String {
+    format!("[{}] {} {}: {}", ts, level.as_str(), component, text_body(message, fields))
+}
+
+fn json_line(ts: u64, level: Level, component: &str, message: &str, fields: &Fields<'_>) -> String {
+    let mut record = Map::new();
+    record.insert("ts".to_string(), Value::from(ts));
+    record.insert("level".to_string(), Value::from(level.as_str()));
+    record.insert("component".to_string(), Value::from(component));
+    record.insert("msg".to_string(), Value::from(message));
+    if let Some(attempt) = fields.attempt {
+        record.insert("attempt".to_string(), Value::from(attempt));
+    }
+    if let Some(user) = fields.user {
+        record.insert("user".to_string(), Value::from(user));
+    }
+    if let Some(duration_ms) = fields.duration_ms {
+        record.insert("duration_ms".to_string(), Value::from(duration_ms));
+    }
+    if let Some(phase) = fields.phase {
+        record.insert("phase".to_string(), Value::from(phase));
+    }
+    Value::Object(record).to_string()
+}
+
+/// Creates `dir` with mode 0700 if needed. Inside a world-writable parent
+/// such as `/tmp`, where anyone could have planted it first, an existing
+/// `dir` must be a real directory owned by us; a symlink is refused.
+pub fn prepare_log_dir(dir: &Path) -> Result<(), String> {
+    let shared_parent = dir
+        .parent()
+        .and_then(|parent| fs::metadata(parent).ok())
+        .map(|meta| meta.mode() & 0o002 != 0)
+        .unwrap_or(false);
+    match fs::symlink_metadata(dir) {
+        Ok(meta) => {
+            if !shared_parent {
+                return Ok(());
+            }
+            if meta.file_type().is_symlink() {
+                return Err(format!(
+                    "refusing symlinked log dir {} in a world-writable directory",
+                    dir.display()
+                ));
+            }
+            if !meta.is_dir() {
+                return Err(format!("log dir {} is not a directory", dir.display()));
+            }
+            let euid = unsafe { libc::geteuid() };
+            if meta.uid() != euid {
+                return Err(format!(
+                    "refusing log dir {} owned by uid {} (running as {})",
+                    dir.display(),
+                    meta.uid(),
+                    euid
+                ));
+            }
+            if meta.mode() & 0o077 != 0 {
+                fs::set_permissions(dir, fs::Permissions::from_mode(0o700))
+                    .map_err(|err| format!("failed to chmod {}: {}", dir.display(), err))?;
+            }
+            Ok(())
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)
+            .map_err(|err| format!("failed to create log dir {}: {}", dir.display(), err)),
+        Err(err) => Err(format!("failed to inspect log dir {}: {}", dir.display(), err)),
+    }
+}
+
+/// Opens `path` for appending with mode 0600, never through a symlink.
+fn open_log_file(path: &Path) -> io::Result<File> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .mode(0o600)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(path)?;
+    // Files created before the mode was enforced.
+    if file.metadata()?.mode() & 0o077 != 0 {
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(file)
+}
+
+fn gzip_file(from: &Path, to: &Path) -> io::Result<()> {
+    let mut input = File::open(from)?;
+    let output = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(to)?;
+    let mut encoder = GzEncoder::new(output, Compression::default());
+    io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?.sync_all()
+}
+
+/// Reads the HMAC key at `path` (the username hash key, the audit chain
+/// key), creating a random one (mode 0600) if there is none yet. Nothing is
+/// written when no randomness is available.
+pub fn load_key(path: &Path) -> io::Result<Vec<u8>> {
+    match fs::read(path) {
+        Ok(key) if key.len() >= 16 => return Ok(key),
+        Ok(_) => {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "key file too short"));
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err),
+    }
+    let key = random_bytes(32)?;
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(path)?;
+    file.write_all(&key)?;
+    file.sync_all()?;
+    Ok(key)
+}
+
+/// `len` bytes from `getrandom(2)`; an error rather than a partly filled
+/// buffer.
+fn random_bytes(len: usize) -> io::Result<Vec<u8>> {
+    let mut buf = vec![0u8; len];
+    let mut filled = 0;
+    while filled < len {
+        let rest = &mut buf[filled..];
+        let read = unsafe { libc::getrandom(rest.as_mut_ptr() as *mut libc::c_void, rest.len(), 0) };
+        if read < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+        if read == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "getrandom returned no bytes"));
+        }
+        filled += read as usize;
+    }
+    Ok(buf)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+pub fn default_log_dir() -> PathBuf {
+    if let Ok(dir) = std::env::var("TISS_GREETD_LOG_DIR") {
+        if !dir.trim().is_empty() {
+            return PathBuf::from(dir);
+        }
+    }
+
+    if let Some(uid) = read_uid_from_proc() {
+        return PathBuf::from(format!("/tmp/tiss-greetd-{}", uid));
+    }
+
+    if let Ok(user) = std::env::var("USER") {
+        if !user.trim().is_empty() {
+            return PathBuf::from(format!("/tmp/tiss-greetd-{}", user));
+        }
+    }
+
+    PathBuf::from("/tmp/tiss-greetd")
+}
+
+fn read_uid_from_proc() -> Option<String> {
+    let content = std::fs::read_to_string("/proc/self/status").ok()?;
+    for line in content.lines() {
+        if let Some(rest) = line.strip_prefix("Uid:") {
+            let uid = rest.split_whitespace().next()?;
+            return Some(uid.to_string());
+        }
+    }
+    None
+}
@@ -0,0 +1,23 @@
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use tiss_greetd_logging::load_key;
+
+#[test]
+fn key_is_created_once() {
+    let dir = std::env::temp_dir().join(format!("tiss-greetd-hash-key-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    let path = dir.join("log-hash.key");
+    let key = load_key(&path).unwrap();
+    assert_eq!(key.len(), 32);
+    assert!(key.iter().any(|byte| *byte != 0));
+    assert_eq!(fs::metadata(&path).unwrap().mode() & 0o777, 0o600);
+    assert_eq!(load_key(&path).unwrap(), key);
+
+    let other = load_key(&dir.join("other.key")).unwrap();
+    assert_ne!(other, key);
+
+    fs::write(&path, b"short").unwrap();
+    assert!(load_key(&path).is_err());
+}
@@ -0,0 +1,286 @@
+//! Scriptable greetd stand-in for end-to-end tests.
+//!
+//! A [`Scenario`] describes the PAM conversation greetd would relay: the
+//! auth messages to send, the answers to expect, and how authentication and
+//! `start_session` end. [`Server`] plays it to every client that connects.
+
+use serde::Deserialize;
+use std::io;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tiss_greetd_ipc::{AuthMessageType, Request, Response, Secret};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Scenario {
+    /// Only this user may log in; others get `PAM_USER_UNKNOWN`.
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub steps: Vec<Step>,
+    /// Sent after the last step instead of `success`.
+    #[serde(default)]
+    pub auth_error: Option<Reply>,
+    /// Sent when a prompt answer does not match `expect`.
+    #[serde(default = "Reply::default_mismatch")]
+    pub mismatch: Reply,
+    /// Sent in response to `start_session` instead of `success`.
+    #[serde(default)]
+    pub start_error: Option<Reply>,
+    /// Delay before answering `start_session`.
+    #[serde(default)]
+    pub start_delay_ms: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Step {
+    #[serde(rename = "type")]
+    pub kind: AuthMessageType,
+    #[serde(default)]
+    pub message: String,
+    /// Expected answer for `visible`/`secret` steps; any answer if unset.
+    #[serde(default)]
+    pub expect: Option<String>,
+    /// Delay before this auth message is sent (simulates slow PAM modules).
+    #[serde(default)]
+    pub delay_ms: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Reply {
+    #[serde(default = "Reply::default_error_type")]
+    pub error_type: String,
+    pub description: String,
+}
+
+impl Reply {
+    fn default_error_type() -> String {
+        "auth_error".to_string()
+    }
+
+    fn default_mismatch() -> Reply {
+        Reply {
+            error_type: Reply::default_error_type(),
+            description: "PAM_AUTH_ERR: Authentication failure".to_string(),
+        }
+    }
+
+    fn to_response(&self) -> Response {
+        Response::Error {
+            error_type: self.error_type.clone(),
+            description: self.description.clone(),
+        }
+    }
+}
+
+impl Scenario {
+    /// Loads a scenario; `.json` files are parsed as JSON, anything else as
+    /// TOML.
+    pub fn load(path: &Path) -> Result<Scenario, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+        let parsed = if path.extension().and_then(|ext| ext.to_str()) == Some("json") {
+            serde_json::from_str(&content).map_err(|err| err.to_string())
+        } else {
+            toml::from_str(&content).map_err(|err| err.to_string())
+        };
+        parsed.map_err(|err| format!("invalid scenario {}: {}", path.display(), err))
+    }
+}
+
+/// A `start_session` the server accepted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StartedSession {
+    pub cmd: Vec<String>,
+    pub env: Vec<String>,
+}
+
+pub struct Server {
+    listener: UnixListener,
+    path: PathBuf,
+    scenario: Scenario,
+    verbose: bool,
+    started: Arc<Mutex<Vec<StartedSession>>>,
+}
+
+impl Server {
+    /// Binds `path`, replacing a stale socket file.
+    pub fn bind(path: impl Into<PathBuf>, scenario: Scenario) -> io::Result<Server> {
+        let path = path.into();
+        if path.exists() {
+            std::fs::remove_file(&path)?;
+        }
+        let listener = UnixListener::bind(&path)?;
+        Ok(Server {
+            listener,
+            path,
+            scenario,
+            verbose: false,
+            started: Arc::default(),
+        })
+    }
+
+    pub fn verbose(mut self, verbose: bool) -> Server {
+        self.verbose = verbose;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Sessions started so far; still filled in after [`Server::spawn`].
+    pub fn started(&self) -> Arc<Mutex<Vec<StartedSession>>> {
+        Arc::clone(&self.started)
+    }
+
+    /// Accepts clients one at a time, forever.
+    pub fn serve(&self) -> io::Result<()> {
+        loop {
+            let (stream, _) = self.listener.accept()?;
+            if let Err(err) = self.handle_client(stream) {
+                self.note(&format!("client error: {}", err));
+            }
+        }
+    }
+
+    pub fn spawn(self) -> thread::JoinHandle<io::Result<()>> {
+        thread::spawn(move || self.serve())
+    }
+
+    fn note(&self, message: &str) {
+        if self.verbose {
+            eprintln!("tiss-greetd-mock: {}", message);
+        }
+    }
+
+    fn handle_client(&self, mut stream: UnixStream) -> Result<(), tiss_greetd_ipc::Error> {
+        let mut conversation: Option<Conversation> = None;
+        loop {
+            let req = match tiss_greetd_ipc::read_request(&mut stream) {
+                Ok(req) => req,
+                Err(tiss_greetd_ipc::Error::Io(err)) if disconnected(&err) => return Ok(()),
+                Err(err) => return Err(err),
+            };
+            let resp = match req {
+                Request::CreateSession { username } => {
+                    self.note(&format!("create_session {}", username));
+                    if conversation.is_some() {
+                        error("error", "a session is already being configured")
+                    } else if self
+                        .scenario
+                        .username
+                        .as_ref()
+                        .is_some_and(|expected| *expected != username)
+                    {
+                        error("auth_error", "PAM_USER_UNKNOWN: User not known")
+                    } else {
+                        let conv = conversation.insert(Conversation::default());
+                        conv.next(&self.scenario)
+                    }
+                }
+                Request::PostAuthMessageResponse { response } => {
+                    self.note("post_auth_message_response");
+                    match conversation.as_mut() {
+                        Some(conv) if !conv.authenticated => {
+                            let resp = conv.answer(&self.scenario, response.as_ref().map(Secret::expose));
+                            if matches!(resp, Response::Error { .. }) {
+                                conversation = None;
+                            }
+                            resp
+                        }
+                        _ => error("error", "no auth message pending"),
+                    }
+                }
+                Request::StartSession { cmd, env } => {
+                    self.note(&format!("start_session {:?} env {:?}", cmd, env));
+                    match conversation.as_ref() {
+                        Some(conv) if conv.authenticated => {
+                            thread::sleep(Duration::from_millis(self.scenario.start_delay_ms));
+                            match self.scenario.start_error.as_ref() {
+                                Some(reply) => reply.to_response(),
+                                None => {
+                                    if let Ok(mut started) = self.started.lock() {
+                                        started.push(StartedSession { cmd, env });
+                                    }
+                                    Response::Success
+                                }
+                            }
+                        }
+                        _ => error("error", "session not authenticated"),
+                    }
+                }
+                Request::CancelSession => {
+                    self.note("cancel_session");
+                    conversation = None;
+                    Response::Success
+                }
+            };
+            match tiss_greetd_ipc::write_response(&mut stream, &resp) {
+                Err(tiss_greetd_ipc::Error::Io(err)) if disconnected(&err) => return Ok(()),
+                result => result?,
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct Conversation {
+    step: usize,
+    authenticated: bool,
+}
+
+impl Conversation {
+    /// Sends the current step, or the final auth result once all steps ran.
+    fn next(&mut self, scenario: &Scenario) -> Response {
+        match scenario.steps.get(self.step) {
+            Some(step) => {
+                thread::sleep(Duration::from_millis(step.delay_ms));
+                Response::AuthMessage {
+                    auth_message_type: step.kind,
+                    auth_message: step.message.clone(),
+                }
+            }
+            None => match scenario.auth_error.as_ref() {
+                Some(reply) => reply.to_response(),
+                None => {
+                    self.authenticated = true;
+                    Response::Success
+                }
+            },
+        }
+    }
+
+    fn answer(&mut self, scenario: &Scenario, response: Option<&str>) -> Response {
+        let Some(step) = scenario.steps.get(self.step) else {
+            return error("error", "no auth message pending");
+        };
+        let needs_answer = matches!(step.kind, AuthMessageType::Visible | AuthMessageType::Secret);
+        if needs_answer {
+            if let Some(expected) = step.expect.as_deref() {
+                if response != Some(expected) {
+                    return scenario.mismatch.to_response();
+                }
+            }
+        }
+        self.step += 1;
+        self.next(scenario)
+    }
+}
+
+/// Clients may hang up right after `cancel_session` without reading the reply.
+fn disconnected(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::UnexpectedEof | io::ErrorKind::BrokenPipe | io::ErrorKind::ConnectionReset
+    )
+}
+
+fn error(error_type: &str, description: &str) -> Response {
+    Response::Error {
+        error_type: error_type.to_string(),
+        description: description.to_string(),
+    }
+}
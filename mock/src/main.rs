@@ -0,0 +1,112 @@
+use std::env;
+use std::path::PathBuf;
+use std::process::Command;
+use tiss_greetd_mock::{Scenario, Server};
+
+#[derive(Debug, Default)]
+struct Options {
+    socket: Option<PathBuf>,
+    scenario: Option<PathBuf>,
+    verbose: bool,
+    command: Vec<String>,
+}
+
+fn main() {
+    let options = match parse_args() {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("tiss-greetd-mock: {}", err);
+            print_usage();
+            std::process::exit(2);
+        }
+    };
+
+    match run(options) {
+        Ok(code) => std::process::exit(code),
+        Err(err) => {
+            eprintln!("tiss-greetd-mock: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn run(options: Options) -> Result<i32, String> {
+    let scenario_path = options
+        .scenario
+        .ok_or_else(|| "--scenario is required".to_string())?;
+    let scenario = Scenario::load(&scenario_path)?;
+    let socket = options.socket.unwrap_or_else(default_socket_path);
+    let server = Server::bind(&socket, scenario)
+        .map_err(|err| format!("failed to bind {}: {}", socket.display(), err))?
+        .verbose(options.verbose);
+
+    if options.command.is_empty() {
+        println!("GREETD_SOCK={}", socket.display());
+        server
+            .serve()
+            .map_err(|err| format!("accept failed: {}", err))?;
+        return Ok(0);
+    }
+
+    let _server = server.spawn();
+    let status = Command::new(&options.command[0])
+        .args(&options.command[1..])
+        .env("GREETD_SOCK", &socket)
+        .status()
+        .map_err(|err| format!("failed to run {}: {}", options.command[0], err));
+    let _ = std::fs::remove_file(&socket);
+    Ok(status?.code().unwrap_or(1))
+}
+
+fn default_socket_path() -> PathBuf {
+    let dir = env::var("XDG_RUNTIME_DIR")
+        .ok()
+        .filter(|dir| !dir.trim().is_empty())
+        .unwrap_or_else(|| "/tmp".to_string());
+    PathBuf::from(dir).join(format!("tiss-greetd-mock-{}.sock", std::process::id()))
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                print_usage();
+                std::process::exit(0);
+            }
+            "-s" | "--socket" => {
+                options.socket = Some(PathBuf::from(next_arg(&mut args, &arg)?));
+            }
+            "--scenario" => {
+                options.scenario = Some(PathBuf::from(next_arg(&mut args, &arg)?));
+            }
+            "-v" | "--verbose" => {
+                options.verbose = true;
+            }
+            "--" => {
+                options.command = args.by_ref().collect();
+            }
+            _ => return Err(format!("unknown option: {}", arg)),
+        }
+    }
+    Ok(options)
+}
+
+fn next_arg(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("{} expects a value", flag))
+}
+
+fn print_usage() {
+    println!(
+        "Usage: tiss-greetd-mock --scenario <file> [options] [-- command...]\n\
+      --scenario <path>        Scenario file (.toml or .json)\n\
+  -s, --socket <path>          Socket path (default: $XDG_RUNTIME_DIR/tiss-greetd-mock-<pid>.sock)\n\
+  -v, --verbose                Log greetd requests to stderr\n\
+  -h, --help                   Show this help\n\
+\n\
+With a command, it runs with GREETD_SOCK set and its exit code is returned.\n\
+Without one, GREETD_SOCK=<path> is printed and the mock serves until killed."
+    );
+}
@@ -0,0 +1,224 @@
+//! Types of the JSON-lines protocol between the backend and the UI,
+//! described in `README.md` next to this crate.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use std::borrow::Cow;
+use tiss_greetd_ipc::Secret;
+
+mod phase;
+
+pub use phase::Phase;
+
+/// Version of this protocol spoken by the backend.
+pub const PROTOCOL_VERSION: u32 = 3;
+/// Oldest UI protocol version the backend still serves.
+pub const MIN_UI_VERSION: u32 = 2;
+/// Newest UI protocol version the backend knows about. Newer UIs are told to
+/// fall back to `PROTOCOL_VERSION`.
+pub const MAX_UI_VERSION: u32 = PROTOCOL_VERSION;
+
+/// Messages sent by the UI.
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(tag = "type")]
+pub enum UiRequest {
+    /// Announces the UI protocol version; answered with `hello` and `state`.
+    #[serde(rename = "hello")]
+    Hello { ui_version: u32 },
+    /// Starts authentication for `username` with an optional launch selection.
+    #[serde(rename = "auth")]
+    Auth {
+        username: String,
+        #[serde(default)]
+        command: Vec<String>,
+        #[serde(default)]
+        env: std::collections::HashMap<String, String>,
+        #[serde(default)]
+        session_id: Option<String>,
+        #[serde(default)]
+        profile_id: Option<String>,
+        #[serde(default)]
+        locale: Option<String>,
+        #[serde(default)]
+        defer_start: bool,
+    },
+    /// Answers prompt `id`; `null` acknowledges a message without input.
+    #[serde(rename = "prompt_response")]
+    PromptResponse {
+        id: u64,
+        #[serde(default)]
+        #[schemars(with = "Option<String>")]
+        response: Option<Secret>,
+    },
+    /// Aborts the current authentication.
+    #[serde(rename = "cancel")]
+    Cancel,
+    /// Liveness check; answered with `pong`.
+    #[serde(rename = "ping")]
+    Ping,
+    /// Confirms `success` (kind `success`) so the backend can exit.
+    #[serde(rename = "ack")]
+    Ack { kind: String },
+    /// Starts the session after a `defer_start` authentication.
+    #[serde(rename = "start")]
+    Start {
+        #[serde(default)]
+        command: Vec<String>,
+        #[serde(default)]
+        env: std::collections::HashMap<String, String>,
+        #[serde(default)]
+        session_id: Option<String>,
+        #[serde(default)]
+        profile_id: Option<String>,
+        #[serde(default)]
+        locale: Option<String>,
+    },
+    /// Requests a power action (`poweroff`, `reboot`, `suspend`).
+    #[serde(rename = "power")]
+    Power { action: String },
+    /// The user picked `username`; answered with `user_selection`.
+    #[serde(rename = "select_user")]
+    SelectUser { username: String },
+}
+
+/// Messages sent by the backend.
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(tag = "type")]
+pub enum BackendResponse {
+    /// Reply to `hello`: versions and capabilities.
+    #[serde(rename = "hello")]
+    Hello {
+        protocol_version: u32,
+        min_ui_version: u32,
+        max_ui_version: u32,
+        capabilities: Vec<String>,
+        power_actions: Vec<String>,
+    },
+    /// Login-capable users for a user picker; sent after `hello`, before
+    /// `state`, when the launcher provided a list.
+    #[serde(rename = "users")]
+    Users { users: Vec<UserInfo> },
+    /// Current backend phase.
+    #[serde(rename = "state")]
+    State { phase: Phase },
+    /// PAM question that needs a `prompt_response`.
+    #[serde(rename = "prompt")]
+    Prompt {
+        id: u64,
+        kind: String,
+        message: String,
+        echo: bool,
+    },
+    /// PAM info or error text; no answer needed.
+    #[serde(rename = "message")]
+    Message { kind: String, message: String },
+    /// Failure with a normalized `code`.
+    #[serde(rename = "error")]
+    Error {
+        code: String,
+        message: String,
+        /// Set with `rate_limited`: seconds until another attempt is allowed.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        retry_after_secs: Option<u64>,
+    },
+    /// Sent after a successful auth, before `success`: when this user last
+    /// logged in and how many failed attempts were recorded since.
+    #[serde(rename = "last_login")]
+    LastLogin {
+        /// Unix time of the previous successful login; absent on the first.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        previous_login: Option<u64>,
+        failed_attempts: u32,
+    },
+    /// Reply to `select_user`: the session, profile and locale this user
+    /// started last, each falling back to the last one started by anyone.
+    #[serde(rename = "user_selection")]
+    UserSelection {
+        username: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        session_id: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        profile_id: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        locale: Option<String>,
+    },
+    /// Autologin countdown, once per second; the login starts when it
+    /// reaches zero unless the UI sends any request but `hello` or `ping`.
+    #[serde(rename = "autologin")]
+    Autologin { username: String, remaining_secs: u64 },
+    /// The countdown was aborted by UI input.
+    #[serde(rename = "autologin_cancelled")]
+    AutologinCancelled,
+    /// The session was started; the UI must `ack` it.
+    #[serde(rename = "success")]
+    Success,
+    /// Reply to `ping`.
+    #[serde(rename = "pong")]
+    Pong,
+}
+
+/// One entry of `users`, as listed in `TISS_GREETD_USERS_JSON`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct UserInfo {
+    pub name: String,
+    /// First GECOS field; absent when empty.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub real_name: Option<String>,
+    pub uid: u32,
+    pub home: String,
+    /// Image path readable by the UI (AccountsService icon or copied `~/.face`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avatar: Option<String>,
+}
+
+/// Parses one UI line.
+///
+/// `prompt_response` is decoded without going through the tagged enum:
+/// serde buffers internally tagged fields (and escaped strings) in its own
+/// allocations, which would leave copies of the answer behind. Here the raw
+/// JSON text of `response` is decoded straight into a [`Secret`].
+pub fn parse_request(line: &str) -> serde_json::Result<UiRequest> {
+    #[derive(Deserialize)]
+    struct Tag<'a> {
+        #[serde(rename = "type", borrow, default)]
+        kind: Option<Cow<'a, str>>,
+    }
+
+    #[derive(Deserialize)]
+    struct PromptResponseLine<'a> {
+        id: u64,
+        #[serde(borrow, default)]
+        response: Option<&'a RawValue>,
+    }
+
+    let tag: Tag = serde_json::from_str(line)?;
+    if tag.kind.as_deref() != Some("prompt_response") {
+        return serde_json::from_str(line);
+    }
+    let parsed: PromptResponseLine = serde_json::from_str(line)?;
+    let response = match parsed.response.map(RawValue::get) {
+        None | Some("null") => None,
+        Some(raw) => Some(Secret::from_json_literal(raw).map_err(serde::de::Error::custom)?),
+    };
+    Ok(UiRequest::PromptResponse {
+        id: parsed.id,
+        response,
+    })
+}
+
+/// JSON Schema (draft-07) covering both directions. `UiRequest` and
+/// `BackendResponse` are under `definitions`; the root accepts either.
+pub fn schema() -> serde_json::Value {
+    let mut generator = schemars::gen::SchemaSettings::draft07().into_generator();
+    let ui = generator.subschema_for::<UiRequest>();
+    let backend = generator.subschema_for::<BackendResponse>();
+    let definitions = generator.take_definitions();
+    serde_json::json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "tiss-greetd UI protocol",
+        "description": format!("JSON Lines protocol version {}", PROTOCOL_VERSION),
+        "oneOf": [ui, backend],
+        "definitions": definitions,
+    })
+}
@@ -0,0 +1,129 @@
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// Backend phase as reported to the UI in `state` messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    /// Ready for input, no authentication in progress.
+    Idle,
+    /// PAM conversation in progress.
+    Auth,
+    /// Credentials accepted for a `defer_start` auth; waiting for `start`.
+    Authenticated,
+    /// `start_session` sent, waiting for greetd.
+    Waiting,
+    /// Last attempt failed; an `error` message follows.
+    Error,
+    /// Session started; waiting for the UI's `ack`.
+    Success,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 6] = [
+        Phase::Idle,
+        Phase::Auth,
+        Phase::Authenticated,
+        Phase::Waiting,
+        Phase::Error,
+        Phase::Success,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Phase::Idle => "idle",
+            Phase::Auth => "auth",
+            Phase::Authenticated => "authenticated",
+            Phase::Waiting => "waiting",
+            Phase::Error => "error",
+            Phase::Success => "success",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Phase> {
+        Phase::ALL
+            .into_iter()
+            .find(|phase| phase.as_str() == value)
+    }
+
+    /// Phases reachable from `self`. `Success` is terminal.
+    pub fn successors(self) -> &'static [Phase] {
+        match self {
+            Phase::Idle => &[Phase::Auth],
+            Phase::Auth => &[Phase::Authenticated, Phase::Waiting, Phase::Error, Phase::Idle],
+            Phase::Authenticated => &[Phase::Waiting, Phase::Error, Phase::Idle],
+            Phase::Waiting => &[Phase::Success, Phase::Error, Phase::Idle],
+            Phase::Error => &[Phase::Auth, Phase::Idle],
+            Phase::Success => &[],
+        }
+    }
+
+    pub fn can_transition_to(self, next: Phase) -> bool {
+        self.successors().contains(&next)
+    }
+}
+
+impl std::fmt::Display for Phase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Phase;
+
+    /// The table from `protocol/README.md`.
+    const LEGAL: &[(Phase, Phase)] = &[
+        (Phase::Idle, Phase::Auth),
+        (Phase::Auth, Phase::Authenticated),
+        (Phase::Auth, Phase::Waiting),
+        (Phase::Auth, Phase::Error),
+        (Phase::Auth, Phase::Idle),
+        (Phase::Authenticated, Phase::Waiting),
+        (Phase::Authenticated, Phase::Error),
+        (Phase::Authenticated, Phase::Idle),
+        (Phase::Waiting, Phase::Success),
+        (Phase::Waiting, Phase::Error),
+        (Phase::Waiting, Phase::Idle),
+        (Phase::Error, Phase::Auth),
+        (Phase::Error, Phase::Idle),
+    ];
+
+    #[test]
+    fn only_documented_transitions_are_allowed() {
+        for from in Phase::ALL {
+            for to in Phase::ALL {
+                assert_eq!(
+                    from.can_transition_to(to),
+                    LEGAL.contains(&(from, to)),
+                    "{} -> {}",
+                    from,
+                    to
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn impossible_moves_are_rejected() {
+        assert!(!Phase::Success.can_transition_to(Phase::Auth));
+        assert!(!Phase::Success.can_transition_to(Phase::Idle));
+        assert!(Phase::Success.successors().is_empty());
+        assert!(!Phase::Idle.can_transition_to(Phase::Success));
+        assert!(!Phase::Idle.can_transition_to(Phase::Waiting));
+        assert!(!Phase::Error.can_transition_to(Phase::Success));
+        // Staying put is not a transition either.
+        assert!(Phase::ALL.iter().all(|phase| !phase.can_transition_to(*phase)));
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for phase in Phase::ALL {
+            assert_eq!(Phase::parse(phase.as_str()), Some(phase));
+            assert_eq!(serde_json::to_value(phase).unwrap(), phase.as_str());
+        }
+        assert_eq!(Phase::parse("Idle"), None);
+        assert_eq!(Phase::parse(""), None);
+    }
+}
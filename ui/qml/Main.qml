@@ -30,12 +30,16 @@ ApplicationWindow {
     property bool promptActive: promptId >= 0
     property bool promptNeedsInput: promptKind === "visible" || promptKind === "secret"
     property string stagedPromptResponse: ""
+    property bool autologinActive: false
 
     BackendProcess {
         id: backend
         sessionCommand: tissSessionCommand
         sessionEnv: tissSessionEnv
         onPhaseChanged: {
+            if (phase !== "idle") {
+                autologinActive = false
+            }
             if (phase === "auth") {
                 statusText.text = "Authenticating..."
             } else if (phase === "waiting") {
@@ -80,11 +84,41 @@ ApplicationWindow {
         onMessageReceived: (kind, message) => {
             statusText.text = message
         }
+        onAutologinCountdown: (username, remainingSecs) => {
+            autologinActive = true
+            statusText.text = "Logging in as " + username + " in " + remainingSecs + "s. Press any key to cancel."
+        }
+        onAutologinCancelled: {
+            autologinActive = false
+            statusText.text = ""
+        }
+        onUserSelectionReceived: (username, sessionId, profileId, locale) => {
+            if (sessionId.length > 0) {
+                backend.selectedSessionId = sessionId
+            }
+            if (profileId.length > 0) {
+                backend.selectedProfileId = profileId
+            }
+            if (locale.length > 0) {
+                backend.selectedLocale = locale
+            }
+        }
+        onLastLoginReceived: (previousLogin, failedAttempts) => {
+            var text = previousLogin >= 0
+                ? "Last login: " + new Date(previousLogin * 1000).toLocaleString()
+                : "First login"
+            if (failedAttempts > 0) {
+                text += " (" + failedAttempts + " failed attempt" + (failedAttempts === 1 ? "" : "s") + " since)"
+            }
+            statusText.text = text
+        }
     }
 
     Component.onCompleted: {
         if (defaultUser.length > 0) {
             usernameField.text = defaultUser
+        } else if (tissLastUser.length > 0) {
+            usernameField.text = tissLastUser
         }
         if (lockUser && defaultUser.length === 0) {
             statusText.text = "TISS_GREETD_DEFAULT_USER is required"
@@ -148,6 +182,13 @@ ApplicationWindow {
         }
     }
 
+    function cancelAutologin() {
+        if (autologinActive) {
+            autologinActive = false
+            backend.cancelAuth()
+        }
+    }
+
     function clearPrompt() {
         promptId = -1
         promptKind = ""
@@ -189,6 +230,9 @@ ApplicationWindow {
             GradientStop { position: 0.0; color: "#0e0f12" }
             GradientStop { position: 1.0; color: "#1b1f2a" }
         }
+        TapHandler {
+            onTapped: root.cancelAutologin()
+        }
     }
 
     ColumnLayout {
@@ -227,6 +271,12 @@ ApplicationWindow {
             readOnly: lockUser
             visible: !lockUser
             enabled: !busy
+            Keys.onPressed: root.cancelAutologin()
+            onEditingFinished: {
+                if (text.length > 0 && backend.hasCapability("user_selection")) {
+                    backend.selectUser(text)
+                }
+            }
         }
 
         TextField {
@@ -237,6 +287,7 @@ ApplicationWindow {
             Layout.alignment: Qt.AlignHCenter
             enabled: !busy
             visible: !root.promptActive
+            Keys.onPressed: root.cancelAutologin()
             onAccepted: root.doLogin()
         }
 
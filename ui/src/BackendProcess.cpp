@@ -8,13 +8,25 @@
 #include <QJsonObject>
 #include <QJsonValue>
 #include <QStandardPaths>
+#include <QTimer>
 
 BackendProcess::BackendProcess(QObject *parent)
     : QObject(parent) {
-    startBackend();
+    m_socketPath = qEnvironmentVariable("TISS_GREETD_BACKEND_SOCKET");
+    if (m_socketPath.isEmpty()) {
+        startBackend();
+        return;
+    }
+    connect(&m_socket, &QLocalSocket::readyRead, this, &BackendProcess::handleReadyRead);
+    connect(&m_socket, &QLocalSocket::connected, this, &BackendProcess::sendHello);
+    connect(&m_socket, &QLocalSocket::errorOccurred, this, &BackendProcess::handleSocketError);
+    connect(&m_socket, &QLocalSocket::disconnected, this, &BackendProcess::handleSocketDisconnected);
+    connectSocket();
 }
 
 BackendProcess::~BackendProcess() {
+    // A socket-mode backend keeps running so the next UI can reattach.
+    disconnect(&m_socket, nullptr, this, nullptr);
     if (m_proc.state() != QProcess::NotRunning) {
         m_proc.terminate();
         m_proc.waitForFinished(1000);
@@ -26,7 +38,7 @@ void BackendProcess::startBackend() {
     m_proc.setProgram(backendPath);
     m_proc.setProcessChannelMode(QProcess::SeparateChannels);
 
-    connect(&m_proc, &QProcess::readyReadStandardOutput, this, &BackendProcess::handleStdout);
+    connect(&m_proc, &QProcess::readyReadStandardOutput, this, &BackendProcess::handleReadyRead);
     connect(&m_proc, QOverload<int, QProcess::ExitStatus>::of(&QProcess::finished),
             this, &BackendProcess::handleFinished);
     connect(&m_proc, &QProcess::errorOccurred, this, &BackendProcess::handleError);
@@ -35,6 +47,55 @@ void BackendProcess::startBackend() {
     m_proc.start();
 }
 
+// Socket mode: attach to a backend that is already listening (e.g. after a UI
+// restart) or spawn one with --listen and retry until it is up.
+void BackendProcess::connectSocket() {
+    m_connectAttempts++;
+    m_socket.connectToServer(m_socketPath);
+}
+
+void BackendProcess::handleSocketError(QLocalSocket::LocalSocketError error) {
+    if (m_socket.state() == QLocalSocket::ConnectedState || m_allowExit) {
+        return;
+    }
+    const bool notListening = error == QLocalSocket::ServerNotFoundError
+        || error == QLocalSocket::ConnectionRefusedError;
+    if (notListening && m_connectAttempts < 50) {
+        if (!m_listenerSpawned) {
+            m_listenerSpawned = true;
+            QProcess::startDetached(resolveBackendPath(), {"--listen", m_socketPath});
+        }
+        QTimer::singleShot(100, this, &BackendProcess::connectSocket);
+        return;
+    }
+    const QString msg = QString("backend socket error: %1").arg(m_socket.errorString());
+    emit errorReceived(QStringLiteral("backend_crash"), msg);
+    emit backendCrashed(msg);
+}
+
+void BackendProcess::handleSocketDisconnected() {
+    if (m_allowExit) {
+        return;
+    }
+    const QString msg = QStringLiteral("backend socket closed");
+    emit errorReceived(QStringLiteral("backend_crash"), msg);
+    emit backendCrashed(msg);
+}
+
+QIODevice *BackendProcess::device() {
+    if (m_socketPath.isEmpty()) {
+        return &m_proc;
+    }
+    return &m_socket;
+}
+
+bool BackendProcess::connected() const {
+    if (m_socketPath.isEmpty()) {
+        return m_proc.state() != QProcess::NotRunning;
+    }
+    return m_socket.state() == QLocalSocket::ConnectedState;
+}
+
 void BackendProcess::authenticate(const QString &username) {
     m_allowExit = false;
     QJsonObject obj;
@@ -49,6 +110,9 @@ void BackendProcess::authenticate(const QString &username) {
     if (!m_selectedLocale.isEmpty()) {
         obj.insert("locale", m_selectedLocale);
     }
+    if (m_deferStart) {
+        obj.insert("defer_start", true);
+    }
     if (!m_sessionCommand.isEmpty()) {
         QJsonArray cmd;
         for (const auto &part : m_sessionCommand) {
@@ -92,11 +156,22 @@ void BackendProcess::startSession(const QStringList &command) {
     m_allowExit = false;
     QJsonObject obj;
     obj.insert("type", "start");
-    QJsonArray cmd;
-    for (const auto &part : command) {
-        cmd.append(part);
+    if (!m_selectedSessionId.isEmpty()) {
+        obj.insert("session_id", m_selectedSessionId);
+    }
+    if (!m_selectedProfileId.isEmpty()) {
+        obj.insert("profile_id", m_selectedProfileId);
+    }
+    if (!m_selectedLocale.isEmpty()) {
+        obj.insert("locale", m_selectedLocale);
+    }
+    if (!command.isEmpty()) {
+        QJsonArray cmd;
+        for (const auto &part : command) {
+            cmd.append(part);
+        }
+        obj.insert("command", cmd);
     }
-    obj.insert("command", cmd);
     if (!m_sessionEnv.isEmpty()) {
         QJsonObject envObj;
         for (auto it = m_sessionEnv.constBegin(); it != m_sessionEnv.constEnd(); ++it) {
@@ -114,6 +189,13 @@ void BackendProcess::requestPower(const QString &action) {
     sendJson(obj);
 }
 
+void BackendProcess::selectUser(const QString &username) {
+    QJsonObject obj;
+    obj.insert("type", "select_user");
+    obj.insert("username", username);
+    sendJson(obj);
+}
+
 void BackendProcess::ackSuccess() {
     QJsonObject obj;
     obj.insert("type", "ack");
@@ -161,40 +243,74 @@ void BackendProcess::setSelectedLocale(const QString &locale) {
     emit sessionConfigChanged();
 }
 
-void BackendProcess::handleStdout() {
-    while (m_proc.canReadLine()) {
-        const QByteArray line = m_proc.readLine();
-        QJsonParseError err;
-        const QJsonDocument doc = QJsonDocument::fromJson(line, &err);
-        if (err.error != QJsonParseError::NoError || !doc.isObject()) {
-            continue;
-        }
-        const QJsonObject obj = doc.object();
-        const QString type = obj.value("type").toString();
-        if (type == "state") {
-            m_phase = obj.value("phase").toString();
-            emit phaseChanged();
-        } else if (type == "prompt") {
-            emit promptReceived(
-                obj.value("id").toInt(),
-                obj.value("kind").toString(),
-                obj.value("message").toString(),
-                obj.value("echo").toBool());
-        } else if (type == "message") {
-            emit messageReceived(
-                obj.value("kind").toString(),
-                obj.value("message").toString());
-        } else if (type == "error") {
-            const QString code = obj.value("code").toString();
-            emit errorReceived(code.isEmpty() ? QStringLiteral("pam_error") : code,
-                               obj.value("message").toString());
-        } else if (type == "success") {
-            ackSuccess();
-            m_allowExit = true;
-            m_phase = "success";
-            emit phaseChanged();
-            emit success();
+void BackendProcess::setDeferStart(bool deferStart) {
+    if (m_deferStart == deferStart) {
+        return;
+    }
+    m_deferStart = deferStart;
+    emit sessionConfigChanged();
+}
+
+void BackendProcess::handleReadyRead() {
+    QIODevice *dev = device();
+    while (dev->canReadLine()) {
+        handleLine(dev->readLine());
+    }
+}
+
+void BackendProcess::handleLine(const QByteArray &line) {
+    QJsonParseError err;
+    const QJsonDocument doc = QJsonDocument::fromJson(line, &err);
+    if (err.error != QJsonParseError::NoError || !doc.isObject()) {
+        return;
+    }
+    const QJsonObject obj = doc.object();
+    const QString type = obj.value("type").toString();
+    if (type == "hello") {
+        m_protocolVersion = obj.value("protocol_version").toInt();
+        m_capabilities.clear();
+        for (const auto &cap : obj.value("capabilities").toArray()) {
+            m_capabilities.append(cap.toString());
         }
+        emit capabilitiesChanged();
+    } else if (type == "state") {
+        m_phase = obj.value("phase").toString();
+        emit phaseChanged();
+    } else if (type == "prompt") {
+        emit promptReceived(
+            obj.value("id").toInt(),
+            obj.value("kind").toString(),
+            obj.value("message").toString(),
+            obj.value("echo").toBool());
+    } else if (type == "message") {
+        emit messageReceived(
+            obj.value("kind").toString(),
+            obj.value("message").toString());
+    } else if (type == "error") {
+        const QString code = obj.value("code").toString();
+        emit errorReceived(code.isEmpty() ? QStringLiteral("pam_error") : code,
+                           obj.value("message").toString());
+    } else if (type == "autologin") {
+        emit autologinCountdown(obj.value("username").toString(), obj.value("remaining_secs").toInt());
+    } else if (type == "autologin_cancelled") {
+        emit autologinCancelled();
+    } else if (type == "user_selection") {
+        emit userSelectionReceived(obj.value("username").toString(),
+                                   obj.value("session_id").toString(),
+                                   obj.value("profile_id").toString(),
+                                   obj.value("locale").toString());
+    } else if (type == "users") {
+        emit usersReceived(obj.value("users").toArray().toVariantList());
+    } else if (type == "last_login") {
+        emit lastLoginReceived(
+            static_cast<qint64>(obj.value("previous_login").toDouble(-1)),
+            obj.value("failed_attempts").toInt());
+    } else if (type == "success") {
+        ackSuccess();
+        m_allowExit = true;
+        m_phase = "success";
+        emit phaseChanged();
+        emit success();
     }
 }
 
@@ -271,18 +387,19 @@ QString BackendProcess::resolveBackendPath() const {
 void BackendProcess::sendHello() {
     QJsonObject obj;
     obj.insert("type", "hello");
-    obj.insert("ui_version", 2);
+    obj.insert("ui_version", 3);
     sendJson(obj);
 }
 
 void BackendProcess::sendJson(const QJsonObject &obj) {
-    if (m_proc.state() == QProcess::NotRunning) {
+    if (!connected()) {
         emit backendCrashed("backend is not running");
         return;
     }
     const QJsonDocument doc(obj);
     QByteArray payload = doc.toJson(QJsonDocument::Compact);
     payload.append('\n');
-    m_proc.write(payload);
-    m_proc.waitForBytesWritten(100);
+    QIODevice *dev = device();
+    dev->write(payload);
+    dev->waitForBytesWritten(100);
 }
@@ -1,5 +1,6 @@
 #pragma once
 
+#include <QLocalSocket>
 #include <QObject>
 #include <QProcess>
 #include <QStringList>
@@ -14,6 +15,9 @@ class BackendProcess : public QObject {
     Q_PROPERTY(QString selectedSessionId READ selectedSessionId WRITE setSelectedSessionId NOTIFY sessionConfigChanged)
     Q_PROPERTY(QString selectedProfileId READ selectedProfileId WRITE setSelectedProfileId NOTIFY sessionConfigChanged)
     Q_PROPERTY(QString selectedLocale READ selectedLocale WRITE setSelectedLocale NOTIFY sessionConfigChanged)
+    Q_PROPERTY(bool deferStart READ deferStart WRITE setDeferStart NOTIFY sessionConfigChanged)
+    Q_PROPERTY(int protocolVersion READ protocolVersion NOTIFY capabilitiesChanged)
+    Q_PROPERTY(QStringList capabilities READ capabilities NOTIFY capabilitiesChanged)
 public:
     explicit BackendProcess(QObject *parent = nullptr);
     ~BackendProcess() override;
@@ -24,7 +28,9 @@ public:
     Q_INVOKABLE void cancelAuth();
     Q_INVOKABLE void startSession(const QStringList &command);
     Q_INVOKABLE void requestPower(const QString &action);
+    Q_INVOKABLE void selectUser(const QString &username);
     Q_INVOKABLE void ackSuccess();
+    Q_INVOKABLE bool hasCapability(const QString &name) const { return m_capabilities.contains(name); }
 
     QString phase() const { return m_phase; }
     bool busy() const { return m_phase == "auth" || m_phase == "waiting"; }
@@ -33,12 +39,16 @@ public:
     QString selectedSessionId() const { return m_selectedSessionId; }
     QString selectedProfileId() const { return m_selectedProfileId; }
     QString selectedLocale() const { return m_selectedLocale; }
+    bool deferStart() const { return m_deferStart; }
+    int protocolVersion() const { return m_protocolVersion; }
+    QStringList capabilities() const { return m_capabilities; }
 
     void setSessionCommand(const QStringList &command);
     void setSessionEnv(const QVariantMap &env);
     void setSelectedSessionId(const QString &sessionId);
     void setSelectedProfileId(const QString &profileId);
     void setSelectedLocale(const QString &locale);
+    void setDeferStart(bool deferStart);
 
 signals:
     void phaseChanged();
@@ -46,21 +56,38 @@ signals:
     void messageReceived(const QString &kind, const QString &message);
     void errorReceived(const QString &code, const QString &message);
     void success();
+    void lastLoginReceived(qint64 previousLogin, int failedAttempts);
+    void usersReceived(const QVariantList &users);
+    void autologinCountdown(const QString &username, int remainingSecs);
+    void autologinCancelled();
+    void userSelectionReceived(const QString &username, const QString &sessionId,
+                               const QString &profileId, const QString &locale);
     void backendCrashed(const QString &message);
     void sessionConfigChanged();
+    void capabilitiesChanged();
 
 private slots:
-    void handleStdout();
+    void handleReadyRead();
+    void handleSocketError(QLocalSocket::LocalSocketError error);
+    void handleSocketDisconnected();
     void handleFinished(int exitCode, QProcess::ExitStatus status);
     void handleError(QProcess::ProcessError error);
     void sendHello();
 
 private:
     void startBackend();
+    void connectSocket();
+    void handleLine(const QByteArray &line);
+    QIODevice *device();
+    bool connected() const;
     QString resolveBackendPath() const;
     void sendJson(const QJsonObject &obj);
 
     QProcess m_proc;
+    QLocalSocket m_socket;
+    QString m_socketPath;
+    bool m_listenerSpawned = false;
+    int m_connectAttempts = 0;
     QString m_phase = "idle";
     bool m_allowExit = false;
     QStringList m_sessionCommand;
@@ -68,4 +95,7 @@ private:
     QString m_selectedSessionId;
     QString m_selectedProfileId;
     QString m_selectedLocale;
+    bool m_deferStart = false;
+    int m_protocolVersion = 0;
+    QStringList m_capabilities;
 };
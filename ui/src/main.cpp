@@ -118,6 +118,27 @@ static QVariantList parseProfilesJson(const QString &raw) {
     return result;
 }
 
+static QVariantList parseUsersJson(const QString &raw) {
+    QVariantList result;
+    if (raw.trimmed().isEmpty()) {
+        return result;
+    }
+    QJsonParseError err;
+    const QJsonDocument doc = QJsonDocument::fromJson(raw.toUtf8(), &err);
+    if (err.error != QJsonParseError::NoError || !doc.isArray()) {
+        qWarning() << "invalid TISS_GREETD_USERS_JSON";
+        return result;
+    }
+    const QJsonArray arr = doc.array();
+    for (const auto &value : arr) {
+        if (!value.isObject()) {
+            continue;
+        }
+        result << value.toObject().toVariantMap();
+    }
+    return result;
+}
+
 static QVariantMap parseLocalesJson(const QString &raw) {
     QVariantMap result;
     if (raw.trimmed().isEmpty()) {
@@ -529,6 +550,7 @@ int main(int argc, char *argv[]) {
         }
     });
     const QString defaultUser = qEnvironmentVariable("TISS_GREETD_DEFAULT_USER");
+    const QString lastUser = qEnvironmentVariable("TISS_GREETD_LAST_USER");
     const bool lockUser = envBool("TISS_GREETD_LOCK_USER", false);
     const bool showPasswordToggle = envBool("TISS_GREETD_SHOW_PASSWORD_TOGGLE", true);
     const QStringList sessionCommand = parseSessionCommandJson(qEnvironmentVariable("TISS_GREETD_SESSION_JSON"));
@@ -537,11 +559,13 @@ int main(int argc, char *argv[]) {
     const QString lastSessionId = qEnvironmentVariable("TISS_GREETD_LAST_SESSION_ID");
     const QVariantList profiles = parseProfilesJson(qEnvironmentVariable("TISS_GREETD_PROFILES_JSON"));
     const QVariantMap locales = parseLocalesJson(qEnvironmentVariable("TISS_GREETD_LOCALES_JSON"));
+    const QVariantList users = parseUsersJson(qEnvironmentVariable("TISS_GREETD_USERS_JSON"));
     const QVariantList powerActions = parsePowerActionsJson(qEnvironmentVariable("TISS_GREETD_POWER_ACTIONS_JSON"));
     const QString lastProfileId = qEnvironmentVariable("TISS_GREETD_LAST_PROFILE_ID");
     const QString lastLocale = qEnvironmentVariable("TISS_GREETD_LAST_LOCALE");
     const QVariantMap appearance = parseAppearanceJson(qEnvironmentVariable("TISS_GREETD_APPEARANCE_JSON"));
     engine.rootContext()->setContextProperty("tissDefaultUser", defaultUser);
+    engine.rootContext()->setContextProperty("tissLastUser", lastUser);
     engine.rootContext()->setContextProperty("tissLockUser", lockUser);
     engine.rootContext()->setContextProperty("tissShowPasswordToggle", showPasswordToggle);
     engine.rootContext()->setContextProperty("tissSessionCommand", sessionCommand);
@@ -550,6 +574,7 @@ int main(int argc, char *argv[]) {
     engine.rootContext()->setContextProperty("tissLastSessionId", lastSessionId);
     engine.rootContext()->setContextProperty("tissProfiles", profiles);
     engine.rootContext()->setContextProperty("tissLocales", locales);
+    engine.rootContext()->setContextProperty("tissUsers", users);
     engine.rootContext()->setContextProperty("tissPowerActions", powerActions);
     engine.rootContext()->setContextProperty("tissLastProfileId", lastProfileId);
     engine.rootContext()->setContextProperty("tissLastLocale", lastLocale);